@@ -1,4 +1,12 @@
+#![allow(clippy::upper_case_acronyms)]
+mod nsf;
+
+use rusty_nes_core::archive;
 use rusty_nes_core::buffer::Buffer;
+use rusty_nes_core::cpu::tracer::Tracer;
+use rusty_nes_core::rom::{fds, patch};
+use rusty_nes_core::CpuEvent;
+use rusty_nes_core::Region;
 use rusty_nes_core::CPU;
 use rusty_nes_core::SAMPLE_RATE;
 use sdl2::audio::AudioCallback;
@@ -10,11 +18,17 @@ use sdl2::EventPump;
 use std::env::args;
 use std::fs::read;
 use std::fs::write;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
+const USAGE: &str = "Usage: rusty_nes_cli <path to \".nes\", \".unf\", \".fds\", \".zip\", \".gz\" file or \".rustynes_sav\" file> [--patch <path to \".ips\", \".ups\" or \".bps\" file>] [--bios <path to the FDS BIOS (disksys.rom)>] [--region <auto, ntsc, pal or dendy>] [--trace <path to output trace log>]\n       rusty_nes_cli <path to \".nsf\" or \".nsfe\" file> [--track <number>] [--seconds <length>] [--wav <path to output \".wav\" file>]";
+
 struct NES<'a> {
     cpu: &'a mut CPU,
 }
@@ -29,27 +43,143 @@ impl<'a> AudioCallback for NES<'a> {
 
 fn main() {
     let args: Vec<String> = args().collect();
-    if args.len() != 2 {
-        panic!("Usage: rusty_nes_cli <path to \".nes\" file or \".rustynes_sav\" file>");
+    // usage: rusty_nes_cli <rom or save file> [--patch <ips/ups/bps file>] [--bios <FDS BIOS file>]
+    //                      [--region <auto/ntsc/pal/dendy>] [--trace <output file>]
+    //        rusty_nes_cli <nsf file> [--track <number>] [--seconds <length>] [--wav <output file>]
+    let (path, options) = match args.as_slice() {
+        [_, path, options @ ..] if options.len() % 2 == 0 => (path, options),
+        _ => panic!("{}", USAGE),
+    };
+    let mut patch_path = None;
+    let mut bios_path = None;
+    let mut track = None;
+    let mut seconds = None;
+    let mut wav_path = None;
+    // None is auto, the region of the ROM
+    let mut region = None;
+    let mut trace_path = None;
+    for option in options.chunks(2) {
+        match option[0].as_str() {
+            "--patch" => patch_path = Some(&option[1]),
+            "--bios" => bios_path = Some(&option[1]),
+            "--track" => track = Some(option[1].parse().expect("Invalid track number")),
+            "--seconds" => {
+                // negative, infinite and NaN lengths would panic in Duration::from_secs_f32
+                let length = option[1].parse().ok();
+                let length = length.filter(|&length| Duration::try_from_secs_f32(length).is_ok());
+                seconds = Some(length.expect("Invalid number of seconds"));
+            }
+            "--wav" => wav_path = Some(&option[1]),
+            "--trace" => trace_path = Some(&option[1]),
+            "--region" => {
+                region = match option[1].to_lowercase().as_str() {
+                    "auto" => None,
+                    "ntsc" => Some(Region::Ntsc),
+                    "pal" => Some(Region::Pal),
+                    "dendy" => Some(Region::Dendy),
+                    _ => panic!("{}", USAGE),
+                }
+            }
+            _ => panic!("{}", USAGE),
+        }
     }
-    // read file path
-    let path = &args[1];
     println!("args: {:?}", args);
     println!("file_path: {}", path);
 
+    // music player mode, there is no window
+    if path.ends_with(".nsf") || path.ends_with(".nsfe") {
+        nsf::run(path, track, wav_path, seconds);
+    }
+
     // Load ROM or save file
     let mut cpu;
+    // battery backed save RAM is kept next to the rom as <rom>.sav
+    let mut sav_path = None;
     let buffer = &mut Buffer::new_buffer();
     if path.ends_with(".rustynes_sav") {
         cpu = CPU::default();
         let bytes = read(path).expect("Failed to read save file");
         buffer.data = bytes;
         cpu.decode(buffer);
-    } else if path.ends_with(".nes") {
+    } else if [".nes", ".unf", ".unif", ".fds", ".zip", ".gz"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+    {
         let bytes = read(path).expect("Failed to read ROM file");
-        cpu = CPU::new_from_rom_bytes(bytes);
+        // zip and gzip archives are read in memory, the first .nes, .unf or .fds file is used
+        let mut bytes = match archive::extract_rom(bytes) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("Failed to read archive \"{}\": {}", path, err);
+                process::exit(1);
+            }
+        };
+        // the patch is applied in memory, the rom file is left untouched
+        if let Some(patch_path) = patch_path {
+            let patch_bytes = read(patch_path).expect("Failed to read patch file");
+            bytes = match patch::apply(&bytes, &patch_bytes) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    eprintln!("Failed to apply patch \"{}\": {}", patch_path, err);
+                    process::exit(1);
+                }
+            };
+            println!("applied patch: {}", patch_path);
+        }
+        // Famicom Disk System disks boot from the FDS BIOS, which is not included
+        let result = if fds::is_fds(&bytes) {
+            let Some(bios_path) = bios_path else {
+                eprintln!(
+                    "\"{}\" is a Famicom Disk System disk, the FDS BIOS is needed: --bios <path to disksys.rom>",
+                    path
+                );
+                process::exit(1);
+            };
+            let bios = read(bios_path).expect("Failed to read FDS BIOS file");
+            CPU::try_new_fds(&bytes, bios)
+        } else {
+            CPU::try_new_from_rom_bytes(bytes)
+        };
+        cpu = match result {
+            Ok(cpu) => cpu,
+            Err(err) => {
+                eprintln!("Failed to load ROM \"{}\": {}", path, err);
+                process::exit(1);
+            }
+        };
+        if cpu.battery_ram().is_some() {
+            let path = Path::new(path).with_extension("sav");
+            if let Ok(bytes) = read(&path) {
+                println!("loaded save RAM: {}", path.display());
+                cpu.load_battery_ram(&bytes);
+            }
+            sav_path = Some(path);
+        } else if cpu.disk_side_count() > 0 {
+            // the data the game writes to the disk is kept next to it as an IPS patch,
+            // the disk image itself is left untouched
+            let path = Path::new(path).with_extension("fds.ips");
+            if let Ok(bytes) = read(&path) {
+                match cpu.load_disk_diff(&bytes) {
+                    Ok(()) => println!("loaded disk writes: {}", path.display()),
+                    Err(err) => eprintln!("Failed to load \"{}\": {}", path.display(), err),
+                }
+            }
+            sav_path = Some(path);
+        }
     } else {
-        panic!("Invalid file type. Please provide a .nes ROM file or .rustynes_sav");
+        panic!("Invalid file type. Please provide a .nes/.unf/.fds ROM file (or a .zip/.gz archive of one) or .rustynes_sav");
+    }
+    // save files keep their region unless one is given
+    if region.is_some() {
+        cpu.set_region(region);
+    }
+    println!("region: {:?}", cpu.region());
+
+    // every instruction is logged in the nestest.log layout, T turns it off and on
+    if let Some(trace_path) = trace_path {
+        let file = File::create(trace_path).expect("Failed to create trace file");
+        cpu.set_tracer(Some(Tracer::new(BufWriter::new(file))));
+        println!("tracing to: {}", trace_path);
     }
 
     // Save initial state of cpu
@@ -62,7 +192,11 @@ fn main() {
     // Set up video
     let video_subsystem = sdl.video().unwrap();
     let window = video_subsystem
-        .window("nes", (256 * 3) as u32, (240 * 3) as u32)
+        .window(
+            cpu.title().unwrap_or("nes"),
+            (256 * 3) as u32,
+            (240 * 3) as u32,
+        )
         .position_centered()
         .build()
         .expect("could not create window");
@@ -94,9 +228,6 @@ fn main() {
     // Set up event handling
     let mut event_pump = sdl.event_pump().unwrap();
 
-    // For 60 fps emulation
-    let target_fps = 62_f64;
-    let target_duration = Duration::from_secs_f64(1.0 / target_fps);
     let mut frame_start_time;
 
     // Game loop
@@ -105,10 +236,19 @@ fn main() {
         frame_start_time = Instant::now();
 
         // Handle input
-        handle_input(&mut cpu, buffer, &mut event_pump);
+        handle_input(&mut cpu, buffer, &mut event_pump, &sav_path);
+
+        // frame rate of the region (60 fps on NTSC, 50 fps on PAL and Dendy),
+        // run slightly faster so the audio buffer does not run dry,
+        // it is checked every frame because loading a state can change the region
+        let target_fps = cpu.region().frame_rate() + 2.0;
+        let target_duration = Duration::from_secs_f64(1.0 / target_fps);
 
         // Get rendering data
         cpu.step_till_next_frame();
+        if let Some(CpuEvent::CpuHalted { addr }) = cpu.take_event() {
+            eprintln!("CPU halted by a JAM opcode at {:04X}", addr);
+        }
         let frame_buffer = cpu.frame_buffer_ref();
 
         // Update texture
@@ -136,10 +276,15 @@ fn main() {
    button 6: Left
    button 7: Right
 */
-pub fn handle_input(c: &mut CPU, buffer: &mut Buffer, event_pump: &mut EventPump) {
+pub fn handle_input(
+    c: &mut CPU,
+    buffer: &mut Buffer,
+    event_pump: &mut EventPump,
+    sav_path: &Option<PathBuf>,
+) {
     for event in event_pump.poll_iter() {
         match event {
-            Event::Quit { .. } => process::exit(0),
+            Event::Quit { .. } => exit(c, sav_path),
 
             Event::KeyDown {
                 keycode: Some(key), ..
@@ -155,7 +300,7 @@ pub fn handle_input(c: &mut CPU, buffer: &mut Buffer, event_pump: &mut EventPump
                 Keycode::A => c.update_button(6, true),
                 Keycode::D => c.update_button(7, true),
 
-                Keycode::Escape => process::exit(0),
+                Keycode::Escape => exit(c, sav_path),
                 Keycode::N => {
                     c.encode(buffer);
                     write("save.rustynes_sav", &buffer.data).expect("error writing file");
@@ -163,6 +308,20 @@ pub fn handle_input(c: &mut CPU, buffer: &mut Buffer, event_pump: &mut EventPump
                 Keycode::M => {
                     c.decode(buffer);
                 }
+                Keycode::T => {
+                    if let Some(tracer) = c.tracer_mut() {
+                        tracer.set_enabled(!tracer.enabled());
+                        println!("tracing {}", if tracer.enabled() { "on" } else { "off" });
+                    }
+                }
+                // Famicom Disk System: flip the disk to the next side
+                Keycode::F if c.disk_side_count() > 0 => {
+                    let side = c
+                        .disk_side()
+                        .map_or(0, |side| (side + 1) % c.disk_side_count());
+                    c.insert_disk_side(Some(side));
+                    println!("disk side {}", side + 1);
+                }
                 _ => (),
             },
 
@@ -185,3 +344,24 @@ pub fn handle_input(c: &mut CPU, buffer: &mut Buffer, event_pump: &mut EventPump
         }
     }
 }
+
+// writes the battery backed save RAM (or the disk writes of an FDS game)
+// and the rest of the trace log before exiting
+fn exit(c: &mut CPU, sav_path: &Option<PathBuf>) -> ! {
+    if let Some(tracer) = c.tracer_mut() {
+        if let Err(err) = tracer.flush() {
+            eprintln!("Failed to write trace log: {}", err);
+        }
+    }
+    let data = c
+        .battery_ram()
+        .map(|ram| ram.to_vec())
+        .or_else(|| c.disk_diff());
+    if let (Some(path), Some(data)) = (sav_path, data) {
+        match write(path, data) {
+            Ok(()) => println!("saved: {}", path.display()),
+            Err(err) => eprintln!("Failed to write \"{}\": {}", path.display(), err),
+        }
+    }
+    process::exit(0)
+}
@@ -0,0 +1,118 @@
+use rusty_nes_core::nsf::NsfPlayer;
+use rusty_nes_core::SAMPLE_RATE;
+use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioSpecDesired;
+use std::fs::read;
+use std::fs::write;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+// length of a WAV render when neither --seconds nor the NSFe file gives one
+const DEFAULT_SECONDS: f32 = 150.0;
+
+struct NSF {
+    player: NsfPlayer,
+}
+
+impl AudioCallback for NSF {
+    type Channel = f32;
+
+    fn callback(&mut self, buffer: &mut [f32]) {
+        self.player.render(buffer);
+    }
+}
+
+// plays a track of an NSF/NSFe file without a window, or renders it to a WAV file
+// track starts at 1, the starting track of the file is used if it is None
+pub fn run(path: &str, track: Option<u8>, wav_path: Option<&String>, seconds: Option<f32>) -> ! {
+    let bytes = read(path).expect("Failed to read NSF file");
+    let mut player = match NsfPlayer::new(&bytes) {
+        Ok(player) => player,
+        Err(err) => {
+            eprintln!("Failed to load NSF \"{}\": {}", path, err);
+            process::exit(1);
+        }
+    };
+    if let Some(track) = track {
+        if let Err(err) = player.set_track(track.wrapping_sub(1)) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+
+    let nsf = player.nsf();
+    let track = player.track();
+    println!("title: {}", nsf.title);
+    println!("artist: {}", nsf.artist);
+    println!("copyright: {}", nsf.copyright);
+    match nsf.track_title(track) {
+        Some(title) => println!("track {}/{}: {}", track + 1, nsf.track_count, title),
+        None => println!("track {}/{}", track + 1, nsf.track_count),
+    }
+    // the NSFe track length, if there is one
+    let seconds = seconds.or_else(|| {
+        let length = nsf.track_lengths.get(track as usize).copied().flatten();
+        length.map(|ms| ms as f32 / 1000.0)
+    });
+
+    match wav_path {
+        Some(wav_path) => {
+            let seconds = seconds.unwrap_or(DEFAULT_SECONDS);
+            let mut samples = vec![0.0; (seconds * SAMPLE_RATE) as usize];
+            player.render(&mut samples);
+            match write(wav_path, wav(&samples)) {
+                Ok(()) => println!("rendered {} seconds to {}", seconds, wav_path),
+                Err(err) => {
+                    eprintln!("Failed to write \"{}\": {}", wav_path, err);
+                    process::exit(1);
+                }
+            }
+        }
+        None => {
+            let sdl = sdl2::init().unwrap();
+            let audio_subsystem = sdl.audio().unwrap();
+            let desired_audio_spec = AudioSpecDesired {
+                freq: Some(SAMPLE_RATE as i32),
+                channels: Some(1), // mono,
+                samples: Some(1024),
+            };
+            let audio_device = audio_subsystem
+                .open_playback(None, &desired_audio_spec, |_spec| NSF { player })
+                .unwrap();
+            audio_device.resume();
+            // without a length the track plays until the process is stopped
+            match seconds {
+                Some(seconds) => thread::sleep(Duration::from_secs_f32(seconds)),
+                None => loop {
+                    thread::sleep(Duration::from_secs(1));
+                },
+            }
+        }
+    }
+    process::exit(0)
+}
+
+// 16 bit mono PCM WAV file
+fn wav(samples: &[f32]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let sample_rate = SAMPLE_RATE as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // channels
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    bytes.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+    bytes.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
@@ -3,6 +3,9 @@ use super::units::Timer;
 const DMC: [u16; 16] = [
     428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
 ];
+const DMC_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
 
 #[derive(Default)]
 pub struct Dmc {
@@ -22,6 +25,7 @@ pub struct Dmc {
     loop_mode: bool,
     timer: Timer,
     output: u8,
+    pub pal: bool, // PAL rate table
 }
 
 // Step /////
@@ -121,7 +125,8 @@ impl Dmc {
     pub fn write0(&mut self, val: u8) {
         self.irq_enabled = val & 0x80 != 0;
         self.loop_mode = val & 0x40 != 0;
-        self.timer.period = DMC[val as usize & 0b1111];
+        let rates = if self.pal { &DMC_PAL } else { &DMC };
+        self.timer.period = rates[val as usize & 0b1111];
         if !self.irq_enabled {
             self.irq_triggered = false;
         }
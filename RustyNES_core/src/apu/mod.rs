@@ -1,3 +1,4 @@
+use crate::region::Region;
 use dmc::Dmc;
 use noise::Noise;
 use square::Square;
@@ -8,11 +9,15 @@ mod noise;
 mod square;
 mod triangle;
 pub mod units;
-const CPU_FREQ: f32 = 1789773.0;
 // pub const SAMPLE_RATE: f32 = 48000.0;
 pub const SAMPLE_RATE: f32 = 44100.0;
 pub const BUFFER_SIZE: usize = 0x2000;
 
+// frame counter steps in APU cycles:
+// quarter frame, half frame, quarter frame, end of the 4-step and of the 5-step sequence
+const FRAME_STEPS: [u32; 5] = [3729, 7457, 11186, 14915, 18641];
+const FRAME_STEPS_PAL: [u32; 5] = [4157, 8314, 12470, 16626, 20783];
+
 pub struct APU {
     // channels
     square1: Square,
@@ -20,13 +25,16 @@ pub struct APU {
     triangle: Triangle,
     noise: Noise,
     pub dmc: Dmc,
+    expansion: f32, // output of the cartridge's expansion audio
 
     // timing
+    region: Region,
     cycle: u32,
     cycles_per_sample: f32, // cycles required to generate one sample
     sample_count: u32,      // total samples generated so far
 
     // frame counter
+    frame_steps: [u32; 5],
     frame_counter: u32,
     four_step_mode: bool, // frame step mode: 4-step or 5-step
     irq_triggered: bool,
@@ -38,6 +46,12 @@ pub struct APU {
     buffer: Box<[f32; BUFFER_SIZE]>, // circular buffer
 }
 
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // Step /////
 impl APU {
     pub fn new() -> Self {
@@ -47,11 +61,14 @@ impl APU {
             triangle: Triangle::new(),
             noise: Noise::new(),
             dmc: Dmc::new(),
+            expansion: 0.0,
 
+            region: Region::Ntsc,
             cycle: 0,
-            cycles_per_sample: CPU_FREQ / SAMPLE_RATE,
+            cycles_per_sample: Region::Ntsc.cpu_freq() / SAMPLE_RATE,
             sample_count: 0,
 
+            frame_steps: FRAME_STEPS,
             frame_counter: 0,
             four_step_mode: true,
             irq_triggered: false,
@@ -63,7 +80,24 @@ impl APU {
         }
     }
 
-    pub fn step(&mut self, dmc_data: u8) {
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // Dendy uses the NTSC rates, only the CPU clock differs
+    pub fn set_region(&mut self, region: Region) {
+        let pal = region == Region::Pal;
+        self.region = region;
+        self.cycles_per_sample = region.cpu_freq() / SAMPLE_RATE;
+        self.frame_steps = if pal { FRAME_STEPS_PAL } else { FRAME_STEPS };
+        self.noise.pal = pal;
+        self.dmc.pal = pal;
+        // keep the sample count in step with the new rate
+        self.sample_count = (self.cycle as f32 / self.cycles_per_sample) as u32;
+    }
+
+    pub fn step(&mut self, dmc_data: u8, expansion: f32) {
+        self.expansion = expansion;
         self.cycle += 1;
         self.triangle.step();
         if self.cycle % 2 == 1 {
@@ -87,18 +121,19 @@ impl APU {
 
     fn step_frame_counter(&mut self) {
         self.frame_counter += 1;
+        let [quarter1, half1, quarter2, end4, end5] = self.frame_steps;
         match self.frame_counter {
-            3729 => self.step_quarter_frame(),
-            7457 => self.step_half_frame(),
-            11186 => self.step_quarter_frame(),
-            14915 if self.four_step_mode => {
+            step if step == quarter1 => self.step_quarter_frame(),
+            step if step == half1 => self.step_half_frame(),
+            step if step == quarter2 => self.step_quarter_frame(),
+            step if step == end4 && self.four_step_mode => {
                 self.step_half_frame();
                 self.frame_counter = 0;
                 if !self.irq_disabled {
                     self.irq_triggered = true;
                 }
             }
-            18641 if !self.four_step_mode => {
+            step if step == end5 && !self.four_step_mode => {
                 self.step_half_frame();
                 self.frame_counter = 0;
             }
@@ -127,7 +162,15 @@ impl APU {
         if addr == 0x4015 {
             return self.read_status();
         }
-        return 0;
+        0
+    }
+
+    // the value read returns, without acknowledging the frame IRQ
+    pub fn peek(&self, addr: u16) -> u8 {
+        if addr == 0x4015 {
+            return self.peek_status();
+        }
+        0
     }
 
     pub fn write(&mut self, addr: u16, val: u8) {
@@ -172,7 +215,14 @@ impl APU {
         }
     }
 
+    // reading the status acknowledges the frame IRQ
     fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.irq_triggered = false;
+        status
+    }
+
+    fn peek_status(&self) -> u8 {
         // read channel status and irq status into single byte
         let mut status = 0;
 
@@ -211,9 +261,7 @@ impl APU {
             status |= 0x80;
         }
 
-        self.irq_triggered = false;
-
-        return status;
+        status
     }
 
     fn write_control(&mut self, val: u8) {
@@ -250,7 +298,7 @@ impl APU {
         let d = self.dmc.output();
         let out1 = 0.00752 * (s1 + s2);
         let out2 = (0.00851 * t) + (0.00494 * n) + (0.00335 * d);
-        let output = out1 + out2;
+        let output = out1 + out2 + self.expansion;
         output * 0.5
     }
 
@@ -265,6 +313,11 @@ impl APU {
         self.buffer_end = (self.buffer_end + 1) % BUFFER_SIZE;
     }
 
+    // samples written to the buffer and not read yet
+    pub fn buffered_samples(&self) -> usize {
+        (self.buffer_end + BUFFER_SIZE - self.buffer_start) % BUFFER_SIZE
+    }
+
     pub fn load_samples(&mut self, buffer: &mut [f32]) {
         let available_samples = {
             let start = self.buffer_start;
@@ -276,13 +329,11 @@ impl APU {
             }
         };
         let mut last_sample = 0.0;
-        for i in 0..buffer.len() {
+        for (i, sample) in buffer.iter_mut().enumerate() {
             if i < available_samples {
                 last_sample = self.read_buffer();
-                buffer[i] = last_sample
-            } else {
-                buffer[i] = last_sample
             }
+            *sample = last_sample
         }
     }
 }
@@ -3,6 +3,9 @@ use super::units::{Envelope, LengthCounter, Timer};
 const NOISE: [u16; 16] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
 ];
+const NOISE_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
 
 #[derive(Default)]
 pub struct Noise {
@@ -12,14 +15,16 @@ pub struct Noise {
     timer: Timer,
     envelope: Envelope,
     length_counter: LengthCounter,
+    pub pal: bool, // PAL period table
 }
 
 // Step /////
 impl Noise {
     pub fn new() -> Self {
-        let mut noise = Self::default();
-        noise.shift_register = 1;
-        noise
+        Self {
+            shift_register: 1,
+            ..Default::default()
+        }
     }
 
     pub fn step(&mut self) {
@@ -79,7 +84,8 @@ impl Noise {
 
     pub fn write1(&mut self, val: u8) {
         self.mode = val & 0x80 != 0;
-        self.timer.period = NOISE[(val & 0b1111) as usize];
+        let periods = if self.pal { &NOISE_PAL } else { &NOISE };
+        self.timer.period = periods[(val & 0b1111) as usize];
     }
 
     pub fn write2(&mut self, val: u8) {
@@ -33,9 +33,10 @@ pub struct Square {
 // Step /////
 impl Square {
     pub fn new(id: u8) -> Self {
-        let mut square = Self::default();
-        square.id = id;
-        square
+        Self {
+            id,
+            ..Default::default()
+        }
     }
 
     pub fn step(&mut self) {
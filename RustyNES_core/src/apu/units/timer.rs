@@ -8,10 +8,10 @@ impl Timer {
     pub fn step(&mut self) -> bool {
         if self.counter == 0 {
             self.counter = self.period;
-            return true;
+            true
         } else {
             self.counter -= 1;
-            return false;
+            false
         }
     }
 }
@@ -0,0 +1,126 @@
+// gzip reader (RFC 1952)
+use super::{inflate::inflate_stream, ArchiveError, MAX_FILE_SIZE};
+use crate::hash::crc32;
+
+// header flags
+const FHCRC: u8 = 0b0000_0010;
+const FEXTRA: u8 = 0b0000_0100;
+const FNAME: u8 = 0b0000_1000;
+const FCOMMENT: u8 = 0b0001_0000;
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1F, 0x8B])
+}
+
+/*
+gzip member:
+0-1:  magic 0x1F 0x8B
+2:    compression method (8 = deflate)
+3:    flags
+4-9:  modification time, extra flags, OS
+optional: extra field, file name, comment, header CRC16
+deflate stream
+CRC32 and size (mod 2^32) of the uncompressed data
+*/
+// decompress the first member of a gzip file
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, ArchiveError> {
+    if !is_gzip(data) || data.len() < 18 {
+        return Err(ArchiveError::Corrupt("gzip: invalid header".to_string()));
+    }
+    if data[2] != 8 {
+        return Err(ArchiveError::UnsupportedCompression(data[2] as u16));
+    }
+    let flags = data[3];
+    let mut index = 10;
+    let truncated = || ArchiveError::Corrupt("gzip: truncated header".to_string());
+
+    if flags & FEXTRA != 0 {
+        let len = data.get(index..index + 2).ok_or_else(truncated)?;
+        index += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    // file name and comment are zero terminated
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let len = data
+                .get(index..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or_else(truncated)?;
+            index += len + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        index += 2;
+    }
+    let compressed = data.get(index..).ok_or_else(truncated)?;
+
+    let (output, len) = inflate_stream(compressed, MAX_FILE_SIZE)?;
+
+    let footer = compressed
+        .get(len..len + 8)
+        .ok_or_else(|| ArchiveError::Corrupt("gzip: missing footer".to_string()))?;
+    let expected = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    let size = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+    let found = crc32(&output);
+    if expected != found {
+        return Err(ArchiveError::Checksum { expected, found });
+    }
+    if size != output.len() as u32 {
+        return Err(ArchiveError::Corrupt(
+            "gzip: size does not match the uncompressed data".to_string(),
+        ));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // made by Python's gzip module, with the file name "rom.nes" (FNAME)
+    const GZIP: [u8; 45] = [
+        0x1F, 0x8B, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xFF, 0x72, 0x6F, 0x6D, 0x2E, 0x6E,
+        0x65, 0x73, 0x00, 0x4B, 0xAF, 0xCA, 0x2C, 0x50, 0x48, 0xCB, 0xAC, 0x28, 0x29, 0x2D, 0x4A,
+        0x55, 0x48, 0x47, 0xE2, 0x70, 0x01, 0x00, 0x48, 0x58, 0x25, 0xA1, 0x1A, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn decompress_member() {
+        assert!(is_gzip(&GZIP));
+        assert_eq!(GZIP[3], FNAME);
+        assert_eq!(decompress(&GZIP).unwrap(), b"gzip fixture gzip fixture\n");
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut data = GZIP;
+        data[GZIP.len() - 8] ^= 1;
+        assert!(matches!(
+            decompress(&data),
+            Err(ArchiveError::Checksum { .. })
+        ));
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let mut data = GZIP;
+        data[GZIP.len() - 4] ^= 1;
+        assert!(matches!(decompress(&data), Err(ArchiveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn unsupported_compression() {
+        let mut data = GZIP;
+        data[2] = 0;
+        assert_eq!(
+            decompress(&data),
+            Err(ArchiveError::UnsupportedCompression(0))
+        );
+    }
+
+    #[test]
+    fn truncated_file() {
+        for len in 0..GZIP.len() {
+            assert!(decompress(&GZIP[..len]).is_err());
+        }
+    }
+}
@@ -0,0 +1,417 @@
+// DEFLATE decompressor (RFC 1951), the compression used by zip and gzip
+use super::ArchiveError;
+
+// base lengths and extra bits of length codes 257-285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// base distances and extra bits of distance codes 0-29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// order in which the code length code lengths are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const MAX_BITS: usize = 15;
+
+// reads bits least significant first
+struct BitReader<'a> {
+    data: &'a [u8],
+    index: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            index: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, ArchiveError> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.index)
+                .ok_or_else(|| corrupt("unexpected end of compressed data"))?;
+            self.index += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    // stored blocks start at a byte boundary
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ArchiveError> {
+        let bytes = self
+            .data
+            .get(self.index..self.index + len)
+            .ok_or_else(|| corrupt("unexpected end of stored block"))?;
+        self.index += len;
+        Ok(bytes)
+    }
+}
+
+// canonical huffman code stored as the number of codes of each length
+// and the symbols ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, ArchiveError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // reject codes with more codes of a length than there is room for
+        let mut left: i32 = 1;
+        for &count in counts.iter().skip(1) {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(corrupt("over-subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    // huffman codes are stored most significant bit first, read one bit at a time
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ArchiveError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(corrupt("invalid huffman code"))
+    }
+}
+
+fn corrupt(reason: &str) -> ArchiveError {
+    ArchiveError::Corrupt(format!("deflate: {}", reason))
+}
+
+// decompress a raw DEFLATE stream of at most max_size bytes
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>, ArchiveError> {
+    inflate_stream(data, max_size).map(|(output, _)| output)
+}
+
+// decompress a DEFLATE stream followed by other data,
+// also returns the number of bytes the stream takes up
+pub fn inflate_stream(data: &[u8], max_size: usize) -> Result<(Vec<u8>, usize), ArchiveError> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::with_capacity(data.len().saturating_mul(4).min(max_size));
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output, max_size)?,
+            1 => {
+                let (lengths, distances) = fixed_codes()?;
+                compressed_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut reader)?;
+                compressed_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            _ => return Err(corrupt("invalid block type")),
+        }
+        if last {
+            return Ok((output, reader.index));
+        }
+    }
+}
+
+// block type 0: LEN, NLEN (one's complement of LEN), LEN bytes
+fn stored_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<(), ArchiveError> {
+    reader.align_to_byte();
+    let header = reader.bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(corrupt("stored block length does not match its complement"));
+    }
+    check_size(output, len as usize, max_size)?;
+    output.extend_from_slice(reader.bytes(len as usize)?);
+    Ok(())
+}
+
+// block type 1: codes defined by the spec
+fn fixed_codes() -> Result<(Huffman, Huffman), ArchiveError> {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+// block type 2: the code lengths are themselves huffman coded
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ArchiveError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(corrupt("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // literal/length and distance code lengths are read as one sequence
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            // repeat the previous length 3-6 times
+            16 => {
+                let previous = *lengths[..index]
+                    .last()
+                    .ok_or_else(|| corrupt("repeat without a previous length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            // repeat zero 3-10 times
+            17 => (0, 3 + reader.bits(3)? as usize),
+            // repeat zero 11-138 times
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err(corrupt("code lengths overflow"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(corrupt("missing end of block code"));
+    }
+
+    let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+    Ok((
+        Huffman::new(literal_lengths)?,
+        Huffman::new(distance_lengths)?,
+    ))
+}
+
+// literals and (length, distance) back references until the end of block symbol
+fn compressed_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ArchiveError> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                check_size(output, 1, max_size)?;
+                output.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(corrupt("invalid length code"));
+                }
+                let len =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DIST_BASE.len() {
+                    return Err(corrupt("invalid distance code"));
+                }
+                let distance =
+                    DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(corrupt("distance points before the start of the data"));
+                }
+
+                check_size(output, len, max_size)?;
+                // the copy can overlap the bytes it writes, so copy byte by byte
+                let start = output.len() - distance;
+                for i in 0..len {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// fails when len more bytes would make the output larger than max_size
+fn check_size(output: &[u8], len: usize, max_size: usize) -> Result<(), ArchiveError> {
+    if output.len() + len > max_size {
+        return Err(corrupt("data is larger than expected"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::MAX_FILE_SIZE;
+
+    // raw DEFLATE streams made with zlib
+    // "stored block" at level 0
+    const STORED: [u8; 17] = [
+        0x01, 0x0C, 0x00, 0xF3, 0xFF, 0x73, 0x74, 0x6F, 0x72, 0x65, 0x64, 0x20, 0x62, 0x6C, 0x6F,
+        0x63, 0x6B,
+    ];
+
+    // "fixed huffman fixed huffman" with the fixed codes (Z_FIXED)
+    const FIXED: [u8; 19] = [
+        0x4B, 0xCB, 0xAC, 0x48, 0x4D, 0x51, 0xC8, 0x28, 0x4D, 0x4B, 0xCB, 0x4D, 0xCC, 0x53, 0x48,
+        0x43, 0xE6, 0x01, 0x00,
+    ];
+
+    // dynamic_text() at level 9
+    const DYNAMIC: [u8; 91] = [
+        0x1D, 0x8C, 0xC9, 0x0D, 0x04, 0x41, 0x08, 0xC4, 0x12, 0xF2, 0xA3, 0xB9, 0x21, 0xFF, 0xC4,
+        0xB6, 0x76, 0x24, 0x84, 0x38, 0xEC, 0x7A, 0x18, 0xC9, 0x61, 0x8D, 0x17, 0xD1, 0xE4, 0xD1,
+        0xC9, 0x1A, 0x81, 0x27, 0x39, 0x8C, 0xE3, 0x84, 0xD1, 0xCE, 0x89, 0x08, 0xE6, 0x61, 0x4E,
+        0x05, 0x27, 0x5A, 0xAE, 0x02, 0x34, 0x27, 0xF5, 0x58, 0xBA, 0xF0, 0x61, 0x97, 0x4A, 0xDC,
+        0x59, 0xA5, 0x29, 0xAA, 0x30, 0x63, 0x9B, 0x96, 0xB1, 0x84, 0x63, 0x22, 0xB9, 0x60, 0x8B,
+        0x39, 0x46, 0x3D, 0xBE, 0xFA, 0x56, 0x1D, 0xF5, 0xEA, 0x3F, 0x24, 0x54, 0x82, 0x34, 0xC9,
+        0x3F,
+    ];
+
+    fn dynamic_text() -> Vec<u8> {
+        (0..60)
+            .map(|i| format!("{},", i * i % 97))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    // BTYPE, bits 1-2 of the first block header
+    fn block_type(data: &[u8]) -> u8 {
+        (data[0] >> 1) & 0b11
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(block_type(&STORED), 0);
+        assert_eq!(inflate(&STORED, MAX_FILE_SIZE).unwrap(), b"stored block");
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(block_type(&FIXED), 1);
+        assert_eq!(
+            inflate(&FIXED, MAX_FILE_SIZE).unwrap(),
+            b"fixed huffman fixed huffman"
+        );
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(block_type(&DYNAMIC), 2);
+        assert_eq!(inflate(&DYNAMIC, MAX_FILE_SIZE).unwrap(), dynamic_text());
+    }
+
+    #[test]
+    fn stream_length_excludes_trailing_data() {
+        let data = [&DYNAMIC[..], b"trailer"].concat();
+        let (output, len) = inflate_stream(&data, MAX_FILE_SIZE).unwrap();
+        assert_eq!(output, dynamic_text());
+        assert_eq!(len, DYNAMIC.len());
+    }
+
+    #[test]
+    fn truncated_stream() {
+        for data in [&STORED[..], &FIXED, &DYNAMIC] {
+            for len in 0..data.len() {
+                assert!(inflate(&data[..len], MAX_FILE_SIZE).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn stored_length_complement() {
+        let mut data = STORED;
+        data[3] ^= 1;
+        assert!(matches!(
+            inflate(&data, MAX_FILE_SIZE),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn reserved_block_type() {
+        assert!(matches!(
+            inflate(&[0b111], MAX_FILE_SIZE),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn output_limit() {
+        let len = dynamic_text().len();
+        assert_eq!(inflate(&DYNAMIC, len).unwrap(), dynamic_text());
+        for data in [&STORED[..], &FIXED, &DYNAMIC] {
+            let len = inflate(data, MAX_FILE_SIZE).unwrap().len();
+            assert!(matches!(
+                inflate(data, len - 1),
+                Err(ArchiveError::Corrupt(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// reads roms from zip and gzip archives without external libraries
+pub mod gzip;
+pub mod inflate;
+pub mod zip;
+
+use std::fmt;
+
+// largest file extracted from an archive, bigger ones are treated as corrupt
+// so a small archive can not decompress into all of the memory
+pub const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+// file extensions of the rom files picked from zip archives
+const ROM_EXTENSIONS: [&str; 4] = [".nes", ".unf", ".unif", ".fds"];
+
+// errors reported while reading an archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    // archive ends early or its structures are invalid
+    Corrupt(String),
+    // compression method other than stored or deflate
+    UnsupportedCompression(u16),
+    // decompressed data does not match the CRC32 stored in the archive
+    Checksum { expected: u32, found: u32 },
+    // zip archive does not contain a rom file
+    NoRom,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Corrupt(reason) => write!(f, "corrupt archive: {}", reason),
+            ArchiveError::UnsupportedCompression(method) => {
+                write!(f, "unsupported compression method: {}", method)
+            }
+            ArchiveError::Checksum { expected, found } => write!(
+                f,
+                "archive checksum mismatch: expected CRC32 {:08X}, found {:08X}",
+                expected, found
+            ),
+            ArchiveError::NoRom => write!(f, "archive does not contain a .nes, .unf or .fds file"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+pub fn is_archive(data: &[u8]) -> bool {
+    zip::is_zip(data) || gzip::is_gzip(data)
+}
+
+// rom file from a zip or gzip archive,
+// the first rom file (.nes, .unf or .fds) is used if a zip archive has more than one
+// data that is not an archive is returned as it is
+pub fn extract_rom(data: Vec<u8>) -> Result<Vec<u8>, ArchiveError> {
+    if gzip::is_gzip(&data) {
+        gzip::decompress(&data)
+    } else if zip::is_zip(&data) {
+        let entry = zip::entries(&data)?
+            .into_iter()
+            .find(|entry| !entry.is_dir() && is_rom_name(&entry.name))
+            .ok_or(ArchiveError::NoRom)?;
+        zip::extract(&data, &entry)
+    } else {
+        Ok(data)
+    }
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ROM_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
@@ -0,0 +1,265 @@
+// zip reader, supports stored and deflate compressed entries (no zip64, no encryption)
+use super::{inflate::inflate, ArchiveError, MAX_FILE_SIZE};
+use crate::hash::crc32;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034B50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014B50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(&LOCAL_HEADER_SIGNATURE.to_le_bytes())
+        || data.starts_with(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes())
+}
+
+// file in the central directory
+#[derive(Clone, Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    pub compression: u16,
+    pub crc32: u32,
+    pub compressed_size: usize,
+    pub size: usize,
+    // offset of the local header
+    local_header: usize,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+fn corrupt(reason: &str) -> ArchiveError {
+    ArchiveError::Corrupt(format!("zip: {}", reason))
+}
+
+// offsets and sizes come from the file, the end is checked so it can not overflow
+fn bytes_at(data: &[u8], index: usize, len: usize) -> Result<&[u8], ArchiveError> {
+    index
+        .checked_add(len)
+        .and_then(|end| data.get(index..end))
+        .ok_or_else(|| corrupt("unexpected end of file"))
+}
+
+fn u16_at(data: &[u8], index: usize) -> Result<u16, ArchiveError> {
+    bytes_at(data, index, 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], index: usize) -> Result<u32, ArchiveError> {
+    bytes_at(data, index, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/*
+end of central directory record (at the end of the file, before an optional comment):
+0:  signature
+10: number of entries
+16: offset of the central directory
+20: comment length
+
+central directory header:
+0:  signature
+10: compression method
+16: CRC32
+20: compressed size
+24: uncompressed size
+28: file name length
+30: extra field length
+32: comment length
+42: offset of the local header
+46: file name, extra field, comment
+
+local header:
+0:  signature
+26: file name length
+28: extra field length
+30: file name, extra field, file data
+*/
+// list the files in the order of the central directory
+pub fn entries(data: &[u8]) -> Result<Vec<ZipEntry>, ArchiveError> {
+    // the record is followed by a comment of up to 65535 bytes, search backwards for it
+    let min_start = data.len().saturating_sub(22 + 0xFFFF);
+    let end = (min_start..=data.len().saturating_sub(22))
+        .rev()
+        .find(|&i| u32_at(data, i).ok() == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or_else(|| corrupt("end of central directory not found"))?;
+
+    let count = u16_at(data, end + 10)? as usize;
+    let mut index = u32_at(data, end + 16)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = bytes_at(data, index, 46)?;
+        if u32_at(header, 0)? != CENTRAL_HEADER_SIGNATURE {
+            return Err(corrupt("invalid central directory header"));
+        }
+        let name_len = u16_at(header, 28)? as usize;
+        let extra_len = u16_at(header, 30)? as usize;
+        let comment_len = u16_at(header, 32)? as usize;
+        let name = bytes_at(&data[index..], 46, name_len)?;
+        let entry = ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            compression: u16_at(header, 10)?,
+            crc32: u32_at(header, 16)?,
+            compressed_size: u32_at(header, 20)? as usize,
+            size: u32_at(header, 24)? as usize,
+            local_header: u32_at(header, 42)? as usize,
+        };
+        if entry.compressed_size == 0xFFFF_FFFF || entry.local_header == 0xFFFF_FFFF {
+            return Err(corrupt("zip64 archives are not supported"));
+        }
+        entries.push(entry);
+        index = [46, name_len, extra_len, comment_len]
+            .into_iter()
+            .try_fold(index, usize::checked_add)
+            .ok_or_else(|| corrupt("unexpected end of file"))?;
+    }
+    Ok(entries)
+}
+
+// decompress a file and check its CRC32
+pub fn extract(data: &[u8], entry: &ZipEntry) -> Result<Vec<u8>, ArchiveError> {
+    if entry.size > MAX_FILE_SIZE {
+        return Err(corrupt("file is too large"));
+    }
+    let header = bytes_at(data, entry.local_header, 30)?;
+    if u32_at(header, 0)? != LOCAL_HEADER_SIGNATURE {
+        return Err(corrupt("invalid local header"));
+    }
+    // the local header can have a different extra field than the central directory
+    let name_len = u16_at(header, 26)? as usize;
+    let extra_len = u16_at(header, 28)? as usize;
+    let start = [30, name_len, extra_len]
+        .into_iter()
+        .try_fold(entry.local_header, usize::checked_add)
+        .ok_or_else(|| corrupt("unexpected end of file"))?;
+    let compressed = bytes_at(data, start, entry.compressed_size)?;
+
+    let output = match entry.compression {
+        STORED => compressed.to_vec(),
+        DEFLATED => inflate(compressed, entry.size)?,
+        method => return Err(ArchiveError::UnsupportedCompression(method)),
+    };
+
+    if output.len() != entry.size {
+        return Err(corrupt("size does not match the uncompressed data"));
+    }
+    let found = crc32(&output);
+    if found != entry.crc32 {
+        return Err(ArchiveError::Checksum {
+            expected: entry.crc32,
+            found,
+        });
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::extract_rom;
+
+    // made by Python's zipfile module: readme.txt (stored) and game.nes (deflated)
+    const ZIP: [u8; 239] = [
+        0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0xF1,
+        0x2A, 0x9B, 0xE6, 0x09, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00,
+        0x72, 0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x6E, 0x6F, 0x74, 0x20, 0x61,
+        0x20, 0x72, 0x6F, 0x6D, 0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00,
+        0x00, 0x21, 0x50, 0x27, 0xEF, 0x87, 0x0E, 0x14, 0x00, 0x00, 0x00, 0x1C, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D, 0x65, 0x2E, 0x6E, 0x65, 0x73, 0xF3, 0x73, 0x0D,
+        0x96, 0x52, 0xA8, 0xCA, 0x2C, 0x50, 0x48, 0xCB, 0xAC, 0x28, 0x29, 0x2D, 0x4A, 0x45, 0x66,
+        0x03, 0x00, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x21, 0x50, 0xF1, 0x2A, 0x9B, 0xE6, 0x09, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00,
+        0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x72, 0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x50, 0x4B,
+        0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x50, 0x27,
+        0xEF, 0x87, 0x0E, 0x14, 0x00, 0x00, 0x00, 0x1C, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x31, 0x00, 0x00, 0x00, 0x67,
+        0x61, 0x6D, 0x65, 0x2E, 0x6E, 0x65, 0x73, 0x50, 0x4B, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00,
+        0x02, 0x00, 0x02, 0x00, 0x6E, 0x00, 0x00, 0x00, 0x6B, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    const README: &[u8] = b"not a rom";
+    const GAME: &[u8] = b"NES\x1a zip fixture zip fixture";
+
+    #[test]
+    fn list_entries() {
+        assert!(is_zip(&ZIP));
+        let entries = entries(&ZIP).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["readme.txt", "game.nes"]);
+        assert_eq!(entries[0].compression, STORED);
+        assert_eq!(entries[1].compression, DEFLATED);
+        assert_eq!(entries[1].size, GAME.len());
+    }
+
+    #[test]
+    fn extract_entries() {
+        let entries = entries(&ZIP).unwrap();
+        assert_eq!(extract(&ZIP, &entries[0]).unwrap(), README);
+        assert_eq!(extract(&ZIP, &entries[1]).unwrap(), GAME);
+    }
+
+    #[test]
+    fn extract_rom_skips_other_files() {
+        assert_eq!(extract_rom(ZIP.to_vec()).unwrap(), GAME);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut entry = entries(&ZIP).unwrap().remove(1);
+        entry.crc32 ^= 1;
+        assert!(matches!(
+            extract(&ZIP, &entry),
+            Err(ArchiveError::Checksum { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_file() {
+        for len in 0..ZIP.len() {
+            let data = &ZIP[..len];
+            if let Ok(entries) = entries(data) {
+                for entry in entries {
+                    assert!(extract(data, &entry).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn size_limit() {
+        let entries = entries(&ZIP).unwrap();
+        for mut entry in entries.clone() {
+            entry.size = MAX_FILE_SIZE + 1;
+            assert!(matches!(
+                extract(&ZIP, &entry),
+                Err(ArchiveError::Corrupt(_))
+            ));
+        }
+        // a deflated file can not grow past its declared size
+        let mut entry = entries[1].clone();
+        entry.size -= 1;
+        assert!(matches!(
+            extract(&ZIP, &entry),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn offsets_out_of_range() {
+        let entry = entries(&ZIP).unwrap().remove(1);
+        for (local_header, compressed_size) in [(usize::MAX - 10, 0), (0, usize::MAX - 20)] {
+            let entry = ZipEntry {
+                local_header,
+                compressed_size,
+                ..entry.clone()
+            };
+            assert!(matches!(
+                extract(&ZIP, &entry),
+                Err(ArchiveError::Corrupt(_))
+            ));
+        }
+    }
+}
@@ -10,6 +10,13 @@ pub struct BUS {
     pub ppu: PPU,
     pub controller: Controller,
     pub apu: APU,
+
+    // last value on the data bus, read back from addresses nothing drives ("open bus")
+    pub open_bus: u8,
+    // writes to the ROM of discrete boards are ANDed with the ROM value (bus conflicts)
+    pub bus_conflicts: bool,
+    // page written to 0x4014, the CPU runs the OAM DMA after the write
+    pub oam_dma_page: Option<u8>,
 }
 
 impl Default for BUS {
@@ -19,6 +26,9 @@ impl Default for BUS {
             ppu: PPU::default(),
             controller: Controller::default(),
             apu: APU::new(),
+            open_bus: 0,
+            bus_conflicts: true,
+            oam_dma_page: None,
         }
     }
 }
@@ -30,57 +40,110 @@ impl BUS {
             ppu,
             controller,
             apu: APU::new(),
+            open_bus: 0,
+            bus_conflicts: true,
+            oam_dma_page: None,
         }
     }
 
     pub fn read(&mut self, addr: u16) -> u8 {
-        match addr {
+        let val = match addr {
             // 2KB of RAM is mirrored 4 times over the address space 0x0000-0x1FFF
             0x0000..=0x1FFF => self.ram[(addr & 0x7FF) as usize],
             // PPU registers are mirrored every 8 bytes from 0x2008 to 0x3FFF
             // addr & 7 masks the address to 0-7
             0x2000..=0x3FFF => self.ppu.read_register(addr & 7),
-            0x4016 => self.controller.read(),
-            0x4000..=0x4017 => self.apu.read(addr),
-            0x4018..=0x401F => 0, // unused
-            0x4020..=0xFFFF => self.ppu.cartridge.read(addr),
+            // the controller only drives the low bits, usually read as 0x40/0x41
+            0x4016 => (self.open_bus & 0xE0) | self.controller.read(),
+            // no second controller
+            0x4017 => self.open_bus & 0xE0,
+            // bit 5 of the APU status is not driven
+            0x4015 => (self.apu.read(addr) & !0x20) | (self.open_bus & 0x20),
+            // write only APU registers and the unused 0x4018-0x401F
+            0x4000..=0x401F => self.open_bus,
+            0x4020..=0xFFFF if self.ppu.cartridge.drives_cpu_bus(addr) => {
+                self.ppu.cartridge.read(addr)
+            }
+            0x4020..=0xFFFF => self.open_bus,
+        };
+        // the APU status is read inside the 2A03 and never reaches the external data bus
+        if addr != 0x4015 {
+            self.open_bus = val;
         }
+        val
+    }
+
+    // the value read returns, without side effects on the registers, the cartridge or the open bus
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[(addr & 0x7FF) as usize],
+            0x2000..=0x3FFF => self.ppu.peek_register(addr & 7),
+            0x4016 => (self.open_bus & 0xE0) | self.controller.peek(),
+            0x4017 => self.open_bus & 0xE0,
+            0x4015 => (self.apu.peek(addr) & !0x20) | (self.open_bus & 0x20),
+            0x4000..=0x401F => self.open_bus,
+            0x4020..=0xFFFF if self.ppu.cartridge.drives_cpu_bus(addr) => {
+                self.ppu.cartridge.peek(addr)
+            }
+            0x4020..=0xFFFF => self.open_bus,
+        }
+    }
+
+    // PPU address space (0x0000-0x3FFF)
+    pub fn peek_ppu(&self, addr: u16) -> u8 {
+        self.ppu.peek_ppu(addr)
+    }
+
+    // writes the 2KB of RAM (0x0000-0x1FFF), other addresses are ignored
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        if let 0x0000..=0x1FFF = addr {
+            self.ram[(addr & 0x7FF) as usize] = val;
+        }
+    }
+
+    // VRAM, palette and CHR RAM, OAM is written with ppu.poke_oam
+    pub fn poke_ppu(&mut self, addr: u16, val: u8) {
+        self.ppu.poke_ppu(addr, val)
     }
 
     pub fn write(&mut self, addr: u16, val: u8) {
+        self.open_bus = val;
         match addr {
             // 2KB of RAM is mirrored 4 times over the address space 0x0000-0x1FFF
             0x0000..=0x1FFF => self.ram[(addr & 0x7FF) as usize] = val,
             // PPU registers are mirrored every 8 bytes from 0x2008 to 0x3FFF
             // addr & 7 masks the address to 0-7
-            0x2000..=0x3FFF => self.ppu.write_register(addr & 7, val),
-            0x4014 => self.dma(val),
+            0x2000..=0x3FFF => {
+                self.ppu.write_register(addr & 7, val);
+                self.ppu.cartridge.ppu_register_written(addr & 7, val);
+            }
+            0x4014 => self.oam_dma_page = Some(val),
             0x4016 => self.controller.write(val),
             0x4000..=0x4017 => self.apu.write(addr, val),
             0x4018..=0x401F => (), // unused
+            // the ROM drives the bus at the same time as the CPU, 0 bits win
+            0x8000..=0xFFFF if self.bus_conflicts && self.ppu.cartridge.has_bus_conflicts() => {
+                let val = val & self.ppu.cartridge.peek(addr);
+                self.ppu.cartridge.write(addr, val);
+            }
             0x4020..=0xFFFF => self.ppu.cartridge.write(addr, val),
         }
     }
-
-    // DMA(Direct Memory Access) is used to transfer 256 bytes of data from CPU memory to OAM memory.
-    // OAM memory is used to store the sprite attributes.
-    // val is hi byte of addr to read 256 bytes from.
-    fn dma(&mut self, val: u8) {
-        let hi = (val as u16) << 8;
-        for lo in 0..256 {
-            let data = self.read(hi | lo);
-            self.ppu.write_oam_data(data);
-        }
-        self.ppu.dma_triggered = true;
-    }
 }
 
 impl BUS {
     pub fn encode(&self, buffer: &mut buffer::Buffer) {
         buffer.write_u8_arr(&self.ram);
+        buffer.write_u8(self.open_bus);
+        buffer.write_bool(self.oam_dma_page.is_some());
+        buffer.write_u8(self.oam_dma_page.unwrap_or(0));
     }
 
     pub fn decode(&mut self, buffer: &mut buffer::Buffer) {
         buffer.read_u8_arr(&mut self.ram);
+        self.open_bus = buffer.read_u8();
+        let oam_dma = buffer.read_bool();
+        let page = buffer.read_u8();
+        self.oam_dma_page = oam_dma.then_some(page);
     }
 }
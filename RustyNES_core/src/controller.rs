@@ -27,6 +27,11 @@ impl Controller {
         val
     }
 
+    // the bit read returns, without moving to the next button
+    pub fn peek(&self) -> u8 {
+        (self.state >> self.index) & 1
+    }
+
     pub fn write(&mut self, val: u8) {
         self.reset = (val & 1) == 1;
         if self.reset {
@@ -0,0 +1,379 @@
+use std::ops::RangeInclusive;
+
+use super::CPU;
+
+// address space of a watchpoint,
+// PPU watchpoints see the rendering fetches and the CPU's accesses through 0x2007
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemorySpace {
+    Cpu,
+    Ppu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// why step_till_next_frame or a step returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // the PPU finished the frame (vblank started)
+    FrameComplete,
+    // before the instruction at addr
+    Breakpoint {
+        addr: u16,
+    },
+    // after the instruction that made the access
+    Watchpoint {
+        space: MemorySpace,
+        access: Access,
+        addr: u16,
+        val: u8,
+    },
+    // at the first instruction of the handler
+    Nmi,
+    Irq,
+    // the PPU reached the dot during the last instruction
+    Dot {
+        line: u16,
+        dot: u16,
+    },
+    // before the BRK at addr
+    Brk {
+        addr: u16,
+    },
+    // a JAM opcode at addr halted the CPU
+    CpuHalted {
+        addr: u16,
+    },
+    // step into, over or out finished
+    StepComplete,
+}
+
+// execution breakpoint, bank is the 8KB PRG ROM bank (offset in PRG ROM / 0x2000)
+// that has to be mapped at addr, None breaks on whatever is mapped there (RAM included)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub bank: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: MemorySpace,
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+enum Step {
+    Into,
+    // until the JSR returns to addr with the stack pointer it was called with
+    Over { addr: u16, sp: u8 },
+    // until an RTS or RTI pulls the stack pointer above sp
+    Out { sp: u8 },
+}
+
+// breakpoints, watchpoints and stepping, checked by the CPU while it runs
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub break_on_nmi: bool,
+    pub break_on_irq: bool,
+    pub break_on_brk: bool,
+    pub break_on_jam: bool,
+    pub break_at_dot: Option<(u16, u16)>, // (line, dot)
+
+    // a step that did not finish before the end of the frame goes on in the next one,
+    // any other stop cancels it
+    step: Option<Step>,
+    // seen while the instruction ran
+    stop: Option<StopReason>,
+    // the instruction the CPU stopped at is run when it is resumed instead of stopping again
+    resume_addr: Option<u16>,
+}
+
+impl Debugger {
+    pub fn stepping(&self) -> bool {
+        self.step.is_some()
+    }
+
+    pub fn cancel_step(&mut self) {
+        self.step = None;
+    }
+
+    // the first reason wins
+    fn stop(&mut self, reason: StopReason) {
+        if self.stop.is_none() {
+            self.stop = Some(reason);
+        }
+    }
+
+    // CPU watchpoints, the PPU checks its own (see PPU::watch)
+    pub(super) fn watch(&mut self, access: Access, addr: u16, val: u8) {
+        let hit = self.watchpoints.iter().any(|watchpoint| {
+            let watched = match access {
+                Access::Read => watchpoint.read,
+                Access::Write => watchpoint.write,
+            };
+            watchpoint.space == MemorySpace::Cpu && watched && watchpoint.range.contains(&addr)
+        });
+        if hit {
+            self.stop(StopReason::Watchpoint {
+                space: MemorySpace::Cpu,
+                access,
+                addr,
+                val,
+            });
+        }
+    }
+
+    pub(super) fn interrupt_entered(&mut self, nmi: bool) {
+        if nmi && self.break_on_nmi {
+            self.stop(StopReason::Nmi);
+        } else if !nmi && self.break_on_irq {
+            self.stop(StopReason::Irq);
+        }
+    }
+}
+
+impl CPU {
+    // runs the instruction at pc and stops after it
+    pub fn step_into(&mut self) -> StopReason {
+        self.start_step(Step::Into)
+    }
+
+    // like step_into, but a JSR is run until the subroutine returns
+    pub fn step_over(&mut self) -> StopReason {
+        let step = if self.bus.peek(self.pc) == 0x20 {
+            Step::Over {
+                addr: self.pc.wrapping_add(3),
+                sp: self.sp,
+            }
+        } else {
+            Step::Into
+        };
+        self.start_step(step)
+    }
+
+    // runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self) -> StopReason {
+        self.start_step(Step::Out { sp: self.sp })
+    }
+
+    // runs until the PPU completes frame number frame (see PPU::frame_count)
+    pub fn run_to_frame(&mut self, frame: u64) -> StopReason {
+        while self.bus.ppu.frame_count() < frame {
+            let reason = self.run_until_stop();
+            if reason != StopReason::FrameComplete {
+                return reason;
+            }
+        }
+        StopReason::FrameComplete
+    }
+
+    // the instruction at pc always runs, even if the CPU did not stop there
+    fn start_step(&mut self, step: Step) -> StopReason {
+        self.debugger.step = Some(step);
+        self.debugger.resume_addr = Some(self.pc);
+        self.run_until_stop()
+    }
+
+    // runs instructions until the frame is complete or the debugger stops the CPU
+    pub(crate) fn run_until_stop(&mut self) -> StopReason {
+        // the PPU watchpoints are checked by the PPU itself
+        let watchpoints = self.debugger.watchpoints.iter();
+        self.bus.ppu.watchpoints = watchpoints
+            .filter(|watchpoint| watchpoint.space == MemorySpace::Ppu)
+            .cloned()
+            .collect();
+        self.bus.ppu.watch_hit = None;
+
+        let reason = loop {
+            // a frame that completed together with the last stop is reported first
+            if self.bus.ppu.frame_complete() {
+                return StopReason::FrameComplete;
+            }
+            if let Some(reason) = self.check_before_instruction() {
+                break reason;
+            }
+            // step out waits for an RTS or RTI
+            let opcode = match self.debugger.step {
+                Some(Step::Out { .. }) => self.bus.peek(self.pc),
+                _ => 0,
+            };
+            let halted = self.halted;
+            self.step();
+            if let Some(reason) = self.check_after_instruction(opcode, halted) {
+                break reason;
+            }
+        };
+        self.debugger.resume_addr = Some(self.pc);
+        // a step the debugger stopped for another reason is not finished later
+        self.debugger.step = None;
+        reason
+    }
+
+    // breakpoints and BRK stop before the instruction runs
+    fn check_before_instruction(&mut self) -> Option<StopReason> {
+        if self.halted {
+            // a halted CPU never finishes a step
+            self.debugger.step.take()?;
+            return Some(StopReason::CpuHalted {
+                addr: self.pc.wrapping_sub(1),
+            });
+        }
+        let pc = self.pc;
+        if self.debugger.resume_addr.take() == Some(pc) {
+            return None;
+        }
+        if self.debugger.break_on_brk && self.bus.peek(pc) == 0x00 {
+            return Some(StopReason::Brk { addr: pc });
+        }
+        let cartridge = &self.bus.ppu.cartridge;
+        let hit = self.debugger.breakpoints.iter().any(|breakpoint| {
+            breakpoint.addr == pc
+                && breakpoint.bank.is_none_or(|bank| {
+                    cartridge
+                        .prg_rom_offset(pc)
+                        .is_some_and(|offset| offset / 0x2000 == bank)
+                })
+        });
+        hit.then_some(StopReason::Breakpoint { addr: pc })
+    }
+
+    // watchpoints, interrupts, the dot, JAM and the end of a step stop after it
+    fn check_after_instruction(&mut self, opcode: u8, was_halted: bool) -> Option<StopReason> {
+        if let Some(reason) = self.bus.ppu.watch_hit.take() {
+            self.debugger.stop(reason);
+        }
+        if let Some(reason) = self.debugger.stop.take() {
+            return Some(reason);
+        }
+        if self.halted && !was_halted && self.debugger.break_on_jam {
+            return Some(StopReason::CpuHalted {
+                addr: self.pc.wrapping_sub(1),
+            });
+        }
+        let done = match self.debugger.step {
+            None => false,
+            Some(Step::Into) => true,
+            Some(Step::Over { addr, sp }) => self.pc == addr && self.sp == sp,
+            // RTI (0x40) or RTS (0x60) returned above the stack pointer the step started with
+            Some(Step::Out { sp }) => {
+                matches!(opcode, 0x40 | 0x60) && (self.sp.wrapping_sub(sp) as i8) > 0
+            }
+        };
+        done.then_some(StopReason::StepComplete)
+    }
+
+    // runs the PPU up to the master clock, stopping the CPU when it passes break_at_dot
+    pub(super) fn run_ppu(&mut self, master_clock: u64) {
+        let Some((line, dot)) = self.debugger.break_at_dot else {
+            self.bus.ppu.run_to(master_clock);
+            return;
+        };
+        let ppu = &self.bus.ppu;
+        let before = ppu.line() as u32 * 341 + ppu.dot() as u32;
+        self.bus.ppu.run_to(master_clock);
+        let ppu = &self.bus.ppu;
+        let after = ppu.line() as u32 * 341 + ppu.dot() as u32;
+        let target = line as u32 * 341 + dot as u32;
+        // the position wraps around at the end of the frame
+        let passed = if before <= after {
+            before < target && target <= after
+        } else {
+            before < target || target <= after
+        };
+        if passed {
+            self.debugger.stop(StopReason::Dot { line, dot });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NROM with the program at 0xC000
+    fn cpu(program: &[u8]) -> CPU {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x3FFC..].copy_from_slice(&[0x00, 0xC0, 0x00, 0xC0]);
+        let mut file = b"NES\x1A\x01\x01\x01\x00".to_vec();
+        file.resize(16, 0);
+        file.extend_from_slice(&prg_rom);
+        file.extend_from_slice(&[0; 0x2000]);
+        let mut cpu = CPU::new_from_rom_bytes(file);
+        // the PPU starts at vblank, the first frame completes before the first instruction
+        assert_eq!(cpu.run_until_stop(), StopReason::FrameComplete);
+        assert_eq!(cpu.pc(), 0xC000);
+        cpu
+    }
+
+    #[test]
+    fn other_stop_cancels_step() {
+        let mut cpu = cpu(&[
+            0x20, 0x06, 0xC0, // JSR 0xC006
+            0x4C, 0x03, 0xC0, // JMP 0xC003
+            0xEA, 0x60, // NOP, RTS
+        ]);
+        cpu.debugger.breakpoints.push(Breakpoint {
+            addr: 0xC007,
+            bank: None,
+        });
+        assert_eq!(cpu.step_over(), StopReason::Breakpoint { addr: 0xC007 });
+        assert!(!cpu.debugger.stepping());
+        cpu.debugger.breakpoints.clear();
+        // the JSR returns, the step over is not finished later
+        assert_eq!(cpu.run_until_stop(), StopReason::FrameComplete);
+    }
+
+    #[test]
+    fn ppu_watchpoints() {
+        let mut cpu = cpu(&[
+            0xA9, 0x21, 0x8D, 0x06, 0x20, // LDA #0x21, STA 0x2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #0x00, STA 0x2006
+            0xA9, 0x55, 0x8D, 0x07, 0x20, // LDA #0x55, STA 0x2007
+            0xA9, 0x08, 0x8D, 0x01, 0x20, // LDA #0x08, STA 0x2001 (background on)
+            0x4C, 0x14, 0xC0, // JMP 0xC014
+        ]);
+        cpu.debugger.watchpoints.push(Watchpoint {
+            space: MemorySpace::Ppu,
+            range: 0x2100..=0x21FF,
+            read: false,
+            write: true,
+        });
+        assert_eq!(
+            cpu.run_until_stop(),
+            StopReason::Watchpoint {
+                space: MemorySpace::Ppu,
+                access: Access::Write,
+                addr: 0x2100,
+                val: 0x55
+            }
+        );
+        assert_eq!(cpu.pc(), 0xC00F);
+
+        // the pattern table fetches of the rendered background
+        cpu.debugger.watchpoints[0] = Watchpoint {
+            space: MemorySpace::Ppu,
+            range: 0x0000..=0x0FFF,
+            read: true,
+            write: false,
+        };
+        let reason = cpu.run_to_frame(3);
+        assert!(matches!(
+            reason,
+            StopReason::Watchpoint {
+                space: MemorySpace::Ppu,
+                access: Access::Read,
+                addr: 0x0000..=0x0FFF,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use super::instructions::{AddressingMode, Instruction, OPCODE, OPCODES};
+
+// formats the instruction at addr in 6502 assembly (e.g. "LDA $12,X", "JMP ($FFFC)"),
+// returns the text and the size of the instruction in bytes
+// bus_peek reads a byte without side effects, branch targets are resolved to absolute addresses
+pub fn disassemble(mut bus_peek: impl FnMut(u16) -> u8, addr: u16) -> (String, u16) {
+    let OPCODE {
+        instruction,
+        mode,
+        size,
+        ..
+    } = &OPCODES[bus_peek(addr) as usize];
+    let lo = bus_peek(addr.wrapping_add(1));
+    let hi = bus_peek(addr.wrapping_add(2));
+    let word = u16::from_le_bytes([lo, hi]);
+
+    let operand = match mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Accumulator => " A".to_string(),
+        AddressingMode::Immediate => format!(" #${:02X}", lo),
+        AddressingMode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(lo as i8 as u16);
+            format!(" ${:04X}", target)
+        }
+        AddressingMode::ZeroPage => format!(" ${:02X}", lo),
+        AddressingMode::ZeroPageX => format!(" ${:02X},X", lo),
+        AddressingMode::ZeroPageY => format!(" ${:02X},Y", lo),
+        AddressingMode::Absolute => format!(" ${:04X}", word),
+        AddressingMode::AbsoluteX => format!(" ${:04X},X", word),
+        AddressingMode::AbsoluteY => format!(" ${:04X},Y", word),
+        AddressingMode::Indirect => format!(" (${:04X})", word),
+        AddressingMode::IndirectX => format!(" (${:02X},X)", lo),
+        AddressingMode::IndirectY => format!(" (${:02X}),Y", lo),
+    };
+
+    (format!("{:?}{}", instruction, operand), *size)
+}
+
+// disassembles the instructions from start up to and including end, as (address, text) pairs
+// the last instruction can read operand bytes past end
+pub fn disassemble_range(
+    mut bus_peek: impl FnMut(u16) -> u8,
+    start: u16,
+    end: u16,
+) -> Vec<(u16, String)> {
+    let mut lines = Vec::new();
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let (text, len) = disassemble(&mut bus_peek, addr as u16);
+        lines.push((addr as u16, text));
+        addr += len as u32;
+    }
+    lines
+}
+
+// disassembles a PRG bank mapped at base_addr (e.g. a 16KB bank at 0x8000 or 0xC000),
+// operand bytes past the end of the bank read as 0
+pub fn disassemble_bank(bank: &[u8], base_addr: u16) -> Vec<(u16, String)> {
+    if bank.is_empty() {
+        return Vec::new();
+    }
+    let end = base_addr.wrapping_add((bank.len() - 1) as u16);
+    disassemble_range(
+        |addr| {
+            let index = addr.wrapping_sub(base_addr) as usize;
+            bank.get(index).copied().unwrap_or(0)
+        },
+        base_addr,
+        end,
+    )
+}
+
+// opcodes outside the documented instruction set,
+// including the NOPs other than 0xEA and the copy of SBC #imm at 0xEB
+pub fn is_unofficial(opcode: u8) -> bool {
+    match OPCODES[opcode as usize].instruction {
+        Instruction::NOP => opcode != 0xEA,
+        Instruction::SBC => opcode == 0xEB,
+        ref instruction => instruction.unofficial(),
+    }
+}
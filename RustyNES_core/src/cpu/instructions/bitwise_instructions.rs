@@ -22,7 +22,7 @@ impl super::CPU {
     pub fn asl(&mut self, addr: u16, addr_mode: &AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         let result = val << 1;
@@ -38,7 +38,7 @@ impl super::CPU {
     pub fn lsr(&mut self, addr: u16, addr_mode: &AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         let result = val >> 1;
@@ -54,7 +54,7 @@ impl super::CPU {
     pub fn rol(&mut self, addr: u16, addr_mode: &AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         let result = (val << 1) | (self.c as u8);
@@ -70,7 +70,7 @@ impl super::CPU {
     pub fn ror(&mut self, addr: u16, addr_mode: &AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         let result = (val >> 1) | ((self.c as u8) << 7);
@@ -1,20 +1,26 @@
 impl super::CPU {
     #[inline]
     pub fn branch(&mut self, new_addr: u16) {
+        // a taken branch that does not cross a page does not poll the IRQ on its last cycle,
+        // an IRQ that comes in then runs after the next instruction
+        if self.run_irq && !self.prev_run_irq {
+            self.run_irq = false;
+        }
+
         // record old address
         let old_addr = self.pc;
 
-        // branch to new address
-        self.pc = new_addr;
-
-        // update cycles
-        self.cycles += 1;
+        // taking the branch takes 1 cycle
+        self.dummy_read(old_addr);
         // check if branch crosses page boundary
         // between old and new address
-        // if it does, add 1 cycle
+        // if it does, add 1 cycle to fix the high byte
         if (old_addr & 0xFF00) != (new_addr & 0xFF00) {
-            self.cycles += 1;
+            self.dummy_read((old_addr & 0xFF00) | (new_addr & 0x00FF));
         }
+
+        // branch to new address
+        self.pc = new_addr;
     }
 
     pub fn bpl(&mut self, new_addr: u16) {
@@ -1,22 +1,22 @@
 impl super::CPU {
     pub fn cmp(&mut self, addr: u16) {
         let val = self.read(addr);
-        let subtraction = self.a.wrapping_sub(val);
-        self.c = self.a >= val;
-        self.update_zn_flags(subtraction);
+        self.compare(self.a, val);
     }
 
     pub fn cpx(&mut self, addr: u16) {
         let val = self.read(addr);
-        let subtraction = self.x.wrapping_sub(val);
-        self.c = self.x >= val;
-        self.update_zn_flags(subtraction);
+        self.compare(self.x, val);
     }
 
     pub fn cpy(&mut self, addr: u16) {
         let val = self.read(addr);
-        let subtraction = self.y.wrapping_sub(val);
-        self.c = self.y >= val;
+        self.compare(self.y, val);
+    }
+
+    pub(super) fn compare(&mut self, reg: u8, val: u8) {
+        let subtraction = reg.wrapping_sub(val);
+        self.c = reg >= val;
         self.update_zn_flags(subtraction);
     }
 
@@ -0,0 +1,167 @@
+use super::AddressingMode;
+use crate::cpu::CpuEvent;
+
+// unofficial opcodes, most of them run two official instructions at once,
+// they follow the 6502 single-step test vectors (NES CPU without decimal mode)
+impl super::CPU {
+    // Read-modify-write ////
+    // ASL + ORA
+    pub fn slo(&mut self, addr: u16) {
+        let val = self.read_for_modify(addr);
+        let result = val << 1;
+        self.c = val & 0x80 != 0;
+        self.write(addr, result);
+        self.a |= result;
+        self.update_zn_flags(self.a);
+    }
+
+    // ROL + AND
+    pub fn rla(&mut self, addr: u16) {
+        let val = self.read_for_modify(addr);
+        let result = (val << 1) | (self.c as u8);
+        self.c = val & 0x80 != 0;
+        self.write(addr, result);
+        self.a &= result;
+        self.update_zn_flags(self.a);
+    }
+
+    // LSR + EOR
+    pub fn sre(&mut self, addr: u16) {
+        let val = self.read_for_modify(addr);
+        let result = val >> 1;
+        self.c = val & 0x01 == 1;
+        self.write(addr, result);
+        self.a ^= result;
+        self.update_zn_flags(self.a);
+    }
+
+    // ROR + ADC, the carry out of the rotate goes into the addition
+    pub fn rra(&mut self, addr: u16) {
+        let val = self.read_for_modify(addr);
+        let result = (val >> 1) | ((self.c as u8) << 7);
+        self.c = val & 0x01 == 1;
+        self.write(addr, result);
+        self.add_with_carry(result);
+    }
+
+    // DEC + CMP
+    pub fn dcp(&mut self, addr: u16) {
+        let result = self.read_for_modify(addr).wrapping_sub(1);
+        self.write(addr, result);
+        self.compare(self.a, result);
+    }
+
+    // INC + SBC
+    pub fn isb(&mut self, addr: u16) {
+        let result = self.read_for_modify(addr).wrapping_add(1);
+        self.write(addr, result);
+        self.add_with_carry(!result);
+    }
+
+    // Load and store ////
+    // LDA + LDX, the immediate version (LXA) mixes in the accumulator like XAA
+    pub fn lax(&mut self, addr: u16, addr_mode: &AddressingMode) {
+        let val = match addr_mode {
+            AddressingMode::Immediate => (self.a | self.magic_constant) & self.read(addr),
+            _ => self.read(addr),
+        };
+        self.a = val;
+        self.x = val;
+        self.update_zn_flags(val);
+    }
+
+    pub fn sax(&mut self, addr: u16) {
+        self.write(addr, self.a & self.x);
+    }
+
+    // loads A, X and the stack pointer with the value ANDed with the stack pointer
+    pub fn las(&mut self, addr: u16) {
+        let val = self.read(addr) & self.sp;
+        self.a = val;
+        self.x = val;
+        self.sp = val;
+        self.update_zn_flags(val);
+    }
+
+    // Immediate ////
+    // AND, then the carry is copied from bit 7
+    pub fn anc(&mut self, addr: u16) {
+        self.a &= self.read(addr);
+        self.update_zn_flags(self.a);
+        self.c = self.n;
+    }
+
+    // AND + LSR A
+    pub fn alr(&mut self, addr: u16) {
+        let val = self.a & self.read(addr);
+        self.c = val & 0x01 == 1;
+        self.a = val >> 1;
+        self.update_zn_flags(self.a);
+    }
+
+    // AND + ROR A, the carry comes from bit 6 and overflow is bit 6 XOR bit 5 of the result
+    pub fn arr(&mut self, addr: u16) {
+        let val = self.a & self.read(addr);
+        self.a = (val >> 1) | ((self.c as u8) << 7);
+        self.update_zn_flags(self.a);
+        self.c = self.a & 0x40 != 0;
+        self.v = ((self.a >> 6) ^ (self.a >> 5)) & 0x01 == 1;
+    }
+
+    // X = (A AND X) - value, sets the flags like CMP
+    pub fn axs(&mut self, addr: u16) {
+        let val = self.read(addr);
+        let ax = self.a & self.x;
+        self.c = ax >= val;
+        self.x = ax.wrapping_sub(val);
+        self.update_zn_flags(self.x);
+    }
+
+    // the accumulator is ORed with a constant that differs between chips before the AND
+    pub fn xaa(&mut self, addr: u16) {
+        let val = self.read(addr);
+        self.a = (self.a | self.magic_constant) & self.x & val;
+        self.update_zn_flags(self.a);
+    }
+
+    // Unstable stores ////
+    // the value is ANDed with the high byte of the base address + 1,
+    // when the index crosses a page the value also replaces the high byte of the address
+    fn unstable_store(&mut self, addr: u16, index: u8, val: u8) {
+        let base = addr.wrapping_sub(index as u16);
+        let val = val & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if (base ^ addr) & 0xFF00 != 0 {
+            ((val as u16) << 8) | (addr & 0x00FF)
+        } else {
+            addr
+        };
+        self.write(addr, val);
+    }
+
+    pub fn shy(&mut self, addr: u16) {
+        self.unstable_store(addr, self.x, self.y);
+    }
+
+    pub fn shx(&mut self, addr: u16) {
+        self.unstable_store(addr, self.y, self.x);
+    }
+
+    pub fn ahx(&mut self, addr: u16) {
+        self.unstable_store(addr, self.y, self.a & self.x);
+    }
+
+    // the stack pointer is set to A AND X, then stored like AHX
+    pub fn tas(&mut self, addr: u16) {
+        self.sp = self.a & self.x;
+        self.unstable_store(addr, self.y, self.sp);
+    }
+
+    // Halt ////
+    // the CPU locks up until it is reset, the front end is told where it stopped
+    pub fn jam(&mut self) {
+        self.halted = true;
+        self.event = Some(CpuEvent::CpuHalted {
+            addr: self.pc.wrapping_sub(1),
+        });
+    }
+}
@@ -3,28 +3,39 @@ impl super::CPU {
         self.pc = addr;
     }
 
+    // addr is the low byte of the target, the high byte is read on the last cycle
     pub fn jsr(&mut self, addr: u16) {
-        self.push_16(self.pc - 1);
-        self.pc = addr;
+        self.dummy_read(0x100 | self.sp as u16);
+        // pc points at the high byte, the last byte of the JSR
+        self.push_16(self.pc);
+        let hi = self.read(self.pc) as u16;
+        self.pc = (hi << 8) | addr;
     }
 
     pub fn rts(&mut self) {
+        self.dummy_read(0x100 | self.sp as u16);
         let pc = self.pull_16();
-        self.pc = pc + 1;
+        // pc is incremented on the last cycle
+        self.dummy_read(pc);
+        self.pc = pc.wrapping_add(1);
     }
 
     pub fn brk(&mut self) {
-        let pc = self.pc + 1;
+        // the byte after BRK is skipped
+        let pc = self.pc.wrapping_add(1);
         self.push_16(pc);
 
         self.b = true;
         self.push_8(self.get_flags() | 0x10);
         self.i = true;
 
-        self.pc = self.read_16(0xFFFE);
+        // an NMI that comes in before the vector is fetched takes over the BRK
+        let vector = self.interrupt_vector();
+        self.pc = self.read_16(vector);
     }
 
     pub fn rti(&mut self) {
+        self.dummy_read(0x100 | self.sp as u16);
         let flags = self.pull_8() & 0xEF | 0x20;
         self.set_flags(flags);
         let pc = self.pull_16();
@@ -1,17 +1,16 @@
 impl super::CPU {
     pub fn adc(&mut self, addr: u16) {
         let val = self.read(addr);
-        let sum_u16 = (self.a as u16) + (val as u16) + (self.c as u16);
-        let sum_u8 = (sum_u16 & 0x00FF) as u8;
-
-        self.v = ((self.a ^ val) & 0x80 == 0) && ((self.a ^ sum_u8) & 0x80 != 0);
-        self.c = sum_u16 > 0xFF;
-        self.a = sum_u8;
-        self.update_zn_flags(sum_u8);
+        self.add_with_carry(val);
     }
 
+    // SBC adds the inverted value, there is no decimal mode on the NES
     pub fn sbc(&mut self, addr: u16) {
-        let val = !self.read(addr);
+        let val = self.read(addr);
+        self.add_with_carry(!val);
+    }
+
+    pub(super) fn add_with_carry(&mut self, val: u8) {
         let sum_u16 = (self.a as u16) + (val as u16) + (self.c as u16);
         let sum_u8 = (sum_u16 & 0x00FF) as u8;
 
@@ -1,3 +1,5 @@
+use super::AddressingMode;
+
 impl super::CPU {
     pub fn lda(&mut self, addr: u16) {
         self.a = self.read(addr);
@@ -27,14 +29,24 @@ impl super::CPU {
     }
 
     pub fn inc(&mut self, addr: u16) {
-        let val = self.read(addr).wrapping_add(1);
+        let val = self.read_for_modify(addr).wrapping_add(1);
         self.write(addr, val);
         self.update_zn_flags(val);
     }
 
     pub fn dec(&mut self, addr: u16) {
-        let val = self.read(addr).wrapping_sub(1);
+        let val = self.read_for_modify(addr).wrapping_sub(1);
         self.write(addr, val);
         self.update_zn_flags(val);
     }
+
+    // unofficial NOPs with an operand read it and ignore the value
+    pub fn nop(&mut self, addr: u16, addr_mode: &AddressingMode) {
+        match addr_mode {
+            AddressingMode::Implied => {}
+            _ => {
+                self.read(addr);
+            }
+        }
+    }
 }
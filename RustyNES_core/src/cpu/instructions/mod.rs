@@ -1,3 +1,4 @@
+use super::debugger::Access;
 use super::CPU;
 
 // Module declarations ///////////////////////
@@ -5,6 +6,7 @@ mod bitwise_instructions;
 mod branch_instructions;
 mod compare_instructions;
 mod flag_instructions;
+mod illegal_instructions;
 mod jump_instructions;
 mod math_instructions;
 mod memory_instructions;
@@ -14,24 +16,62 @@ mod stack_instructions;
 // Utils //////////////////
 impl CPU {
     // IO utils //////////////////
-    fn read(&mut self, addr: u16) -> u8 {
-        self.bus.read(addr)
+    // every read and write takes one CPU cycle
+    pub(super) fn read(&mut self, addr: u16) -> u8 {
+        self.start_cycle(true);
+        let val = self.bus.read(addr);
+        self.end_cycle(true);
+        if !self.debugger.watchpoints.is_empty() {
+            self.debugger.watch(Access::Read, addr, val);
+        }
+        val
     }
 
-    fn write(&mut self, addr: u16, val: u8) {
+    pub(super) fn write(&mut self, addr: u16, val: u8) {
+        self.start_cycle(false);
         self.bus.write(addr, val);
+        self.end_cycle(false);
+        if !self.debugger.watchpoints.is_empty() {
+            self.debugger.watch(Access::Write, addr, val);
+        }
+    }
+
+    // the 6502 reads on every cycle, even when it does not need the value,
+    // these reads still have side effects on the registers they hit
+    pub(super) fn dummy_read(&mut self, addr: u16) {
+        self.read(addr);
+    }
+
+    // read-modify-write instructions write the value back unchanged while they modify it
+    fn read_for_modify(&mut self, addr: u16) -> u8 {
+        let val = self.read(addr);
+        self.write(addr, val);
+        val
     }
 
     pub fn read_16(&mut self, addr: u16) -> u16 {
         let lo = self.read(addr) as u16;
-        let hi = self.read(addr + 1) as u16;
+        let hi = self.read(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    // reads the byte at pc and moves pc to the next byte
+    fn fetch_8(&mut self) -> u8 {
+        let val = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        val
+    }
+
+    fn fetch_16(&mut self) -> u16 {
+        let lo = self.fetch_8() as u16;
+        let hi = self.fetch_8() as u16;
         (hi << 8) | lo
     }
 
     fn read_16_from_same_page(&mut self, addr: u16) -> u16 {
         let lo = self.read(addr) as u16;
         let hi = if addr & 0xFF != 0xFF {
-            self.read(addr + 1) as u16
+            self.read(addr.wrapping_add(1)) as u16
         } else {
             self.read(addr & 0xFF00) as u16
         };
@@ -39,7 +79,7 @@ impl CPU {
     }
 
     // Stack utils //////////////////
-    fn push_8(&mut self, val: u8) {
+    pub(super) fn push_8(&mut self, val: u8) {
         self.write(0x100 | self.sp as u16, val);
         self.sp = self.sp.wrapping_sub(1);
     }
@@ -63,7 +103,7 @@ impl CPU {
     }
 
     // Status flag update utils //////////////////
-    fn get_flags(&self) -> u8 {
+    pub(super) fn get_flags(&self) -> u8 {
         (self.c as u8)
             | (self.z as u8) << 1
             | (self.i as u8) << 2
@@ -109,83 +149,102 @@ pub enum AddressingMode {
 }
 
 impl AddressingMode {
-    pub fn fetch_operand_address(&self, cpu: &mut CPU) -> (u16, bool) {
-        let pc = cpu.pc;
+    // reads the operand bytes (moving pc to the next instruction) and returns the operand address,
+    // with the dummy reads each mode does on the way
+    pub fn fetch_operand_address(&self, cpu: &mut CPU, instruction: &Instruction) -> u16 {
         match *self {
-            AddressingMode::Accumulator => (0, false),
-
-            AddressingMode::Implied => (0, false),
+            // the byte after the opcode is read and ignored
+            AddressingMode::Accumulator | AddressingMode::Implied => {
+                cpu.dummy_read(cpu.pc);
+                0
+            }
 
-            AddressingMode::Immediate => (pc + 1, false),
+            // the operand is read by the instruction
+            AddressingMode::Immediate => {
+                let addr = cpu.pc;
+                cpu.pc = cpu.pc.wrapping_add(1);
+                addr
+            }
 
             AddressingMode::Relative => {
-                let offset = cpu.read(pc + 1) as i8 as i16;
-                let pc = pc as i16;
-                let addr = (pc + offset + 2) as u16;
-                (addr, false)
+                let offset = cpu.fetch_8() as i8;
+                cpu.pc.wrapping_add(offset as u16)
             }
 
             //// ZeroPage ////
-            AddressingMode::ZeroPage => {
-                let addr = cpu.read(pc + 1) as u16;
-                (addr, false)
-            }
+            AddressingMode::ZeroPage => cpu.fetch_8() as u16,
 
+            // the zero page address is read before the index is added
             AddressingMode::ZeroPageX => {
-                let addr = cpu.read(pc + 1).wrapping_add(cpu.x) as u16;
-                (addr, false)
+                let addr = cpu.fetch_8();
+                cpu.dummy_read(addr as u16);
+                addr.wrapping_add(cpu.x) as u16
             }
 
             AddressingMode::ZeroPageY => {
-                let addr = cpu.read(pc + 1).wrapping_add(cpu.y) as u16;
-                (addr, false)
+                let addr = cpu.fetch_8();
+                cpu.dummy_read(addr as u16);
+                addr.wrapping_add(cpu.y) as u16
             }
 
             //// Absolute ////
-            AddressingMode::Absolute => {
-                let addr = cpu.read_16(pc + 1);
-                (addr, false)
+            // JSR reads the high byte after pushing the return address, see jsr
+            AddressingMode::Absolute if matches!(instruction, Instruction::JSR) => {
+                cpu.fetch_8() as u16
             }
 
+            AddressingMode::Absolute => cpu.fetch_16(),
+
             AddressingMode::AbsoluteX => {
-                let addr = cpu.read_16(pc + 1);
-                let new_addr = addr.wrapping_add(cpu.x as u16);
-                (new_addr, self.page_crossed(addr, new_addr))
+                let addr = cpu.fetch_16();
+                self.index(cpu, addr, cpu.x, instruction)
             }
 
             AddressingMode::AbsoluteY => {
-                let addr = cpu.read_16(pc + 1);
-                let new_addr = addr.wrapping_add(cpu.y as u16);
-                (new_addr, self.page_crossed(addr, new_addr))
+                let addr = cpu.fetch_16();
+                self.index(cpu, addr, cpu.y, instruction)
             }
 
             //// Indirect ////
             AddressingMode::Indirect => {
-                let addr = cpu.read_16(pc + 1);
-                let addr = cpu.read_16_from_same_page(addr);
-                (addr, false)
+                let addr = cpu.fetch_16();
+                cpu.read_16_from_same_page(addr)
             }
 
             AddressingMode::IndirectX => {
-                let addr = cpu.read(pc + 1).wrapping_add(cpu.x) as u16;
-                let addr = cpu.read_16_from_same_page(addr);
-                (addr, false)
+                let addr = cpu.fetch_8();
+                cpu.dummy_read(addr as u16);
+                let addr = addr.wrapping_add(cpu.x) as u16;
+                cpu.read_16_from_same_page(addr)
             }
 
             AddressingMode::IndirectY => {
-                let addr = cpu.read(pc + 1) as u16;
-                let new_addr = cpu.read_16_from_same_page(addr).wrapping_add(cpu.y as u16);
-                (new_addr, self.page_crossed(addr, new_addr))
+                let addr = cpu.fetch_8() as u16;
+                let addr = cpu.read_16_from_same_page(addr);
+                self.index(cpu, addr, cpu.y, instruction)
             }
         }
     }
 
+    // the CPU first reads from the indexed address without the carry into the high byte,
+    // when the page is crossed it takes an extra cycle to read from the right address
+    // instructions that write to the address always take the extra cycle
+    fn index(&self, cpu: &mut CPU, addr: u16, index: u8, instruction: &Instruction) -> u16 {
+        let new_addr = addr.wrapping_add(index as u16);
+        if self.page_crossed(addr, new_addr) || instruction.writes_memory() {
+            cpu.dummy_read((addr & 0xFF00) | (new_addr & 0x00FF));
+        }
+        new_addr
+    }
+
     fn page_crossed(&self, addr_1: u16, addr_2: u16) -> bool {
         (addr_1 & 0xFF00) != (addr_2 & 0xFF00)
     }
 }
 
 // Instructions //////////////////
+// Debug prints the mnemonic
+#[derive(Debug)]
 pub enum Instruction {
     // BITWISE ////
     AND,
@@ -286,6 +345,56 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    pub fn unofficial(&self) -> bool {
+        matches!(
+            self,
+            AHX | ALR
+                | ANC
+                | ARR
+                | AXS
+                | DCP
+                | ISB
+                | JAM
+                | LAS
+                | LAX
+                | RLA
+                | RRA
+                | SAX
+                | SHX
+                | SHY
+                | SLO
+                | SRE
+                | TAS
+                | XAA
+        )
+    }
+
+    // stores and read-modify-write instructions
+    pub fn writes_memory(&self) -> bool {
+        matches!(
+            self,
+            STA | STX
+                | STY
+                | ASL
+                | LSR
+                | ROL
+                | ROR
+                | INC
+                | DEC
+                | AHX
+                | DCP
+                | ISB
+                | RLA
+                | RRA
+                | SAX
+                | SHX
+                | SHY
+                | SLO
+                | SRE
+                | TAS
+        )
+    }
+
     pub fn execute(&self, cpu: &mut CPU, addr: u16, mode: &AddressingMode) {
         match *self {
             // BITWISE ////
@@ -362,29 +471,29 @@ impl Instruction {
             Instruction::PLP => cpu.plp(),
 
             // OTHER ////
-            Instruction::NOP => (),
+            Instruction::NOP => cpu.nop(addr, mode),
             Instruction::BRK => cpu.brk(),
 
             // ILLEGAL ////
-            Instruction::AHX => (),
-            Instruction::ALR => (),
-            Instruction::ANC => (),
-            Instruction::ARR => (),
-            Instruction::AXS => (),
-            Instruction::DCP => (),
-            Instruction::ISB => (),
-            Instruction::JAM => (),
-            Instruction::LAS => (),
-            Instruction::LAX => (),
-            Instruction::RLA => (),
-            Instruction::RRA => (),
-            Instruction::SAX => (),
-            Instruction::SHX => (),
-            Instruction::SHY => (),
-            Instruction::SLO => (),
-            Instruction::SRE => (),
-            Instruction::TAS => (),
-            Instruction::XAA => (),
+            Instruction::AHX => cpu.ahx(addr),
+            Instruction::ALR => cpu.alr(addr),
+            Instruction::ANC => cpu.anc(addr),
+            Instruction::ARR => cpu.arr(addr),
+            Instruction::AXS => cpu.axs(addr),
+            Instruction::DCP => cpu.dcp(addr),
+            Instruction::ISB => cpu.isb(addr),
+            Instruction::JAM => cpu.jam(),
+            Instruction::LAS => cpu.las(addr),
+            Instruction::LAX => cpu.lax(addr, mode),
+            Instruction::RLA => cpu.rla(addr),
+            Instruction::RRA => cpu.rra(addr),
+            Instruction::SAX => cpu.sax(addr),
+            Instruction::SHX => cpu.shx(addr),
+            Instruction::SHY => cpu.shy(addr),
+            Instruction::SLO => cpu.slo(addr),
+            Instruction::SRE => cpu.sre(addr),
+            Instruction::TAS => cpu.tas(addr),
+            Instruction::XAA => cpu.xaa(addr),
         }
     }
 }
@@ -393,6 +502,9 @@ impl Instruction {
 use AddressingMode::*;
 use Instruction::*;
 
+// size and cycles describe the opcode, the CPU takes one cycle per bus access instead
+// extra_cycles: page crossed (taken branches take one more)
+#[allow(dead_code)]
 pub struct OPCODE {
     pub instruction: Instruction,
     pub mode: AddressingMode,
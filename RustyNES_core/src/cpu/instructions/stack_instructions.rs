@@ -3,7 +3,9 @@ impl super::CPU {
         self.push_8(self.a);
     }
 
+    // pulls read the stack once before incrementing the stack pointer
     pub fn pla(&mut self) {
+        self.dummy_read(0x100 | self.sp as u16);
         self.a = self.pull_8();
         self.update_zn_flags(self.a);
     }
@@ -14,6 +16,7 @@ impl super::CPU {
     }
 
     pub fn plp(&mut self) {
+        self.dummy_read(0x100 | self.sp as u16);
         let flags = self.pull_8() & 0xEF | 0x20;
         self.set_flags(flags);
     }
@@ -1,18 +1,31 @@
 use crate::{
     buffer::{self, Buffer},
-    rom::{create_cartridge, ROM},
+    mappers::MapperRegistry,
+    rom::ROM,
 };
 
+use self::debugger::Debugger;
+use self::disassembler::{disassemble, is_unofficial};
 use self::instructions::{OPCODE, OPCODES};
+use self::tracer::Tracer;
 use super::bus::BUS;
+pub mod debugger;
+pub mod disassembler;
 mod instructions;
-
-pub enum Interrupt {
-    NMI,
-    IRQ,
-    None,
+pub mod tracer;
+
+// things the front end should know about, taken with CPU::take_event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuEvent {
+    // a JAM (KIL) opcode at addr locked up the CPU,
+    // the PPU and APU keep running but no more instructions are executed
+    CpuHalted { addr: u16 },
 }
 
+// XAA and LAX #imm OR the accumulator with a constant that depends on the chip and its temperature,
+// 0xEE matches the 6502 single-step tests
+const MAGIC_CONSTANT: u8 = 0xEE;
+
 #[derive(Default)]
 pub struct CPU {
     a: u8,  // Accumulator
@@ -32,11 +45,31 @@ pub struct CPU {
     v: bool, // Overflow (bit 6)
     n: bool, // Negative (bit 7)
 
-    // buffer
-    cycles: u32,
-    stall: u32,
+    // timing
+    cycles: u64,       // CPU cycles since power on
+    master_clock: u64, // master clock at the end of the last CPU cycle (12 per cycle on NTSC)
+
+    // interrupt polling, the lines are sampled at the end of every cycle
+    // and an interrupt runs after the instruction if it was seen before its last cycle
+    nmi_line: bool, // level of the PPU's NMI output on the last cycle
+    need_nmi: bool, // NMI edge seen
+    prev_need_nmi: bool,
+    run_irq: bool, // IRQ line active and not masked
+    prev_run_irq: bool,
+
+    halted: bool,            // set by JAM
+    event: Option<CpuEvent>, // not taken by the front end yet
+    magic_constant: u8,      // used by XAA and LAX #imm
+
+    // logs every instruction, not part of save states
+    tracer: Option<Tracer>,
+
     // for communication with other components
     pub bus: BUS,
+    // breakpoints, watchpoints and stepping, not part of save states
+    pub debugger: Debugger,
+    // builds the cartridge again when a save state is loaded
+    pub mapper_registry: MapperRegistry,
 }
 
 impl CPU {
@@ -59,96 +92,262 @@ impl CPU {
             n: false,
 
             cycles: 0,
-            stall: 0,
+            master_clock: 0,
+
+            nmi_line: false,
+            need_nmi: false,
+            prev_need_nmi: false,
+            run_irq: false,
+            prev_run_irq: false,
+
+            halted: false,
+            event: None,
+            magic_constant: MAGIC_CONSTANT,
+
+            tracer: None,
 
             bus,
+            debugger: Debugger::default(),
+            mapper_registry: MapperRegistry::new(),
         };
 
         // inital state of cpu
-        cpu.sp = 0xFD;
-        cpu.pc = cpu.read_16(0xFFFC);
-        cpu.cycles = 7;
         cpu.i = true;
         cpu.u = true;
+        cpu.reset();
         cpu
     }
 
+    // the reset sequence is an interrupt that reads the stack instead of writing it (7 cycles)
+    fn reset(&mut self) {
+        self.dummy_read(self.pc);
+        self.dummy_read(self.pc);
+        for _ in 0..3 {
+            self.dummy_read(0x100 | self.sp as u16);
+            self.sp = self.sp.wrapping_sub(1);
+        }
+        self.i = true;
+        self.pc = self.read_16(0xFFFC);
+    }
+
+    // runs one instruction, the OAM DMA it started and the interrupt it was interrupted by,
+    // the PPU, APU and cartridge are run on every cycle
     pub fn step(&mut self) -> u32 {
         // record cycle before executing instruction
         let start_cycles = self.cycles;
 
-        // handle dma
-        self.handle_dma();
-
-        // handle early return if cpu is stalled
-        if self.stall > 0 {
-            self.stall -= 1;
-            return 1;
+        // a halted CPU keeps the address bus at 0xFFFF and ignores interrupts
+        if self.halted {
+            self.dummy_read(0xFFFF);
+            return (self.cycles - start_cycles) as u32;
         }
 
-        // handle interrupt
-        self.handle_interrupt();
+        if self.tracer.as_ref().is_some_and(|tracer| tracer.enabled()) {
+            let line = self.trace_line();
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.trace(&line);
+            }
+        }
 
         // fetch instruction
-        let opcode = self.bus.read(self.pc) as usize;
+        let opcode = self.read(self.pc) as usize;
+        self.pc = self.pc.wrapping_add(1);
 
         // decode instruction
-        let opcode = &OPCODES[opcode];
         let OPCODE {
-            instruction,
-            mode,
-            size,
-            cycles,
-            extra_cycles,
-        } = opcode;
-
-        // fetch address of operand and check if page crossed
-        // fetching from memory takes extra cycles if page boundary is crossed
-        let (address, page_crossed) = mode.fetch_operand_address(self);
+            instruction, mode, ..
+        } = &OPCODES[opcode];
 
-        // update pc to next instruction
-        self.pc += size;
+        // fetch address of operand, reading the operand bytes and doing the dummy reads of the mode
+        let address = mode.fetch_operand_address(self, instruction);
 
         // execute instruction and pass address of operand and addressing mode
         instruction.execute(self, address, mode);
 
-        // update cycles after executing instruction
-        if page_crossed {
-            self.cycles += cycles + extra_cycles;
-        } else {
-            self.cycles += cycles;
+        // writing 0x4014 halts the CPU for the OAM DMA
+        if let Some(page) = self.bus.oam_dma_page.take() {
+            self.oam_dma(page);
+        }
+
+        // handle interrupt seen before the last cycle of the instruction
+        if !self.halted && (self.prev_need_nmi || self.prev_run_irq) {
+            self.interrupt();
         }
 
         // return cycles taken to execute instruction
-        self.cycles - start_cycles
+        (self.cycles - start_cycles) as u32
     }
 
-    fn handle_dma(&mut self) {
-        if self.bus.ppu.dma_triggered() {
-            self.stall += 513 + (self.cycles & 1);
-        }
+    // the NSF player calls the INIT and PLAY routines of a tune,
+    // the routine returns (RTS) to return_addr
+    pub(crate) fn call(&mut self, addr: u16, return_addr: u16, a: u8, x: u8) {
+        self.push_16(return_addr.wrapping_sub(1));
+        self.pc = addr;
+        self.a = a;
+        self.x = x;
+        self.y = 0;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
     }
 
-    fn handle_interrupt(&mut self) {
-        if self.bus.ppu.nmi_triggered() {
-            self.interrupt(0xFFFA)
+    // the last event since it was taken, None if nothing happened
+    pub fn take_event(&mut self) -> Option<CpuEvent> {
+        self.event.take()
+    }
+
+    // None removes the tracer
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    // to turn tracing on and off or flush the output
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    // the instruction at pc and the state before it runs, in the nestest.log layout
+    pub fn trace_line(&self) -> String {
+        let pc = self.pc;
+        let (text, len) = disassemble(|addr| self.bus.peek(addr), pc);
+        let bytes = (0..len)
+            .map(|i| format!("{:02X}", self.bus.peek(pc.wrapping_add(i))))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let unofficial = if is_unofficial(self.bus.peek(pc)) {
+            '*'
         } else {
-            if !self.i {
-                let cartridge_irq = self.bus.ppu.cartridge.irq_triggered();
-                let apu_irq = self.bus.apu.irq_triggered();
-                if cartridge_irq || apu_irq {
-                    self.interrupt(0xFFFE);
-                }
-            }
+            ' '
+        };
+        // B is not a real flag and bit 5 always reads as set
+        let flags = (self.get_flags() & !0x10) | 0x20;
+        format!(
+            "{:04X}  {:<8} {}{:<31} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            pc,
+            bytes,
+            unofficial,
+            text,
+            self.a,
+            self.x,
+            self.y,
+            flags,
+            self.sp,
+            self.bus.ppu.line(),
+            self.bus.ppu.dot(),
+            self.cycles
+        )
+    }
+
+    // value ORed with the accumulator by XAA and LAX #imm,
+    // commonly 0xEE, 0xEF, 0xFF or 0x00 depending on the console
+    pub fn set_magic_constant(&mut self, magic_constant: u8) {
+        self.magic_constant = magic_constant;
+    }
+
+    // DMA (Direct Memory Access) transfers 256 bytes from CPU memory to OAM memory,
+    // OAM memory is used to store the sprite attributes
+    // takes 513 cycles (514 if it starts on an odd cycle): a halt cycle, an alignment cycle,
+    // then a read and a write to 0x2004 for each byte
+    fn oam_dma(&mut self, page: u8) {
+        self.dummy_read(self.pc);
+        if self.cycles & 1 == 1 {
+            self.dummy_read(self.pc);
+        }
+        let hi = (page as u16) << 8;
+        for lo in 0..256 {
+            let data = self.read(hi | lo);
+            self.write(0x2004, data);
         }
     }
 
-    fn interrupt(&mut self, vector: u16) {
+    // NMI or IRQ, an NMI that comes in before the vector is fetched takes over an IRQ
+    fn interrupt(&mut self) {
+        self.dummy_read(self.pc);
+        self.dummy_read(self.pc);
         self.push_16(self.pc); // push program counter
-        self.php(); // push status register
+        self.push_8(self.get_flags() & !0x10); // push status register with B clear
         self.i = true; // set interrupt disable flag to true
+        let vector = self.interrupt_vector();
         self.pc = self.read_16(vector); // set program counter to interrupt vector
-        self.cycles += 7;
+        self.debugger.interrupt_entered(vector == 0xFFFA);
+    }
+
+    // used by interrupts and BRK
+    fn interrupt_vector(&mut self) -> u16 {
+        if self.need_nmi {
+            self.need_nmi = false;
+            0xFFFA
+        } else {
+            0xFFFE
+        }
+    }
+}
+
+// Cycles /////////////////////
+impl CPU {
+    // the PPU runs during the whole CPU cycle, reads happen a bit before the middle of the cycle
+    // and writes a bit after it (in master clocks: 5 + 7 on NTSC reads, 7 + 5 on writes)
+    fn start_cycle(&mut self, read: bool) {
+        let region = self.bus.ppu.region();
+        let half = region.master_clocks_per_cpu_cycle() / 2;
+        self.master_clock += if read { half - 1 } else { half + 1 };
+        self.run_ppu(self.master_clock - 1);
+    }
+
+    fn end_cycle(&mut self, read: bool) {
+        let region = self.bus.ppu.region();
+        let clocks = region.master_clocks_per_cpu_cycle();
+        let half = clocks / 2;
+        self.master_clock += if read {
+            clocks - half + 1
+        } else {
+            clocks - half - 1
+        };
+        self.run_ppu(self.master_clock - 1);
+        self.cycles += 1;
+
+        self.step_apu();
+        self.poll_interrupts();
+    }
+
+    // runs the APU and the cartridge for one CPU cycle
+    fn step_apu(&mut self) {
+        // todo: only read data when needed
+        // peek the cartridge, the sample fetch must not change the open bus value
+        // or trigger the side effects of a read
+        let addr = self.bus.apu.dmc.current_address;
+        let dmc_data = self.bus.ppu.cartridge.peek(addr);
+        let expansion_audio = self.bus.ppu.cartridge.audio_output();
+        self.bus.apu.step(dmc_data, expansion_audio);
+        self.bus.ppu.cartridge.cpu_clock();
+    }
+
+    // NMI is edge triggered, IRQ is level triggered and masked by the I flag
+    fn poll_interrupts(&mut self) {
+        self.prev_need_nmi = self.need_nmi;
+        let nmi_line = self.bus.ppu.nmi_line();
+        if nmi_line && !self.nmi_line {
+            self.need_nmi = true;
+        }
+        self.nmi_line = nmi_line;
+
+        self.prev_run_irq = self.run_irq;
+        self.run_irq =
+            !self.i && (self.bus.ppu.cartridge.irq_triggered() || self.bus.apu.irq_triggered());
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // the PPU's master clock is restarted with it when the region changes
+    pub(crate) fn set_master_clock(&mut self, master_clock: u64) {
+        self.master_clock = master_clock;
     }
 }
 
@@ -170,8 +369,17 @@ impl CPU {
         buffer.write_bool(self.v);
         buffer.write_bool(self.n);
 
-        buffer.write_u32(self.cycles);
-        buffer.write_u32(self.stall);
+        buffer.write_u64(self.cycles);
+        buffer.write_u64(self.master_clock);
+
+        buffer.write_bool(self.nmi_line);
+        buffer.write_bool(self.need_nmi);
+        buffer.write_bool(self.prev_need_nmi);
+        buffer.write_bool(self.run_irq);
+        buffer.write_bool(self.prev_run_irq);
+
+        buffer.write_bool(self.halted);
+        buffer.write_u8(self.magic_constant);
     }
 
     fn decode_cpu(&mut self, buffer: &mut buffer::Buffer) {
@@ -190,8 +398,17 @@ impl CPU {
         self.v = buffer.read_bool();
         self.n = buffer.read_bool();
 
-        self.cycles = buffer.read_u32();
-        self.stall = buffer.read_u32();
+        self.cycles = buffer.read_u64();
+        self.master_clock = buffer.read_u64();
+
+        self.nmi_line = buffer.read_bool();
+        self.need_nmi = buffer.read_bool();
+        self.prev_need_nmi = buffer.read_bool();
+        self.run_irq = buffer.read_bool();
+        self.prev_run_irq = buffer.read_bool();
+
+        self.halted = buffer.read_bool();
+        self.magic_constant = buffer.read_u8();
     }
 
     pub fn encode(&mut self, buffer: &mut buffer::Buffer) {
@@ -206,9 +423,13 @@ impl CPU {
 
     pub fn decode(&mut self, buffer: &mut buffer::Buffer) {
         let rom = ROM::decode(buffer);
-        self.bus.ppu.cartridge = create_cartridge(rom.mapper_id, rom);
+        self.bus.ppu.cartridge = self
+            .mapper_registry
+            .create(rom)
+            .unwrap_or_else(|err| panic!("{}", err));
         self.bus.ppu.cartridge.decode(buffer);
         self.bus.ppu.decode(buffer);
+        self.bus.apu.set_region(self.bus.ppu.region());
         self.bus.controller.decode(buffer);
         self.bus.decode(buffer);
         self.decode_cpu(buffer);
@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+
+// writes a line in the nestest.log layout before every instruction the CPU runs:
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+// PC, instruction bytes, disassembly (unofficial opcodes start with *), registers,
+// PPU line and dot, and the CPU cycles since power on
+pub struct Tracer {
+    output: Box<dyn Write + Send>,
+    enabled: bool,
+}
+
+impl Tracer {
+    // starts enabled, wrap files in a BufWriter
+    pub fn new(output: impl Write + Send + 'static) -> Tracer {
+        Tracer {
+            output: Box::new(output),
+            enabled: true,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    // tracing is turned off if the output can not be written to
+    pub(crate) fn trace(&mut self, line: &str) {
+        if writeln!(self.output, "{}", line).is_err() {
+            self.enabled = false;
+        }
+    }
+}
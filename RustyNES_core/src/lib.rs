@@ -1,56 +1,179 @@
 #![allow(clippy::upper_case_acronyms)]
 pub mod apu;
+pub mod archive;
 pub mod buffer;
 pub mod bus;
 pub mod controller;
 pub mod cpu;
+pub mod hash;
 pub mod mappers;
+pub mod nsf;
 pub mod ppu;
+pub mod region;
 pub mod rom;
 
 pub use apu::BUFFER_SIZE;
 pub use apu::SAMPLE_RATE;
 use bus::BUS;
 use controller::Controller;
+pub use cpu::debugger::StopReason;
+pub use cpu::CpuEvent;
 pub use cpu::CPU;
+use mappers::MapperRegistry;
 use ppu::PPU;
+pub use region::Region;
+use rom::patch::PatchError;
+use rom::RomError;
 use rom::ROM;
 
 impl CPU {
+    // panics if the rom can not be loaded, use try_new_from_rom_bytes to handle the error
     pub fn new_from_rom_bytes(bytes: Vec<u8>) -> CPU {
-        let cartridge = ROM::new_cartridge(bytes);
+        Self::try_new_from_rom_bytes(bytes).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new_from_rom_bytes(bytes: Vec<u8>) -> Result<CPU, RomError> {
+        Self::try_new_with_registry(bytes, MapperRegistry::new())
+    }
+
+    // the cartridge is built from a custom set of mappers,
+    // which is also used to load save states
+    pub fn try_new_with_registry(
+        bytes: Vec<u8>,
+        mapper_registry: MapperRegistry,
+    ) -> Result<CPU, RomError> {
+        CPU::try_new_from_rom(ROM::new(bytes)?, mapper_registry)
+    }
+
+    // Famicom Disk System: disk image (.fds) and the FDS BIOS (disksys.rom, 8KB)
+    pub fn try_new_fds(image: &[u8], bios: Vec<u8>) -> Result<CPU, RomError> {
+        CPU::try_new_fds_with_registry(image, bios, MapperRegistry::new())
+    }
+
+    pub fn try_new_fds_with_registry(
+        image: &[u8],
+        bios: Vec<u8>,
+        mapper_registry: MapperRegistry,
+    ) -> Result<CPU, RomError> {
+        CPU::try_new_from_rom(ROM::new_fds(image, bios)?, mapper_registry)
+    }
+
+    fn try_new_from_rom(rom: ROM, mapper_registry: MapperRegistry) -> Result<CPU, RomError> {
+        let cartridge = mapper_registry.create(rom)?;
         let ppu = PPU::new_ppu(cartridge);
         let controller = Controller::new_controller();
         let bus = BUS::new_bus(ppu, controller);
-        CPU::new_cpu(bus)
+        let mut cpu = CPU::new_cpu(bus);
+        cpu.mapper_registry = mapper_registry;
+        cpu.set_region(None);
+        Ok(cpu)
     }
 
     pub fn new_nes_from_save_bytes(bytes: Vec<u8>) -> CPU {
+        Self::new_nes_from_save_bytes_with_registry(bytes, MapperRegistry::new())
+    }
+
+    pub fn new_nes_from_save_bytes_with_registry(
+        bytes: Vec<u8>,
+        mapper_registry: MapperRegistry,
+    ) -> CPU {
         let mut cpu = CPU::default();
+        cpu.mapper_registry = mapper_registry;
         let buffer = &mut buffer::Buffer::new_buffer();
         buffer.data = bytes;
         cpu.decode(buffer);
         cpu
     }
 
+    // title from the game database, None if the rom is not in it
+    pub fn title(&self) -> Option<&'static str> {
+        self.bus.ppu.cartridge.data().title()
+    }
+
+    // battery backed save RAM, None if the cartridge has no battery
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        let cartridge = &self.bus.ppu.cartridge;
+        let ram = cartridge.battery_ram();
+        if cartridge.data().battery && !ram.is_empty() {
+            Some(ram)
+        } else {
+            None
+        }
+    }
+
+    // restores save RAM returned by battery_ram,
+    // ignored if the cartridge has no battery, extra bytes are dropped
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        let cartridge = &mut self.bus.ppu.cartridge;
+        if !cartridge.data().battery {
+            return;
+        }
+        let ram = cartridge.battery_ram_mut();
+        let len = ram.len().min(data.len());
+        ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    // number of disk sides, 0 if the game is not a Famicom Disk System disk
+    pub fn disk_side_count(&self) -> usize {
+        self.bus.ppu.cartridge.disk_side_count()
+    }
+
+    pub fn disk_side(&self) -> Option<usize> {
+        self.bus.ppu.cartridge.disk_side()
+    }
+
+    // inserts a disk side (starting at 0) or ejects the disk with None
+    pub fn insert_disk_side(&mut self, side: Option<usize>) {
+        self.bus.ppu.cartridge.insert_disk_side(side)
+    }
+
+    // the data the game wrote to the disk, as an IPS patch of the original disk image
+    // None if the game is not on a disk or nothing was written
+    pub fn disk_diff(&self) -> Option<Vec<u8>> {
+        let cartridge = &self.bus.ppu.cartridge;
+        if cartridge.disk_side_count() == 0 {
+            return None;
+        }
+        let original = cartridge.data().disk_sides.concat();
+        let image = cartridge.disk_image();
+        if image == original {
+            return None;
+        }
+        Some(rom::patch::create_ips(&original, &image))
+    }
+
+    // restores the disk writes returned by disk_diff
+    pub fn load_disk_diff(&mut self, diff: &[u8]) -> Result<(), PatchError> {
+        let cartridge = &mut self.bus.ppu.cartridge;
+        if cartridge.disk_side_count() == 0 {
+            return Ok(());
+        }
+        let image = rom::patch::apply_ips(&cartridge.data().disk_sides.concat(), diff)?;
+        cartridge.load_disk_image(&image);
+        Ok(())
+    }
+
+    pub fn region(&self) -> Region {
+        self.bus.ppu.region()
+    }
+
+    // None picks the region of the ROM (NES 2.0 header or game database)
+    pub fn set_region(&mut self, region: Option<Region>) {
+        let region =
+            region.unwrap_or_else(|| Region::from_timing(self.bus.ppu.cartridge.data().timing));
+        self.set_master_clock(0);
+        self.bus.ppu.set_region(region);
+        self.bus.apu.set_region(region);
+    }
+
     pub fn update_button(&mut self, index: u8, pressed: bool) {
         self.bus.controller.update_button(index, pressed)
     }
 
-    pub fn step_till_next_frame(&mut self) {
-        while !self.bus.ppu.frame_complete() {
-            let cpu_cycles = self.step();
-            let ppu_cycles = cpu_cycles * 3; // 1 CPU cycle = 3 PPU cycles
-            for _ in 0..ppu_cycles {
-                self.bus.ppu.step();
-            }
-            for _ in 0..cpu_cycles {
-                // todo: only read data when needed
-                let addr = self.bus.apu.dmc.current_address;
-                let dmc_data = self.bus.read(addr);
-                self.bus.apu.step(dmc_data);
-            }
-        }
+    // returns early when the debugger stops the CPU, running it again resumes the frame
+    pub fn step_till_next_frame(&mut self) -> StopReason {
+        // the PPU and APU are run by the CPU on every cycle
+        self.run_until_stop()
     }
 
     pub fn frame_buffer_ref(&self) -> &[u8] {
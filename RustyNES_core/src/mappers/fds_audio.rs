@@ -0,0 +1,320 @@
+use crate::buffer::Buffer;
+
+// Famicom Disk System expansion audio
+// one 64 step wavetable channel with a volume envelope,
+// its pitch is bent by a modulation unit with its own table and envelope
+// 0x4040-0x407F: wavetable RAM, 0x4080-0x408A: registers, 0x4090/0x4092: envelope gains
+#[derive(Clone, Debug)]
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    wave_write_enabled: bool,  // 0x4089 bit 7, also holds the output
+    master_volume: u8,         // 0x4089 bits 0-1
+    master_envelope_speed: u8, // 0x408A
+    wave_halted: bool,         // 0x4083 bit 7
+    envelopes_halted: bool,    // 0x4083 bit 6
+
+    volume: Envelope,
+    wave_accumulator: u16,
+    wave_position: u8,
+
+    modulator: Envelope,
+    mod_table: [u8; 64],
+    mod_position: u8,
+    mod_halted: bool, // 0x4087 bit 7
+    mod_accumulator: u16,
+    mod_counter: i8, // 7 bit signed
+    mod_pitch: i32,  // pitch offset applied to the wave frequency
+
+    output_level: u8,
+}
+
+// envelope and frequency shared by the volume (0x4080, 0x4082-0x4083)
+// and modulation (0x4084, 0x4086-0x4087) units
+#[derive(Clone, Debug, Default)]
+struct Envelope {
+    speed: u8,
+    increase: bool,
+    disabled: bool,
+    gain: u8,
+    timer: u32,
+    frequency: u16, // 12 bits
+}
+
+impl Envelope {
+    fn write_control(&mut self, val: u8, master_speed: u8) {
+        self.speed = val & 0x3F;
+        self.increase = val & 0x40 != 0;
+        self.disabled = val & 0x80 != 0;
+        // with the envelope disabled the speed bits are the gain
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn write_frequency_low(&mut self, val: u8) {
+        self.frequency = (self.frequency & 0x0F00) | val as u16;
+    }
+
+    fn write_frequency_high(&mut self, val: u8) {
+        self.frequency = (self.frequency & 0x00FF) | ((val & 0x0F) as u16) << 8;
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    // the gain moves by one step every 8 * (speed + 1) * master speed CPU cycles,
+    // it only goes up to 32 (but can be set up to 63 directly)
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.reset_timer(master_speed);
+            if self.increase && self.gain < 32 {
+                self.gain += 1;
+            } else if !self.increase && self.gain > 0 {
+                self.gain -= 1;
+            }
+        }
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.speed);
+        buffer.write_bool(self.increase);
+        buffer.write_bool(self.disabled);
+        buffer.write_u8(self.gain);
+        buffer.write_u32(self.timer);
+        buffer.write_u16(self.frequency);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.speed = buffer.read_u8();
+        self.increase = buffer.read_bool();
+        self.disabled = buffer.read_bool();
+        self.gain = buffer.read_u8();
+        self.timer = buffer.read_u32();
+        self.frequency = buffer.read_u16();
+    }
+}
+
+// change of the mod counter for each mod table value, None resets it to 0
+const MOD_STEPS: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+
+// wave level for master volume 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write_enabled: false,
+            master_volume: 0,
+            master_envelope_speed: 0xE8,
+            wave_halted: true,
+            envelopes_halted: false,
+            volume: Envelope::default(),
+            wave_accumulator: 0,
+            wave_position: 0,
+            modulator: Envelope::default(),
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_halted: true,
+            mod_accumulator: 0,
+            mod_counter: 0,
+            mod_pitch: 0,
+            output_level: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave_table[(addr - 0x4040) as usize],
+            0x4090 => self.volume.gain,
+            0x4092 => self.modulator.gain,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // the wavetable can only be written while the output is held
+            0x4040..=0x407F if self.wave_write_enabled => {
+                self.wave_table[(addr - 0x4040) as usize] = val & 0x3F;
+            }
+            0x4080 => self.volume.write_control(val, self.master_envelope_speed),
+            0x4082 => self.volume.write_frequency_low(val),
+            0x4083 => {
+                self.volume.write_frequency_high(val);
+                self.wave_halted = val & 0x80 != 0;
+                self.envelopes_halted = val & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_position = 0;
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_halted {
+                    self.volume.reset_timer(self.master_envelope_speed);
+                    self.modulator.reset_timer(self.master_envelope_speed);
+                }
+            }
+            0x4084 => self
+                .modulator
+                .write_control(val, self.master_envelope_speed),
+            0x4085 => self.set_mod_counter(val & 0x7F),
+            0x4086 => self.modulator.write_frequency_low(val),
+            0x4087 => {
+                self.modulator.write_frequency_high(val);
+                self.mod_halted = val & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // while the modulator is halted, each write fills two entries of the mod table
+            0x4088 if self.mod_halted => {
+                for _ in 0..2 {
+                    self.mod_table[self.mod_position as usize] = val & 0x07;
+                    self.mod_position = (self.mod_position + 1) & 0x3F;
+                }
+            }
+            0x4089 => {
+                self.wave_write_enabled = val & 0x80 != 0;
+                self.master_volume = val & 0b11;
+            }
+            0x408A => self.master_envelope_speed = val,
+            _ => {}
+        }
+    }
+
+    // 7 bit signed counter, wraps around at -64/63
+    fn set_mod_counter(&mut self, val: u8) {
+        self.mod_counter = ((val << 1) as i8) >> 1;
+    }
+
+    // called once per CPU cycle
+    pub fn clock(&mut self) {
+        if !self.wave_halted && !self.envelopes_halted {
+            self.volume.clock(self.master_envelope_speed);
+            self.modulator.clock(self.master_envelope_speed);
+        }
+
+        // modulator, steps through the mod table when its 16 bit accumulator overflows
+        let mod_frequency = self.modulator.frequency;
+        if !self.mod_halted && mod_frequency > 0 {
+            let (accumulator, overflow) = self.mod_accumulator.overflowing_add(mod_frequency);
+            self.mod_accumulator = accumulator;
+            if overflow {
+                match MOD_STEPS[self.mod_table[self.mod_position as usize] as usize] {
+                    Some(step) => {
+                        self.set_mod_counter(self.mod_counter.wrapping_add(step) as u8 & 0x7F)
+                    }
+                    None => self.mod_counter = 0,
+                }
+                self.mod_position = (self.mod_position + 1) & 0x3F;
+                self.update_mod_pitch();
+            }
+        }
+
+        // wave, steps through the wavetable when its 16 bit accumulator overflows
+        self.update_output();
+        if self.wave_halted || self.wave_write_enabled {
+            return;
+        }
+        let frequency = self.volume.frequency as i32 + self.mod_pitch;
+        if frequency > 0 {
+            let (accumulator, overflow) = self.wave_accumulator.overflowing_add(frequency as u16);
+            self.wave_accumulator = accumulator;
+            if overflow {
+                self.wave_position = (self.wave_position + 1) & 0x3F;
+            }
+        }
+    }
+
+    // pitch offset from the mod counter and mod gain (formula from the nesdev wiki)
+    fn update_mod_pitch(&mut self) {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulator.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= self.volume.frequency as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_pitch = temp;
+    }
+
+    fn update_output(&mut self) {
+        // the output holds its last value while the wavetable is written
+        if self.wave_write_enabled {
+            return;
+        }
+        let level = self.volume.gain.min(32) as u32 * MASTER_VOLUMES[self.master_volume as usize];
+        let sample = self.wave_table[self.wave_position as usize] as u32;
+        self.output_level = (sample * level / 1152) as u8;
+    }
+
+    pub fn output(&self) -> f32 {
+        // 0-63, full volume is roughly 2.4 times as loud as an APU square channel
+        self.output_level as f32 / 63.0 * 0.36
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.wave_table);
+        buffer.write_bool(self.wave_write_enabled);
+        buffer.write_u8(self.master_volume);
+        buffer.write_u8(self.master_envelope_speed);
+        buffer.write_bool(self.wave_halted);
+        buffer.write_bool(self.envelopes_halted);
+        self.volume.encode(buffer);
+        buffer.write_u16(self.wave_accumulator);
+        buffer.write_u8(self.wave_position);
+        self.modulator.encode(buffer);
+        buffer.write_u8_arr(&self.mod_table);
+        buffer.write_u8(self.mod_position);
+        buffer.write_bool(self.mod_halted);
+        buffer.write_u16(self.mod_accumulator);
+        buffer.write_u8(self.mod_counter as u8);
+        buffer.write_u32(self.mod_pitch as u32);
+        buffer.write_u8(self.output_level);
+    }
+
+    pub fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.wave_table);
+        self.wave_write_enabled = buffer.read_bool();
+        self.master_volume = buffer.read_u8();
+        self.master_envelope_speed = buffer.read_u8();
+        self.wave_halted = buffer.read_bool();
+        self.envelopes_halted = buffer.read_bool();
+        self.volume.decode(buffer);
+        self.wave_accumulator = buffer.read_u16();
+        self.wave_position = buffer.read_u8();
+        self.modulator.decode(buffer);
+        buffer.read_u8_arr(&mut self.mod_table);
+        self.mod_position = buffer.read_u8();
+        self.mod_halted = buffer.read_bool();
+        self.mod_accumulator = buffer.read_u16();
+        self.mod_counter = buffer.read_u8() as i8;
+        self.mod_pitch = buffer.read_u32() as i32;
+        self.output_level = buffer.read_u8();
+    }
+}
@@ -1,64 +1,84 @@
-use super::Mapper;
+use super::{copy_trainer, Mapper};
 use crate::{buffer, rom::ROM};
 
 #[derive(Clone, Debug)]
 pub struct Mapper0 {
-    prg_ram: [u8; 0x800],
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
     rom: ROM,
 }
 
 impl Default for Mapper0 {
     fn default() -> Self {
-        Self {
-            prg_ram: [0; 0x800],
-            rom: ROM::default(),
-        }
+        Self::new(ROM::default())
     }
 }
 
 impl Mapper0 {
     pub fn new(rom: ROM) -> Self {
         Self {
-            prg_ram: [0; 0x800],
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
             rom,
         }
     }
+
+    // 16KB PRG ROM is mirrored at 0xC000-0xFFFF
+    fn prg_offset(&self, addr: u16) -> usize {
+        let mut offset = addr as usize - 0x8000;
+        if self.rom.prg_rom_banks == 1 {
+            offset &= 0x3FFF;
+        }
+        offset
+    }
 }
 
 impl Mapper for Mapper0 {
-    fn read(&mut self, addr: u16) -> u8 {
+    fn peek(&self, addr: u16) -> u8 {
         let addr = addr as usize;
         match addr {
-            // CHR ROM
-            0x0000..=0x1FFF => {
-                let offset = self.rom.chr_rom_start + addr;
-                self.rom.bytes[offset]
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                self.chr_ram[addr % self.chr_ram.len()]
             }
 
-            // PRG RAM
-            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) & 0x7FF],
+            // CHR ROM
+            0x0000..=0x1FFF => self.rom.chr_rom[addr],
 
-            // PRG ROM
-            0x8000..=0xFFFF => {
-                let offset = {
-                    let mut addr = addr - 0x8000;
-                    if self.rom.prg_rom_banks == 1 {
-                        addr &= 0x3FFF;
-                    }
-                    self.rom.prg_rom_start + addr
-                };
-                self.rom.bytes[offset]
+            // PRG RAM (mirrored if smaller than 8KB)
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) % self.prg_ram.len()]
             }
 
-            _ => unreachable!("Invalid read address for NROM: {:#X}", addr),
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr as u16)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
         }
     }
 
     fn write(&mut self, addr: u16, val: u8) {
         let addr = addr as usize;
         match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr % len] = val
+            }
+
             // PRG RAM
-            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) & 0x7FF] = val,
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) % len] = val
+            }
+
             _ => (),
         }
     }
@@ -67,11 +87,25 @@ impl Mapper for Mapper0 {
         &self.rom
     }
 
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
     fn encode(&self, buffer: &mut buffer::Buffer) {
         buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
     }
 
     fn decode(&mut self, buffer: &mut buffer::Buffer) {
         buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
     }
 }
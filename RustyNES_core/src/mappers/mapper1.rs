@@ -0,0 +1,276 @@
+use super::{copy_trainer, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// MMC1 (SxROM boards)
+// registers are loaded serially through a 5-bit shift register, one bit per write
+#[derive(Clone, Debug)]
+pub struct Mapper1 {
+    shift_register: u8,
+    shift_count: u8,
+
+    // internal registers
+    control: u8, // mirroring, PRG mode, CHR mode
+    chr_bank0: u8,
+    chr_bank1: u8,
+    prg_bank: u8, // PRG bank and PRG RAM disable
+
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    prg_offsets: [u32; 2], // 16KB windows at 0x8000 and 0xC000
+    chr_offsets: [u32; 2], // 4KB windows at 0x0000 and 0x1000
+    prg_ram_offset: u32,
+
+    // writes on consecutive cpu cycles (read-modify-write instructions)
+    // are ignored by the serial port, only the first one is used
+    cycles_since_write: u8,
+    rom: ROM,
+}
+
+impl Mapper1 {
+    pub fn new(rom: ROM) -> Mapper1 {
+        let mut mapper = Mapper1 {
+            shift_register: 0,
+            shift_count: 0,
+            // power on in PRG mode 3 (last bank fixed at 0xC000)
+            control: 0x0C,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            prg_offsets: [0; 2],
+            chr_offsets: [0; 2],
+            prg_ram_offset: 0,
+            cycles_since_write: u8::MAX,
+            rom,
+        };
+        mapper.update_offsets();
+        mapper
+    }
+
+    fn write_serial(&mut self, addr: u16, val: u8) {
+        // writing a value with bit 7 set resets the shift register
+        // and locks PRG ROM at 0xC000 to the last bank
+        if val & 0x80 != 0 {
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+            self.update_offsets();
+            return;
+        }
+
+        // bits are shifted in from bit 0 of the written value, lsb first
+        self.shift_register |= (val & 1) << self.shift_count;
+        self.shift_count += 1;
+
+        // on the 5th write, bits 13-14 of the address select the register
+        if self.shift_count == 5 {
+            let data = self.shift_register;
+            match addr {
+                0x8000..=0x9FFF => self.control = data,
+                0xA000..=0xBFFF => self.chr_bank0 = data,
+                0xC000..=0xDFFF => self.chr_bank1 = data,
+                _ => self.prg_bank = data,
+            }
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.update_offsets();
+        }
+    }
+
+    fn update_offsets(&mut self) {
+        self.rom.mirroring = match self.control & 0b11 {
+            0 => Mirroring::OneScreenLower,
+            1 => Mirroring::OneScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        };
+
+        // CHR banks are in 4KB units
+        let _4kb = 0x1000;
+        let chr_bank_count = if self.rom.chr_rom_banks == 0 {
+            (self.chr_ram.len() / 0x1000).max(1) as u32
+        } else {
+            self.rom.chr_rom_banks as u32 * 2
+        };
+        let (chr0, chr1) = if self.control & 0x10 == 0 {
+            // 8KB mode, low bit of bank is ignored
+            let bank = (self.chr_bank0 & 0x1E) as u32;
+            (bank, bank + 1)
+        } else {
+            // two separate 4KB banks
+            (self.chr_bank0 as u32, self.chr_bank1 as u32)
+        };
+        self.chr_offsets[0] = (chr0 % chr_bank_count) * _4kb;
+        self.chr_offsets[1] = (chr1 % chr_bank_count) * _4kb;
+
+        // 512KB boards (SUROM) use bit 4 of the CHR bank to select the 256KB PRG half
+        let prg_outer = if self.rom.prg_rom_banks > 16 {
+            (self.chr_bank0 & 0x10) as u32
+        } else {
+            0
+        };
+
+        // PRG banks are in 16KB units
+        let _16kb = 0x4000;
+        let prg_bank_count = self.rom.prg_rom_banks as u32;
+        let bank = (self.prg_bank & 0x0F) as u32;
+        let (prg0, prg1) = match (self.control >> 2) & 0b11 {
+            // switch 32KB at 0x8000, low bit of bank is ignored
+            0 | 1 => (prg_outer | (bank & 0x0E), prg_outer | (bank & 0x0E) | 1),
+            // fix first bank at 0x8000, switch 16KB bank at 0xC000
+            2 => (prg_outer, prg_outer | bank),
+            // fix last bank at 0xC000, switch 16KB bank at 0x8000
+            _ => (prg_outer | bank, prg_outer | 0x0F),
+        };
+        self.prg_offsets[0] = (prg0 % prg_bank_count) * _16kb;
+        self.prg_offsets[1] = (prg1 % prg_bank_count) * _16kb;
+
+        // boards with more than 8KB of PRG RAM (SOROM, SXROM)
+        // use bits 2-3 of the CHR bank to select the 8KB RAM bank
+        let prg_ram_banks = (self.prg_ram.len() / 0x2000).max(1) as u32;
+        let ram_bank = ((self.chr_bank0 >> 2) & 0b11) as u32;
+        self.prg_ram_offset = (ram_bank % prg_ram_banks) * 0x2000;
+    }
+
+    // bit 4 of the PRG bank register disables PRG RAM
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && self.prg_bank & 0x10 == 0
+    }
+
+    fn prg_ram_index(&self, addr: u16) -> usize {
+        let offset = self.prg_ram_offset as usize + (addr - 0x6000) as usize;
+        offset % self.prg_ram.len()
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let index = ((addr - 0x8000) / 0x4000) as usize;
+        self.prg_offsets[index] as usize + (addr & 0x3FFF) as usize
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn cpu_clock(&mut self) {
+        self.cycles_since_write = self.cycles_since_write.saturating_add(1);
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM
+            0x0000..=0x1FFF => {
+                let index = (addr / 0x1000) as usize;
+                let offset = self.chr_offsets[index] as usize + (addr & 0xFFF) as usize;
+                if self.rom.chr_rom_banks == 0 {
+                    self.chr_ram[offset % self.chr_ram.len()]
+                } else {
+                    self.rom.chr_rom[offset]
+                }
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[self.prg_ram_index(addr)],
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let index = (addr / 0x1000) as usize;
+                let offset = self.chr_offsets[index] as usize + (addr & 0xFFF) as usize;
+                let len = self.chr_ram.len();
+                self.chr_ram[offset % len] = val;
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let index = self.prg_ram_index(addr);
+                self.prg_ram[index] = val;
+            }
+
+            // Serial port
+            0x8000..=0xFFFF => {
+                let consecutive = self.cycles_since_write < 2;
+                self.cycles_since_write = 0;
+                if !consecutive {
+                    self.write_serial(addr, val);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram_enabled(),
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        if self.prg_ram.len() < 0x2000 {
+            return false;
+        }
+        let index = self.prg_ram_index(0x7000);
+        copy_trainer(&mut self.prg_ram, index, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.shift_register);
+        buffer.write_u8(self.shift_count);
+        buffer.write_u8(self.control);
+        buffer.write_u8(self.chr_bank0);
+        buffer.write_u8(self.chr_bank1);
+        buffer.write_u8(self.prg_bank);
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u32_arr(&self.prg_offsets);
+        buffer.write_u32_arr(&self.chr_offsets);
+        buffer.write_u32(self.prg_ram_offset);
+        buffer.write_u8(self.cycles_since_write);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.shift_register = buffer.read_u8();
+        self.shift_count = buffer.read_u8();
+        self.control = buffer.read_u8();
+        self.chr_bank0 = buffer.read_u8();
+        self.chr_bank1 = buffer.read_u8();
+        self.prg_bank = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        buffer.read_u32_arr(&mut self.prg_offsets);
+        buffer.read_u32_arr(&mut self.chr_offsets);
+        self.prg_ram_offset = buffer.read_u32();
+        self.cycles_since_write = buffer.read_u8();
+    }
+}
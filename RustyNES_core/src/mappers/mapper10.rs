@@ -0,0 +1,139 @@
+use super::{copy_trainer, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// MMC4 (FxROM, Fire Emblem)
+// like MMC2 but with 16KB PRG ROM banking and 8KB PRG RAM
+// 16KB switchable PRG ROM bank at 0x8000, last bank fixed at 0xC000
+// two 4KB CHR ROM windows, each with two banks selected by a latch
+// the latches flip when the PPU fetches tile 0xFD or 0xFE from the pattern tables
+#[derive(Clone, Debug)]
+pub struct Mapper10 {
+    prg_ram: Vec<u8>,
+    prg_bank: u8,
+    // CHR banks: [window][latch], latch 0 = 0xFD, latch 1 = 0xFE
+    chr_banks: [[u8; 2]; 2],
+    latches: [u8; 2],
+    rom: ROM,
+}
+
+impl Mapper10 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper10 {
+            prg_ram: vec![0; rom.prg_ram_len()],
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [1, 1],
+            rom,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize;
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize % bank_count,
+            _ => bank_count - 1,
+        };
+        bank * 0x4000 + (addr & 0x3FFF) as usize
+    }
+}
+
+impl Mapper for Mapper10 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM
+            0x0000..=0x1FFF => {
+                let window = (addr / 0x1000) as usize;
+                let bank = self.chr_banks[window][self.latches[window] as usize] as usize;
+                let bank_count = self.rom.chr_rom_banks as usize * 2;
+                let offset = (bank % bank_count) * 0x1000 + (addr & 0xFFF) as usize;
+                self.rom.chr_rom[offset]
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+            }
+            0xA000..=0xAFFF => self.prg_bank = val & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = val & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = val & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = val & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = val & 0x1F,
+            0xF000..=0xFFFF => {
+                self.rom.mirroring = if val & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    // the latch changes after the fetch, so the tile 0xFD/0xFE itself uses the old bank
+    // unlike MMC2, both windows react to the whole 8 byte range of the tile
+    fn chr_fetched(&mut self, addr: u16) {
+        match addr {
+            0x0FD8..=0x0FDF => self.latches[0] = 0,
+            0x0FE8..=0x0FEF => self.latches[0] = 1,
+            0x1FD8..=0x1FDF => self.latches[1] = 0,
+            0x1FE8..=0x1FEF => self.latches[1] = 1,
+            _ => {}
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8(self.prg_bank);
+        buffer.write_u8_arr(&self.chr_banks[0]);
+        buffer.write_u8_arr(&self.chr_banks[1]);
+        buffer.write_u8_arr(&self.latches);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.prg_ram);
+        self.prg_bank = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.chr_banks[0]);
+        buffer.read_u8_arr(&mut self.chr_banks[1]);
+        buffer.read_u8_arr(&mut self.latches);
+    }
+}
@@ -0,0 +1,265 @@
+use super::{copy_trainer, namco163_audio::Namco163Audio, Mapper};
+use crate::{buffer::Buffer, rom::ROM};
+
+// Namco 163 (mapper 19)
+// - 8KB PRG banks, 1KB CHR banks
+// - each nametable can be mapped to CIRAM or to a 1KB CHR ROM bank
+// - up to 8 wavetable expansion audio channels (see Namco163Audio)
+// - 15-bit IRQ counter incremented every CPU cycle
+// pattern table banks mapped to CIRAM (0xE0-0xFF with 0xE800 bits 6/7 clear)
+// are read from CHR ROM instead
+#[derive(Clone, Debug)]
+pub struct Mapper19 {
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    prg_banks: [u8; 3], // 0x8000, 0xA000, 0xC000
+    sound_disabled: bool,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    write_protect: u8, // 0xF800
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_triggered: bool,
+
+    // the audio RAM doubles as general purpose RAM (0x4800)
+    audio: Namco163Audio,
+    rom: ROM,
+}
+
+impl Mapper19 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper19 {
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0; 4],
+            prg_banks: [0; 3],
+            sound_disabled: false,
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            write_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_triggered: false,
+            audio: Namco163Audio::new(),
+            rom,
+        }
+    }
+
+    fn chr_rom_offset(&self, bank: u8, addr: u16) -> usize {
+        let len = self.rom.chr_rom_banks as usize * 0x2000;
+        (bank as usize * 0x400 + (addr & 0x3FF) as usize) % len.max(1)
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        if self.rom.chr_rom_banks == 0 {
+            let offset = self.chr_banks[(addr / 0x400) as usize] as usize * 0x400;
+            self.chr_ram[(offset + (addr & 0x3FF) as usize) % self.chr_ram.len()]
+        } else {
+            let bank = self.chr_banks[(addr / 0x400) as usize];
+            self.rom.chr_rom[self.chr_rom_offset(bank, addr)]
+        }
+    }
+
+    // writes need 0x4X in 0xF800, low bits protect 2KB quarters of PRG RAM
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let quarter = (addr - 0x6000) / 0x800;
+        self.write_protect & 0xF0 == 0x40 && self.write_protect & (1 << quarter) == 0
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[((addr - 0x8000) / 0x2000) as usize] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+}
+
+impl Mapper for Mapper19 {
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_triggered = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // the data port moves to the next byte of the internal RAM
+            0x4800..=0x4FFF => self.audio.read_data(),
+            _ => self.peek(addr),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM
+            0x0000..=0x1FFF => self.read_chr(addr),
+
+            // Internal RAM
+            0x4800..=0x4FFF => self.audio.peek_data(),
+
+            // IRQ counter
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+
+            // PRG RAM
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let offset = self.chr_banks[(addr / 0x400) as usize] as usize * 0x400;
+                let len = self.chr_ram.len();
+                self.chr_ram[(offset + (addr & 0x3FF) as usize) % len] = val;
+            }
+
+            // Internal RAM
+            0x4800..=0x4FFF => self.audio.write_data(val),
+
+            // IRQ counter, writes acknowledge the IRQ
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | val as u16;
+                self.irq_triggered = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((val & 0x7F) as u16) << 8;
+                self.irq_enabled = val & 0x80 != 0;
+                self.irq_triggered = false;
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() && self.prg_ram_writable(addr) => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+            }
+
+            // CHR and nametable banks
+            0x8000..=0xBFFF => self.chr_banks[((addr - 0x8000) / 0x800) as usize] = val,
+            0xC000..=0xDFFF => self.nametable_banks[((addr - 0xC000) / 0x800) as usize] = val,
+
+            // PRG banks
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = val & 0x3F;
+                self.sound_disabled = val & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = val & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = val & 0x3F,
+
+            // Internal RAM address and PRG RAM write protection
+            0xF800..=0xFFFF => {
+                self.audio.write_address(val);
+                self.write_protect = val;
+            }
+
+            _ => {}
+        }
+    }
+
+    // banks 0xE0-0xFF select one of the two CIRAM pages (by bit 0),
+    // any other value maps a 1KB CHR ROM bank as the nametable
+    fn peek_nametable(&self, addr: u16, vram: &[u8]) -> Option<u8> {
+        let bank = self.nametable_banks[((addr >> 10) & 0b11) as usize];
+        let offset = (addr & 0x3FF) as usize;
+        if bank >= 0xE0 || self.rom.chr_rom_banks == 0 {
+            Some(vram[(bank & 1) as usize * 0x400 + offset])
+        } else {
+            Some(self.rom.chr_rom[self.chr_rom_offset(bank, addr)])
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, val: u8, vram: &mut [u8]) -> bool {
+        let bank = self.nametable_banks[((addr >> 10) & 0b11) as usize];
+        if bank >= 0xE0 || self.rom.chr_rom_banks == 0 {
+            vram[(bank & 1) as usize * 0x400 + (addr & 0x3FF) as usize] = val;
+        }
+        true
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_disabled {
+            return 0.0;
+        }
+        self.audio.output()
+    }
+
+    // the IRQ line stays asserted until the counter is written
+    fn irq_triggered(&mut self) -> bool {
+        self.irq_triggered
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            // sound data and IRQ counter
+            0x4800..=0x5FFF => true,
+            0x6000..=0x7FFF => !self.prg_ram.is_empty(),
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.chr_banks);
+        buffer.write_u8_arr(&self.nametable_banks);
+        buffer.write_u8_arr(&self.prg_banks);
+        buffer.write_bool(self.sound_disabled);
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.write_protect);
+        buffer.write_u16(self.irq_counter);
+        buffer.write_bool(self.irq_enabled);
+        buffer.write_bool(self.irq_triggered);
+        self.audio.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.chr_banks);
+        buffer.read_u8_arr(&mut self.nametable_banks);
+        buffer.read_u8_arr(&mut self.prg_banks);
+        self.sound_disabled = buffer.read_bool();
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.write_protect = buffer.read_u8();
+        self.irq_counter = buffer.read_u16();
+        self.irq_enabled = buffer.read_bool();
+        self.irq_triggered = buffer.read_bool();
+        self.audio.decode(buffer);
+    }
+}
@@ -1,72 +1,83 @@
 use crate::{buffer::Buffer, rom::ROM};
 
-use super::Mapper;
+use super::{copy_trainer, Mapper};
 
 #[derive(Clone, Debug)]
 pub struct Mapper2 {
-    prg_ram: [u8; 2048],
-    chr_ram: [u8; 0x2000],
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
     bank: u8,
+    // NES 2.0 submapper 1 is the variant without bus conflicts
+    bus_conflicts: bool,
     rom: ROM,
 }
 
 impl Mapper2 {
     pub fn new(rom: ROM) -> Self {
         Mapper2 {
-            prg_ram: [0; 0x800],
-            chr_ram: [0; 0x2000],
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
             bank: 0,
+            bus_conflicts: rom.submapper != 1,
             rom,
         }
     }
+
+    // switchable bank at 0x8000, last bank at 0xC000
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank as usize,
+            _ => (self.rom.prg_rom_banks - 1) as usize,
+        };
+        bank * 0x4000 + (addr & 0x3FFF) as usize
+    }
 }
 
 impl Mapper for Mapper2 {
-    fn read(&mut self, addr: u16) -> u8 {
+    fn peek(&self, addr: u16) -> u8 {
         let addr = addr as usize;
         match addr {
             // CHR ROM/RAM
             0x0000..=0x1FFF => {
                 if self.rom.chr_rom_banks == 0 {
-                    self.chr_ram[addr]
+                    self.chr_ram[addr % self.chr_ram.len()]
                 } else {
-                    let addr = self.rom.chr_rom_start + addr;
-                    self.rom.bytes[addr]
+                    self.rom.chr_rom[addr]
                 }
             }
 
-            // PRG RAM
-            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) & 0x7FF],
-
-            // PRG ROM
-            0x8000..=0xBFFF => {
-                let bank = self.bank as usize * 0x4000;
-                let addr = self.rom.prg_rom_start + bank + (addr & 0x3FFF);
-                self.rom.bytes[addr]
+            // PRG RAM (mirrored if smaller than 8KB)
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) % self.prg_ram.len()]
             }
 
-            // PRG ROM (last bank)
-            0xC000..=0xFFFF => {
-                let bank = (self.rom.prg_rom_banks - 1) as usize * 0x4000;
-                let addr = self.rom.prg_rom_start + bank + (addr & 0x3FFF);
-                self.rom.bytes[addr]
-            }
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr as u16)],
             _ => 0,
         }
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
     fn write(&mut self, addr: u16, val: u8) {
         let addr = addr as usize;
         match addr {
             // CHR RAM
-            0x0000..=0x1FFF => {
-                if self.rom.chr_rom_banks == 0 {
-                    self.chr_ram[addr] = val;
-                }
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr % len] = val;
             }
 
             // PRG RAM
-            0x6000..=0x7FFF => self.prg_ram[addr - 0x6000] = val,
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) % len] = val;
+            }
 
             // Bank select
             0x8000..=0xFFFF => self.bank = val & 0b1111,
@@ -75,10 +86,26 @@ impl Mapper for Mapper2 {
         }
     }
 
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
     fn data(&self) -> &ROM {
         &self.rom
     }
 
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
     fn encode(&self, buffer: &mut Buffer) {
         buffer.write_u8_arr(&self.prg_ram);
         buffer.write_u8_arr(&self.chr_ram);
@@ -0,0 +1,512 @@
+use super::{fds_audio::FdsAudio, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{fds, Mirroring, ROM},
+};
+
+// Famicom Disk System RAM adapter (mapper 20 by convention, there is no cartridge)
+// 32KB PRG RAM at 0x6000-0xDFFF, the 8KB BIOS at 0xE000-0xFFFF and 8KB CHR RAM
+// 0x4020-0x4026: timer IRQ and disk drive control, 0x4030-0x4033: status
+// 0x4040-0x408A: expansion audio
+#[derive(Clone, Debug)]
+pub struct Mapper20 {
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+
+    // disk sides as the drive sees them (with gaps, start marks and CRCs)
+    sides: Vec<Vec<u8>>,
+    side: Option<usize>,
+    // side to insert once the BIOS has noticed the previous one was ejected
+    next_side: Option<usize>,
+    insert_delay: u32,
+
+    // 0x4023
+    disk_io_enabled: bool,
+    sound_io_enabled: bool,
+
+    // timer IRQ, counts down once per CPU cycle
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    // 0x4025
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    disk_irq: bool,
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+    external_data: u8, // 0x4026
+
+    // drive state
+    position: usize,
+    delay: u32,
+    scanning: bool,
+    end_of_head: bool,
+    gap_ended: bool,
+    crc: u16,
+    previous_crc_control: bool,
+
+    audio: FdsAudio,
+    rom: ROM,
+}
+
+// CPU cycles per byte read or written by the drive (about 96.4 kbit/s)
+const BYTE_CYCLES: u32 = 150;
+// CPU cycles the head takes to go back to the start of the disk
+const REWIND_CYCLES: u32 = 50000;
+// CPU cycles the drive stays empty when the disk side is changed
+const SIDE_CHANGE_CYCLES: u32 = 1_000_000;
+
+impl Mapper20 {
+    pub fn new(rom: ROM) -> Self {
+        let sides: Vec<Vec<u8>> = rom
+            .disk_sides
+            .iter()
+            .map(|side| fds::to_raw_side(side))
+            .collect();
+        Mapper20 {
+            prg_ram: vec![0; 0x8000],
+            chr_ram: vec![0; 0x2000],
+            side: if sides.is_empty() { None } else { Some(0) },
+            sides,
+            next_side: None,
+            insert_delay: 0,
+            disk_io_enabled: false,
+            sound_io_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: false,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            transfer_complete: false,
+            read_data: 0,
+            write_data: 0,
+            external_data: 0,
+            position: 0,
+            delay: 0,
+            scanning: false,
+            end_of_head: true,
+            gap_ended: false,
+            crc: 0,
+            previous_crc_control: false,
+            audio: FdsAudio::new(),
+            rom,
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    // moves the disk under the head, one byte every BYTE_CYCLES cycles
+    fn clock_disk(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+            if self.insert_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+
+        let Some(side) = self.side else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut irq = self.disk_irq_enabled;
+        if self.read_mode {
+            let data = self.sides[side][self.position];
+            if !self.previous_crc_control {
+                self.update_crc(data);
+            }
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if data != 0 && !self.gap_ended {
+                // the start mark of a block is not passed to the BIOS
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                self.disk_irq |= irq;
+            }
+            if !self.disk_ready {
+                data = 0;
+            }
+            if !self.crc_control {
+                self.update_crc(data);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            self.sides[side][self.position] = data;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    // CRC-16 (polynomial 0x8408, least significant bit first)
+    fn update_crc(&mut self, val: u8) {
+        for bit in 0..8 {
+            let carry = self.crc & 1 != 0;
+            self.crc >>= 1;
+            if carry {
+                self.crc ^= 0x8408;
+            }
+            if val & (1 << bit) != 0 {
+                self.crc ^= 0x8000;
+            }
+        }
+    }
+
+    fn write_control(&mut self, val: u8) {
+        self.motor_on = val & 0x01 != 0;
+        self.reset_transfer = val & 0x02 != 0;
+        self.read_mode = val & 0x04 != 0;
+        self.rom.mirroring = if val & 0x08 != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+        self.crc_control = val & 0x10 != 0;
+        self.disk_ready = val & 0x40 != 0;
+        self.disk_irq_enabled = val & 0x80 != 0;
+        self.disk_irq = false;
+    }
+}
+
+impl Mapper for Mapper20 {
+    fn cpu_clock(&mut self) {
+        self.clock_timer();
+        self.audio.clock();
+        self.clock_disk();
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.peek(addr);
+        match addr {
+            // reading the disk status acknowledges both IRQs
+            0x4030 if self.disk_io_enabled => {
+                self.timer_irq = false;
+                self.disk_irq = false;
+                self.transfer_complete = false;
+            }
+            0x4031 if self.disk_io_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+        val
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF => self.chr_ram[addr as usize],
+
+            // disk status
+            0x4030 if self.disk_io_enabled => {
+                self.timer_irq as u8 | (self.transfer_complete as u8) << 1
+            }
+
+            // data read from the disk
+            0x4031 if self.disk_io_enabled => self.read_data,
+
+            // drive status: no disk, not ready, write protected
+            0x4032 if self.disk_io_enabled => {
+                let empty = self.side.is_none();
+                let not_ready = empty || !self.scanning;
+                0x40 | empty as u8 | (not_ready as u8) << 1 | (empty as u8) << 2
+            }
+
+            // external connector, bit 7 is the battery status (good)
+            0x4033 if self.disk_io_enabled => 0x80,
+
+            // expansion audio
+            0x4040..=0x407F | 0x4090 | 0x4092 if self.sound_io_enabled => self.audio.read(addr),
+
+            // PRG RAM
+            0x6000..=0xDFFF => self.prg_ram[(addr - 0x6000) as usize],
+
+            // BIOS
+            0xE000..=0xFFFF => self.rom.prg_rom[(addr - 0xE000) as usize],
+
+            _ => 0,
+        }
+    }
+
+    // the BIOS, the disk is loaded into PRG RAM
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0xE000..=0xFFFF => Some((addr - 0xE000) as usize),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF => self.chr_ram[addr as usize] = val,
+
+            // timer IRQ reload value
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | val as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | (val as u16) << 8,
+
+            // timer IRQ control
+            0x4022 => {
+                self.timer_repeat = val & 0x01 != 0;
+                self.timer_enabled = val & 0x02 != 0 && self.disk_io_enabled;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+
+            // master I/O enable
+            0x4023 => {
+                self.disk_io_enabled = val & 0x01 != 0;
+                self.sound_io_enabled = val & 0x02 != 0;
+                if !self.disk_io_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+
+            // data to write to the disk
+            0x4024 if self.disk_io_enabled => {
+                self.write_data = val;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+
+            0x4025 if self.disk_io_enabled => self.write_control(val),
+
+            0x4026 if self.disk_io_enabled => self.external_data = val,
+
+            // expansion audio
+            0x4040..=0x408A if self.sound_io_enabled => self.audio.write(addr, val),
+
+            // PRG RAM
+            0x6000..=0xDFFF => self.prg_ram[(addr - 0x6000) as usize] = val,
+
+            _ => {}
+        }
+    }
+
+    fn irq_triggered(&mut self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x4030..=0x4033 => self.disk_io_enabled,
+            0x4040..=0x407F | 0x4090 | 0x4092 => self.sound_io_enabled,
+            0x6000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn disk_side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        self.side
+    }
+
+    // the drive is left empty for a while when the side is changed,
+    // so the BIOS notices the disk was ejected
+    fn insert_disk_side(&mut self, side: Option<usize>) {
+        let side = side.filter(|&side| side < self.sides.len());
+        if self.side.is_some() && side.is_some() {
+            self.side = None;
+            self.next_side = side;
+            self.insert_delay = SIDE_CHANGE_CYCLES;
+        } else {
+            self.side = side;
+            self.next_side = None;
+            self.insert_delay = 0;
+        }
+    }
+
+    fn disk_image(&self) -> Vec<u8> {
+        self.sides
+            .iter()
+            .flat_map(|side| fds::from_raw_side(side))
+            .collect()
+    }
+
+    fn load_disk_image(&mut self, image: &[u8]) {
+        if let Ok(sides) = fds::parse_sides(image) {
+            self.sides = sides.iter().map(|side| fds::to_raw_side(side)).collect();
+        }
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.sides.len() as u8);
+        for side in self.sides.iter() {
+            buffer.write_u64(side.len() as u64);
+            buffer.write_u8_arr(side);
+        }
+        // 0xFF = no disk
+        buffer.write_u8(self.side.map_or(0xFF, |side| side as u8));
+        buffer.write_u8(self.next_side.map_or(0xFF, |side| side as u8));
+        buffer.write_u32(self.insert_delay);
+        buffer.write_bool(self.disk_io_enabled);
+        buffer.write_bool(self.sound_io_enabled);
+        buffer.write_u16(self.timer_reload);
+        buffer.write_u16(self.timer_counter);
+        buffer.write_bool(self.timer_repeat);
+        buffer.write_bool(self.timer_enabled);
+        buffer.write_bool(self.timer_irq);
+        buffer.write_bool(self.motor_on);
+        buffer.write_bool(self.reset_transfer);
+        buffer.write_bool(self.read_mode);
+        buffer.write_bool(self.crc_control);
+        buffer.write_bool(self.disk_ready);
+        buffer.write_bool(self.disk_irq_enabled);
+        buffer.write_bool(self.disk_irq);
+        buffer.write_bool(self.transfer_complete);
+        buffer.write_u8(self.read_data);
+        buffer.write_u8(self.write_data);
+        buffer.write_u8(self.external_data);
+        buffer.write_u32(self.position as u32);
+        buffer.write_u32(self.delay);
+        buffer.write_bool(self.scanning);
+        buffer.write_bool(self.end_of_head);
+        buffer.write_bool(self.gap_ended);
+        buffer.write_u16(self.crc);
+        buffer.write_bool(self.previous_crc_control);
+        buffer.write_u8(match self.rom.mirroring {
+            Mirroring::Horizontal => 0,
+            _ => 1,
+        });
+        self.audio.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.sides = (0..buffer.read_u8())
+            .map(|_| {
+                let mut side = vec![0; buffer.read_u64() as usize];
+                buffer.read_u8_arr(&mut side);
+                side
+            })
+            .collect();
+        let side = buffer.read_u8();
+        self.side = (side != 0xFF).then_some(side as usize);
+        let next_side = buffer.read_u8();
+        self.next_side = (next_side != 0xFF).then_some(next_side as usize);
+        self.insert_delay = buffer.read_u32();
+        self.disk_io_enabled = buffer.read_bool();
+        self.sound_io_enabled = buffer.read_bool();
+        self.timer_reload = buffer.read_u16();
+        self.timer_counter = buffer.read_u16();
+        self.timer_repeat = buffer.read_bool();
+        self.timer_enabled = buffer.read_bool();
+        self.timer_irq = buffer.read_bool();
+        self.motor_on = buffer.read_bool();
+        self.reset_transfer = buffer.read_bool();
+        self.read_mode = buffer.read_bool();
+        self.crc_control = buffer.read_bool();
+        self.disk_ready = buffer.read_bool();
+        self.disk_irq_enabled = buffer.read_bool();
+        self.disk_irq = buffer.read_bool();
+        self.transfer_complete = buffer.read_bool();
+        self.read_data = buffer.read_u8();
+        self.write_data = buffer.read_u8();
+        self.external_data = buffer.read_u8();
+        self.position = buffer.read_u32() as usize;
+        self.delay = buffer.read_u32();
+        self.scanning = buffer.read_bool();
+        self.end_of_head = buffer.read_bool();
+        self.gap_ended = buffer.read_bool();
+        self.crc = buffer.read_u16();
+        self.previous_crc_control = buffer.read_bool();
+        self.rom.mirroring = match buffer.read_u8() {
+            0 => Mirroring::Horizontal,
+            _ => Mirroring::Vertical,
+        };
+        self.audio.decode(buffer);
+    }
+}
@@ -0,0 +1,258 @@
+use super::{copy_trainer, vrc_irq::VrcIrq, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25)
+// the boards are the same chip with its two register select lines
+// wired to different CPU address lines, the wiring is given by the submapper:
+//   21: 1 = VRC4a (A1, A2), 2 = VRC4c (A6, A7)
+//   22: VRC2a (A1, A0), CHR banks are in 2KB units
+//   23: 1 = VRC4f (A0, A1), 2 = VRC4e (A2, A3), 3 = VRC2b (A0, A1)
+//   25: 1 = VRC4b (A1, A0), 2 = VRC4d (A3, A2), 3 = VRC2c (A1, A0)
+// submapper 0 listens on both wirings of the mapper number
+#[derive(Clone, Debug)]
+pub struct Mapper21 {
+    // CPU address bits connected to register select lines 0 and 1
+    select_lines: (u16, u16),
+    vrc2: bool,
+
+    prg_banks: [u8; 2],
+    prg_swap_mode: bool,
+    chr_banks: [u16; 8],
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+
+    // VRC2 boards without PRG RAM have a 1 bit latch at 0x6000-0x6FFF
+    latch: u8,
+    irq: VrcIrq,
+    rom: ROM,
+}
+
+impl Mapper21 {
+    pub fn new(rom: ROM) -> Self {
+        let (select_lines, vrc2) = match (rom.mapper_id, rom.submapper) {
+            (21, 1) => ((0x02, 0x04), false),
+            (21, 2) => ((0x40, 0x80), false),
+            (21, _) => ((0x42, 0x84), false),
+            (22, _) => ((0x02, 0x01), true),
+            (23, 1) => ((0x01, 0x02), false),
+            (23, 2) => ((0x04, 0x08), false),
+            (23, 3) => ((0x01, 0x02), true),
+            (23, _) => ((0x05, 0x0A), false),
+            (25, 1) => ((0x02, 0x01), false),
+            (25, 2) => ((0x08, 0x04), false),
+            (25, 3) => ((0x02, 0x01), true),
+            _ => ((0x0A, 0x05), false),
+        };
+        Mapper21 {
+            select_lines,
+            vrc2,
+            prg_banks: [0; 2],
+            prg_swap_mode: false,
+            chr_banks: [0; 8],
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            latch: 0,
+            irq: VrcIrq::new(),
+            rom,
+        }
+    }
+
+    // reduce a CPU address to 0xX000-0xX003
+    fn register(&self, addr: u16) -> u16 {
+        let (line0, line1) = self.select_lines;
+        let bit0 = (addr & line0 != 0) as u16;
+        let bit1 = (addr & line1 != 0) as u16;
+        (addr & 0xF000) | bit1 << 1 | bit0
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        let second_last = bank_count - 2;
+        let bank = match (addr, self.prg_swap_mode) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let mut bank = self.chr_banks[(addr / 0x400) as usize] as usize;
+        // VRC2a ignores the low bit of the bank number
+        if self.rom.mapper_id == 22 {
+            bank >>= 1;
+        }
+        bank * 0x400 + (addr & 0x3FF) as usize
+    }
+
+    fn write_chr_bank(&mut self, reg: u16, val: u8) {
+        // each 1KB bank is written as a low and high nibble
+        // 0xB000/0xB001 = bank 0, 0xB002/0xB003 = bank 1, 0xC000 = bank 2 ...
+        let index = (((reg >> 12) - 0xB) * 2 + ((reg >> 1) & 1)) as usize;
+        let bank = &mut self.chr_banks[index];
+        if reg & 1 == 0 {
+            *bank = (*bank & 0x1F0) | (val & 0x0F) as u16;
+        } else {
+            *bank = (*bank & 0x0F) | ((val & 0x1F) as u16) << 4;
+        }
+    }
+
+    fn write_mirroring(&mut self, val: u8) {
+        self.rom.mirroring = if self.vrc2 {
+            match val & 1 {
+                0 => Mirroring::Vertical,
+                _ => Mirroring::Horizontal,
+            }
+        } else {
+            match val & 0b11 {
+                0 => Mirroring::Vertical,
+                1 => Mirroring::Horizontal,
+                2 => Mirroring::OneScreenLower,
+                _ => Mirroring::OneScreenUpper,
+            }
+        };
+    }
+}
+
+impl Mapper for Mapper21 {
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                if self.rom.chr_rom_banks == 0 {
+                    self.chr_ram[offset % self.chr_ram.len()]
+                } else {
+                    let len = self.rom.chr_rom_banks as usize * 0x2000;
+                    self.rom.chr_rom[offset % len]
+                }
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+
+            // VRC2 latch
+            0x6000..=0x6FFF if self.vrc2 => self.latch,
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let offset = self.chr_offset(addr);
+                let len = self.chr_ram.len();
+                self.chr_ram[offset % len] = val;
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+            }
+
+            // VRC2 latch
+            0x6000..=0x6FFF if self.vrc2 => self.latch = val & 1,
+
+            // Registers
+            0x8000..=0xFFFF => {
+                let reg = self.register(addr);
+                match reg {
+                    0x8000..=0x8003 => self.prg_banks[0] = val & 0x1F,
+                    0x9000..=0x9001 => self.write_mirroring(val),
+                    0x9002..=0x9003 if !self.vrc2 => self.prg_swap_mode = val & 0b10 != 0,
+                    0xA000..=0xA003 => self.prg_banks[1] = val & 0x1F,
+                    0xB000..=0xEFFF => self.write_chr_bank(reg, val),
+                    0xF000 if !self.vrc2 => {
+                        self.irq.latch = (self.irq.latch & 0xF0) | (val & 0x0F);
+                    }
+                    0xF001 if !self.vrc2 => {
+                        self.irq.latch = (self.irq.latch & 0x0F) | (val & 0x0F) << 4;
+                    }
+                    0xF002 if !self.vrc2 => self.irq.write_control(val),
+                    0xF003 if !self.vrc2 => self.irq.acknowledge(),
+                    _ => {}
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    // the IRQ line stays asserted until it is acknowledged
+    fn irq_triggered(&mut self) -> bool {
+        self.irq.pending
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => true,
+            // VRC2 microwire latch
+            0x6000..=0x6FFF => self.vrc2,
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.prg_banks);
+        buffer.write_bool(self.prg_swap_mode);
+        for bank in self.chr_banks.iter() {
+            buffer.write_u16(*bank);
+        }
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.latch);
+        self.irq.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.prg_banks);
+        self.prg_swap_mode = buffer.read_bool();
+        for bank in self.chr_banks.iter_mut() {
+            *bank = buffer.read_u16();
+        }
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.latch = buffer.read_u8();
+        self.irq.decode(buffer);
+    }
+}
@@ -0,0 +1,229 @@
+use super::{copy_trainer, vrc6_audio::Vrc6Audio, vrc_irq::VrcIrq, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// Konami VRC6 (mappers 24 and 26)
+// mapper 26 (VRC6b) has CPU address lines A0 and A1 swapped
+// two pulse and a sawtooth expansion audio channel at 0x9000-0xB002
+#[derive(Clone, Debug)]
+pub struct Mapper24 {
+    swapped_lines: bool,
+    prg_bank_16k: u8, // 0x8000
+    prg_bank_8k: u8,  // 0xC000
+    chr_banks: [u8; 8],
+    ppu_banking: u8, // 0xB003
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+    rom: ROM,
+}
+
+impl Mapper24 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper24 {
+            swapped_lines: rom.mapper_id == 26,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            ppu_banking: 0,
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+            rom,
+        }
+    }
+
+    // reduce a CPU address to 0xX000-0xX003
+    fn register(&self, addr: u16) -> u16 {
+        let reg = if self.swapped_lines {
+            (addr & 1) << 1 | (addr >> 1) & 1
+        } else {
+            addr & 0b11
+        };
+        (addr & 0xF000) | reg
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        let bank = match addr {
+            0x8000..=0xBFFF => (self.prg_bank_16k as usize) * 2 + ((addr >> 13) & 1) as usize,
+            0xC000..=0xDFFF => self.prg_bank_8k as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let slot = (addr / 0x400) as usize;
+        let bank = match self.ppu_banking & 0b11 {
+            // 1KB banks
+            0 => self.chr_banks[slot] as usize,
+            // 2KB banks
+            1 => self.chr_bank_2k(self.chr_banks[slot / 2], addr),
+            // 1KB banks at 0x0000-0x0FFF and 2KB banks at 0x1000-0x1FFF
+            _ if slot < 4 => self.chr_banks[slot] as usize,
+            _ => self.chr_bank_2k(self.chr_banks[4 + (slot - 4) / 2], addr),
+        };
+        bank * 0x400 + (addr & 0x3FF) as usize
+    }
+
+    // in 2KB banks A10 comes from the PPU when bit 5 of 0xB003 is set,
+    // otherwise the same 1KB bank is seen twice
+    fn chr_bank_2k(&self, bank: u8, addr: u16) -> usize {
+        if self.ppu_banking & 0x20 != 0 {
+            ((bank & 0xFE) as usize) | ((addr >> 10) & 1) as usize
+        } else {
+            bank as usize
+        }
+    }
+
+    fn write_ppu_banking(&mut self, val: u8) {
+        self.ppu_banking = val;
+        self.rom.mirroring = match (val >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        };
+    }
+
+    // bit 7 of 0xB003 enables PRG RAM
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && self.ppu_banking & 0x80 != 0
+    }
+}
+
+impl Mapper for Mapper24 {
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                if self.rom.chr_rom_banks == 0 {
+                    self.chr_ram[offset % self.chr_ram.len()]
+                } else {
+                    let len = self.rom.chr_rom_banks as usize * 0x2000;
+                    self.rom.chr_rom[offset % len]
+                }
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let offset = self.chr_offset(addr);
+                let len = self.chr_ram.len();
+                self.chr_ram[offset % len] = val;
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+            }
+
+            // Registers
+            0x8000..=0xFFFF => match self.register(addr) {
+                0x8000..=0x8003 => self.prg_bank_16k = val & 0x0F,
+                reg @ (0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002) => {
+                    self.audio.write(reg, val)
+                }
+                0xB003 => self.write_ppu_banking(val),
+                0xC000..=0xC003 => self.prg_bank_8k = val & 0x1F,
+                reg @ 0xD000..=0xE003 => {
+                    let index = ((reg >> 12) - 0xD) * 4 + (reg & 0b11);
+                    self.chr_banks[index as usize] = val;
+                }
+                0xF000 => self.irq.latch = val,
+                0xF001 => self.irq.write_control(val),
+                0xF002 => self.irq.acknowledge(),
+                _ => {}
+            },
+
+            _ => {}
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    // the IRQ line stays asserted until it is acknowledged
+    fn irq_triggered(&mut self) -> bool {
+        self.irq.pending
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram_enabled(),
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.prg_bank_16k);
+        buffer.write_u8(self.prg_bank_8k);
+        buffer.write_u8_arr(&self.chr_banks);
+        buffer.write_u8(self.ppu_banking);
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        self.irq.encode(buffer);
+        self.audio.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.prg_bank_16k = buffer.read_u8();
+        self.prg_bank_8k = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.chr_banks);
+        self.ppu_banking = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.irq.decode(buffer);
+        self.audio.decode(buffer);
+    }
+}
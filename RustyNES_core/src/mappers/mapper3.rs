@@ -0,0 +1,97 @@
+use super::Mapper;
+use crate::{buffer::Buffer, rom::ROM};
+
+// CNROM
+// fixed PRG ROM (16KB or 32KB), switchable 8KB CHR ROM bank
+#[derive(Clone, Debug)]
+pub struct Mapper3 {
+    chr_ram: Vec<u8>,
+    chr_bank: u8,
+    // written value is ANDed with the PRG ROM byte at the same address
+    bus_conflicts: bool,
+    rom: ROM,
+}
+
+impl Mapper3 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper3 {
+            chr_ram: vec![0; rom.chr_ram_len()],
+            chr_bank: 0,
+            // NES 2.0 submapper 1 is the variant without bus conflicts
+            bus_conflicts: rom.submapper != 1,
+            rom,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let mut addr = addr as usize - 0x8000;
+        if self.rom.prg_rom_banks == 1 {
+            addr &= 0x3FFF;
+        }
+        addr
+    }
+}
+
+impl Mapper for Mapper3 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                self.chr_ram[addr as usize % self.chr_ram.len()]
+            }
+
+            // CHR ROM
+            0x0000..=0x1FFF => {
+                let bank = (self.chr_bank as usize % self.rom.chr_rom_banks as usize) * 0x2000;
+                self.rom.chr_rom[bank + addr as usize]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr as usize % len] = val;
+            }
+
+            // Bank select
+            0x8000..=0xFFFF => {
+                self.chr_bank = val;
+            }
+
+            _ => {}
+        }
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.chr_bank);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.chr_bank = buffer.read_u8();
+    }
+}
@@ -0,0 +1,148 @@
+use super::{copy_trainer, Mapper};
+use crate::{buffer::Buffer, rom::ROM};
+
+// BNROM and NINA-001
+// two unrelated boards share mapper 34:
+// BNROM: switchable 32KB PRG ROM bank at 0x8000-0xFFFF (with bus conflicts), 8KB CHR RAM
+// NINA-001: 8KB PRG RAM, registers at 0x7FFD-0x7FFF for 32KB PRG and two 4KB CHR ROM banks
+#[derive(Clone, Debug)]
+pub struct Mapper34 {
+    nina: bool,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+    rom: ROM,
+}
+
+impl Mapper34 {
+    pub fn new(rom: ROM) -> Self {
+        // NES 2.0 submapper 1 is NINA-001, submapper 2 is BNROM,
+        // otherwise only NINA-001 has more than 8KB of CHR ROM
+        let nina = match rom.submapper {
+            1 => true,
+            2 => false,
+            _ => rom.chr_rom_banks > 1,
+        };
+        // only NINA-001 has PRG RAM
+        let prg_ram_len = if nina {
+            rom.prg_ram_len().max(0x2000)
+        } else {
+            0
+        };
+        Mapper34 {
+            nina,
+            prg_ram: vec![0; prg_ram_len],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            prg_bank: 0,
+            chr_banks: [0, 1],
+            rom,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = (self.rom.prg_rom_banks as usize / 2).max(1);
+        let bank = (self.prg_bank as usize % bank_count) * 0x8000;
+        let addr = (addr as usize - 0x8000) % (self.rom.prg_rom_banks as usize * 0x4000);
+        bank + addr
+    }
+}
+
+impl Mapper for Mapper34 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                self.chr_ram[addr as usize % self.chr_ram.len()]
+            }
+
+            // CHR ROM (two 4KB banks)
+            0x0000..=0x1FFF => {
+                let bank_count = self.rom.chr_rom_banks as usize * 2;
+                let index = (addr / 0x1000) as usize;
+                let bank = (self.chr_banks[index] as usize % bank_count) * 0x1000;
+                let addr = (addr & 0xFFF) as usize;
+                self.rom.chr_rom[bank + addr]
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr as usize % len] = val;
+            }
+
+            // PRG RAM, NINA-001 registers are also written to the RAM underneath
+            0x6000..=0x7FFF if self.nina => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+                match addr {
+                    0x7FFD => self.prg_bank = val & 1,
+                    0x7FFE => self.chr_banks[0] = val & 0x0F,
+                    0x7FFF => self.chr_banks[1] = val & 0x0F,
+                    _ => {}
+                }
+            }
+
+            // BNROM bank select
+            0x8000..=0xFFFF if !self.nina => self.prg_bank = val,
+
+            _ => {}
+        }
+    }
+
+    // only BNROM has bus conflicts
+    fn has_bus_conflicts(&self) -> bool {
+        !self.nina
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.prg_bank);
+        buffer.write_u8_arr(&self.chr_banks);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.prg_bank = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.chr_banks);
+    }
+}
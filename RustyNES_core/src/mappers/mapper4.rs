@@ -1,4 +1,4 @@
-use super::Mapper;
+use super::{copy_trainer, Mapper};
 use crate::rom::{Mirroring, ROM};
 
 #[derive(Clone, Debug)]
@@ -7,7 +7,7 @@ pub struct Mapper4 {
     reg_index: u8,
     prg_mode: u8,
     chr_mode: u8,
-    prg_ram: [u8; 0x2000],
+    prg_ram: Vec<u8>,
     prg_offsets: [u32; 4],
     chr_offsets: [u32; 8],
     irq_enabled: bool,
@@ -29,7 +29,7 @@ impl Mapper4 {
             registers: [0; 8],
             chr_mode: 0,
             prg_mode: 0,
-            prg_ram: [0; 0x2000],
+            prg_ram: vec![0; rom.prg_ram_len()],
             prg_offsets: [offset0, offset1, offset2, offset3],
             chr_offsets: [0; 8],
             irq_enabled: false,
@@ -116,6 +116,11 @@ impl Mapper4 {
             self.prg_offsets[3] = (bank_count - 1) as u32 * _8kb;
         }
     }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let index = ((addr - 0x8000) / 0x2000) as usize;
+        self.prg_offsets[index] as usize + (addr & 0x1FFF) as usize
+    }
 }
 
 impl Mapper for Mapper4 {
@@ -131,31 +136,39 @@ impl Mapper for Mapper4 {
         }
     }
 
-    fn read(&mut self, addr: u16) -> u8 {
+    fn peek(&self, addr: u16) -> u8 {
         match addr {
             // CHR ROM
             0x0000..=0x1FFF => {
                 let index = (addr / 0x400) as usize;
                 let offset = self.chr_offsets[index] as usize + (addr & 0x3FF) as usize;
-                self.rom.bytes[self.rom.chr_rom_start + offset]
+                self.rom.chr_rom[offset]
             }
 
-            // PRG RAM
-            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            // PRG RAM (mirrored if smaller than 8KB)
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
 
             // PRG ROM
-            0x8000..=0xFFFF => {
-                let index = ((addr - 0x8000) / 0x2000) as usize;
-                let offset = self.prg_offsets[index] as usize + (addr & 0x1FFF) as usize;
-                self.rom.bytes[self.rom.prg_rom_start + offset]
-            }
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
             _ => 0,
         }
     }
 
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
     fn write(&mut self, addr: u16, val: u8) {
         match addr {
-            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = val,
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+            }
 
             0x8000..=0x9FFF => {
                 if addr & 1 == 0 {
@@ -175,16 +188,27 @@ impl Mapper for Mapper4 {
         }
     }
 
+    // the IRQ stays active until it is acknowledged by writing 0xE000
     fn irq_triggered(&mut self) -> bool {
-        let triggered = self.irq_triggered;
-        self.irq_triggered = false;
-        triggered
+        self.irq_triggered
     }
 
     fn data(&self) -> &ROM {
         &self.rom
     }
 
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
     fn encode(&self, buffer: &mut crate::buffer::Buffer) {
         buffer.write_u8_arr(&self.registers);
         buffer.write_u8(self.reg_index);
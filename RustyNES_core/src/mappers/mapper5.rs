@@ -0,0 +1,615 @@
+use super::{copy_trainer, Mapper};
+use crate::{buffer::Buffer, rom::ROM};
+
+// MMC5 (ExROM, Castlevania III, Uncharted Waters)
+// - 4 PRG banking modes, PRG RAM can be mapped into 0x6000-0xDFFF
+// - 4 CHR banking modes with separate sprite and background bank sets for 8x16 sprites
+// - 1KB ExRAM usable as nametable, extended attributes or plain RAM
+// - per quadrant nametable mapping with a fill mode
+// - vertical split screen
+// - scanline IRQ, scanlines are detected by watching PPU fetches
+// - 8x8 -> 16 bit hardware multiplier
+#[derive(Clone, Debug)]
+pub struct Mapper5 {
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    exram: [u8; 0x400],
+
+    // registers
+    prg_mode: u8,             // 0x5100
+    chr_mode: u8,             // 0x5101
+    prg_ram_protect: [u8; 2], // 0x5102, 0x5103
+    exram_mode: u8,           // 0x5104
+    nametable_mapping: u8,    // 0x5105
+    fill_tile: u8,            // 0x5106
+    fill_attribute: u8,       // 0x5107
+    prg_banks: [u8; 5],       // 0x5113-0x5117
+    chr_banks: [u16; 12],     // 0x5120-0x512B, sprite set 0-7, background set 8-11
+    chr_upper: u8,            // 0x5130
+    last_chr_write_bg: bool,  // which set was written last, used outside of rendering
+    split_control: u8,        // 0x5200
+    split_scroll: u8,         // 0x5201
+    split_bank: u8,           // 0x5202
+    irq_compare: u8,          // 0x5203
+    irq_enabled: bool,        // 0x5204
+    multiplicand: u8,         // 0x5205
+    multiplier: u8,           // 0x5206
+
+    // PPU state snooped from writes to PPUCTRL and PPUMASK
+    sprite_8x16: bool,
+    rendering_enabled: bool,
+
+    // scanline detection
+    // the PPU reads the same nametable address three times in a row
+    // at the end of each scanline (dots 337, 339 and dot 1 of the next line)
+    last_ppu_addr: u16,
+    match_count: u8,
+    in_frame: bool,
+    scanline: u8,
+    irq_pending: bool,
+
+    // fetch tracking within a scanline
+    tile: u8, // background tile being fetched (0-1 prefetched, 2-33 during the line)
+    pattern_reads: u8, // pattern reads since the last nametable fetch
+    sprite_fetch: bool,
+    exattr: u8,  // ExRAM byte of the current tile in extended attribute mode
+    split_y: u8, // vertical scroll of the split region on the current line
+    split: bool, // current tile lies inside the split region
+    rom: ROM,
+}
+
+impl Mapper5 {
+    pub fn new(rom: ROM) -> Self {
+        // MMC5 boards have up to 64KB of PRG RAM,
+        // iNES 1.0 headers can not describe that so use the maximum
+        let prg_ram_len = if rom.nes2 { rom.prg_ram_len() } else { 0x10000 };
+        Mapper5 {
+            prg_ram: vec![0; prg_ram_len],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            exram: [0; 0x400],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            last_chr_write_bg: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            sprite_8x16: false,
+            rendering_enabled: false,
+            last_ppu_addr: 0,
+            match_count: 0,
+            in_frame: false,
+            scanline: 0,
+            irq_pending: false,
+            tile: 0,
+            pattern_reads: 0,
+            sprite_fetch: false,
+            exattr: 0,
+            split_y: 0,
+            split: false,
+            rom,
+        }
+    }
+
+    // PRG /////////////////
+
+    // returns (is_rom, 8KB bank) for a CPU address in 0x6000-0xFFFF
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        if addr < 0x8000 {
+            return (false, (self.prg_banks[0] & 0x07) as usize);
+        }
+        // index of the 8KB window (0-3) and the register that controls it
+        let window = ((addr - 0x8000) / 0x2000) as u8;
+        let (reg, mask) = match self.prg_mode & 0b11 {
+            // 32KB
+            0 => (4, 0x7C),
+            // 16KB + 16KB
+            1 => (if window < 2 { 2 } else { 4 }, 0x7E),
+            // 16KB + 8KB + 8KB
+            2 => match window {
+                0 | 1 => (2, 0x7E),
+                2 => (3, 0x7F),
+                _ => (4, 0x7F),
+            },
+            // 8KB * 4
+            _ => (window as usize + 1, 0x7F),
+        };
+        let val = self.prg_banks[reg];
+        // bit 7 selects ROM, 0x5117 always maps ROM
+        let rom = reg == 4 || val & 0x80 != 0;
+        // windows larger than 8KB ignore the low bits of the bank
+        let sub_bank = match mask {
+            0x7C => window & 0b11,
+            0x7E => window & 0b01,
+            _ => 0,
+        };
+        let bank = ((val & mask) | sub_bank) as usize;
+        if rom {
+            (true, bank)
+        } else {
+            (false, bank & 0x07)
+        }
+    }
+
+    fn prg_rom_index(&self, bank: usize, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn prg_ram_index(&self, bank: usize, addr: u16) -> usize {
+        let banks = self.prg_ram.len() / 0x2000;
+        (bank % banks) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect[0] & 0b11 == 0b10 && self.prg_ram_protect[1] & 0b11 == 0b01
+    }
+
+    // CHR /////////////////
+
+    // background fetches use the second set (0x5128-0x512B) only with 8x16 sprites
+    fn use_bg_set(&self) -> bool {
+        if !self.sprite_8x16 {
+            false
+        } else if self.in_frame && self.rendering_enabled {
+            !self.sprite_fetch
+        } else {
+            self.last_chr_write_bg
+        }
+    }
+
+    fn chr_offset(&self, addr: u16, bg: bool) -> usize {
+        let addr = addr as usize;
+        let (reg, size) = match self.chr_mode & 0b11 {
+            0 => (if bg { 11 } else { 7 }, 0x2000),
+            1 => (if bg { 11 } else { [3, 7][addr / 0x1000] }, 0x1000),
+            2 => {
+                if bg {
+                    ([9, 11][(addr & 0xFFF) / 0x800], 0x800)
+                } else {
+                    ([1, 3, 5, 7][addr / 0x800], 0x800)
+                }
+            }
+            _ => {
+                if bg {
+                    (8 + (addr & 0xFFF) / 0x400, 0x400)
+                } else {
+                    (addr / 0x400, 0x400)
+                }
+            }
+        };
+        self.chr_banks[reg] as usize * size + (addr % size)
+    }
+
+    fn read_chr(&self, offset: usize) -> u8 {
+        if self.rom.chr_rom_banks == 0 {
+            self.chr_ram[offset % self.chr_ram.len()]
+        } else {
+            let len = self.rom.chr_rom_banks as usize * 0x2000;
+            self.rom.chr_rom[offset % len]
+        }
+    }
+
+    // Scanline detection and fetch tracking /////////////////
+
+    fn ppu_read(&mut self, addr: u16) {
+        if addr == self.last_ppu_addr && (0x2000..=0x2FFF).contains(&addr) {
+            self.match_count = self.match_count.saturating_add(1);
+            if self.match_count == 2 {
+                self.scanline_start();
+            }
+        } else {
+            self.match_count = 0;
+        }
+        self.last_ppu_addr = addr;
+    }
+
+    fn scanline_start(&mut self) {
+        // a frame has at most 240 rendered lines,
+        // a detection after the last one means the PPU went through vblank
+        if !self.in_frame || self.scanline >= 239 {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+            self.split_y = self.split_scroll;
+        } else {
+            self.scanline += 1;
+            if self.scanline == self.irq_compare {
+                self.irq_pending = true;
+            }
+            self.split_y = if self.split_y >= 239 {
+                0
+            } else {
+                self.split_y + 1
+            };
+        }
+        // the read that completed the detection is the 3rd tile of the line
+        // (tiles 0 and 1 were fetched at the end of the previous line)
+        self.tile = 1;
+    }
+
+    // called for nametable (not attribute) fetches
+    fn tile_fetch(&mut self) {
+        // after the last tile of a line, the next fetch is the prefetch of the next line
+        self.tile = if self.tile >= 33 { 0 } else { self.tile + 1 };
+        self.pattern_reads = 0;
+        self.sprite_fetch = false;
+
+        let split_enabled = self.split_control & 0x80 != 0 && self.exram_mode <= 1;
+        let split_tile = (self.split_control & 0x1F).min(34);
+        let right_side = self.split_control & 0x40 != 0;
+        self.split = split_enabled
+            && self.in_frame
+            && if right_side {
+                self.tile >= split_tile
+            } else {
+                self.tile < split_tile
+            };
+    }
+
+    // called for pattern table reads
+    fn pattern_fetch(&mut self) {
+        // the two pattern reads of the last tile are followed by the sprite fetches
+        if self.tile == 33 && self.pattern_reads >= 2 {
+            self.sprite_fetch = true;
+        }
+        self.pattern_reads = self.pattern_reads.saturating_add(1);
+    }
+
+    fn rendering(&self) -> bool {
+        self.in_frame && self.rendering_enabled
+    }
+
+    // Registers /////////////////
+
+    fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x5204 => (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6,
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            // ExRAM is only readable by the CPU in modes 2 and 3
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[(addr - 0x5C00) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x5100 => self.prg_mode = val & 0b11,
+            0x5101 => self.chr_mode = val & 0b11,
+            0x5102 => self.prg_ram_protect[0] = val,
+            0x5103 => self.prg_ram_protect[1] = val,
+            0x5104 => self.exram_mode = val & 0b11,
+            0x5105 => self.nametable_mapping = val,
+            0x5106 => self.fill_tile = val,
+            0x5107 => self.fill_attribute = val & 0b11,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = val,
+            0x5120..=0x512B => {
+                let index = (addr - 0x5120) as usize;
+                self.chr_banks[index] = val as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_write_bg = index >= 8;
+            }
+            0x5130 => self.chr_upper = val & 0b11,
+            0x5200 => self.split_control = val,
+            0x5201 => self.split_scroll = val,
+            0x5202 => self.split_bank = val,
+            0x5203 => self.irq_compare = val,
+            0x5204 => self.irq_enabled = val & 0x80 != 0,
+            0x5205 => self.multiplicand = val,
+            0x5206 => self.multiplier = val,
+            0x5C00..=0x5FFF => {
+                let index = (addr - 0x5C00) as usize;
+                match self.exram_mode {
+                    // nametable modes can only be written while rendering, otherwise 0 is written
+                    0 | 1 => self.exram[index] = if self.rendering() { val } else { 0 },
+                    2 => self.exram[index] = val,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mapper5 {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            self.ppu_read(addr);
+            self.pattern_fetch();
+        }
+        let val = self.peek(addr);
+        match addr {
+            // reading the status acknowledges the IRQ
+            0x5204 => self.irq_pending = false,
+            // reading the NMI vector means the PPU entered vblank
+            0xFFFA | 0xFFFB => {
+                self.in_frame = false;
+                self.last_ppu_addr = 0;
+            }
+            _ => {}
+        }
+        val
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM
+            0x0000..=0x1FFF => {
+                let bg = !self.sprite_fetch && self.rendering();
+                let offset = if bg && self.split {
+                    // split region uses its own 4KB bank and vertical scroll
+                    let fine_y = (self.split_y & 0b111) as usize;
+                    self.split_bank as usize * 0x1000 + ((addr as usize & 0xFF8) | fine_y)
+                } else if bg && self.exram_mode == 1 {
+                    // extended attributes select a 4KB bank for each tile
+                    let bank = (self.exattr & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                    bank * 0x1000 + (addr & 0xFFF) as usize
+                } else {
+                    self.chr_offset(addr, self.use_bg_set())
+                };
+                self.read_chr(offset)
+            }
+
+            // Registers and ExRAM
+            0x5000..=0x5FFF => self.peek_register(addr),
+
+            // PRG RAM/ROM
+            0x6000..=0xFFFF => {
+                let (rom, bank) = self.prg_bank(addr);
+                if rom {
+                    self.rom.prg_rom[self.prg_rom_index(bank, addr)]
+                } else if self.prg_ram.is_empty() {
+                    0
+                } else {
+                    self.prg_ram[self.prg_ram_index(bank, addr)]
+                }
+            }
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0xFFFF => match self.prg_bank(addr) {
+                (true, bank) => Some(self.prg_rom_index(bank, addr)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let offset = self.chr_offset(addr, self.use_bg_set());
+                let len = self.chr_ram.len();
+                self.chr_ram[offset % len] = val;
+            }
+
+            // Registers and ExRAM
+            0x5000..=0x5FFF => self.write_register(addr, val),
+
+            // PRG RAM (0x6000-0xDFFF)
+            0x6000..=0xDFFF => {
+                let (rom, bank) = self.prg_bank(addr);
+                if !rom && !self.prg_ram.is_empty() && self.prg_ram_writable() {
+                    let index = self.prg_ram_index(bank, addr);
+                    self.prg_ram[index] = val;
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn ppu_register_written(&mut self, addr: u16, val: u8) {
+        match addr {
+            0 => self.sprite_8x16 = val & 0x20 != 0,
+            1 => {
+                self.rendering_enabled = val & 0x18 != 0;
+                if !self.rendering_enabled {
+                    self.in_frame = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_nametable(&mut self, addr: u16, vram: &[u8]) -> Option<u8> {
+        let addr = 0x2000 | (addr & 0x0FFF);
+        self.ppu_read(addr);
+
+        let offset = (addr & 0x3FF) as usize;
+        let attribute = offset >= 0x3C0;
+        if !attribute {
+            self.tile_fetch();
+        }
+
+        if self.split && self.rendering() {
+            // split region reads its nametable and attributes from ExRAM
+            let column = (self.tile & 0x1F) as usize;
+            let row = (self.split_y / 8) as usize;
+            return Some(if attribute {
+                let attr = self.exram[0x3C0 + (row / 4) * 8 + column / 4];
+                let shift = ((row & 2) << 1) | (column & 2);
+                ((attr >> shift) & 0b11) * 0x55
+            } else {
+                self.exram[row * 32 + column]
+            });
+        }
+
+        if self.exram_mode == 1 && self.rendering() {
+            // extended attributes, every tile has its own palette in ExRAM
+            if attribute {
+                return Some((self.exattr >> 6) * 0x55);
+            }
+            self.exattr = self.exram[offset];
+        }
+
+        self.peek_nametable(addr, vram)
+    }
+
+    // the nametable mapping, the split and extended attributes only apply to the PPU's fetches
+    fn peek_nametable(&self, addr: u16, vram: &[u8]) -> Option<u8> {
+        let offset = (addr & 0x3FF) as usize;
+        let attribute = offset >= 0x3C0;
+        let quadrant = (addr >> 10) & 0b11;
+        let data = match (self.nametable_mapping >> (quadrant * 2)) & 0b11 {
+            0 => vram[offset],
+            1 => vram[0x400 + offset],
+            2 if self.exram_mode <= 1 => self.exram[offset],
+            2 => 0,
+            // fill mode
+            _ if attribute => self.fill_attribute * 0x55,
+            _ => self.fill_tile,
+        };
+        Some(data)
+    }
+
+    fn write_nametable(&mut self, addr: u16, val: u8, vram: &mut [u8]) -> bool {
+        let offset = (addr & 0x3FF) as usize;
+        let quadrant = (addr >> 10) & 0b11;
+        match (self.nametable_mapping >> (quadrant * 2)) & 0b11 {
+            0 => vram[offset] = val,
+            1 => vram[0x400 + offset] = val,
+            2 if self.exram_mode <= 1 => self.exram[offset] = val,
+            _ => {}
+        }
+        true
+    }
+
+    // the IRQ line stays asserted until it is acknowledged by reading 0x5204
+    fn irq_triggered(&mut self) -> bool {
+        self.irq_enabled && self.irq_pending
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x5204..=0x5206 => true,
+            0x5C00..=0x5FFF => self.exram_mode >= 2,
+            0x6000..=0xFFFF => self.prg_bank(addr).0 || !self.prg_ram.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        if self.prg_ram.len() < 0x2000 {
+            return false;
+        }
+        // 0x5113 selects the PRG RAM bank of 0x6000-0x7FFF
+        let (_, bank) = self.prg_bank(0x7000);
+        let index = self.prg_ram_index(bank, 0x7000);
+        copy_trainer(&mut self.prg_ram, index, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8_arr(&self.exram);
+
+        buffer.write_u8(self.prg_mode);
+        buffer.write_u8(self.chr_mode);
+        buffer.write_u8_arr(&self.prg_ram_protect);
+        buffer.write_u8(self.exram_mode);
+        buffer.write_u8(self.nametable_mapping);
+        buffer.write_u8(self.fill_tile);
+        buffer.write_u8(self.fill_attribute);
+        buffer.write_u8_arr(&self.prg_banks);
+        for bank in self.chr_banks.iter() {
+            buffer.write_u16(*bank);
+        }
+        buffer.write_u8(self.chr_upper);
+        buffer.write_bool(self.last_chr_write_bg);
+        buffer.write_u8(self.split_control);
+        buffer.write_u8(self.split_scroll);
+        buffer.write_u8(self.split_bank);
+        buffer.write_u8(self.irq_compare);
+        buffer.write_bool(self.irq_enabled);
+        buffer.write_u8(self.multiplicand);
+        buffer.write_u8(self.multiplier);
+
+        buffer.write_bool(self.sprite_8x16);
+        buffer.write_bool(self.rendering_enabled);
+
+        buffer.write_u16(self.last_ppu_addr);
+        buffer.write_u8(self.match_count);
+        buffer.write_bool(self.in_frame);
+        buffer.write_u8(self.scanline);
+        buffer.write_bool(self.irq_pending);
+
+        buffer.write_u8(self.tile);
+        buffer.write_u8(self.pattern_reads);
+        buffer.write_bool(self.sprite_fetch);
+        buffer.write_u8(self.exattr);
+        buffer.write_u8(self.split_y);
+        buffer.write_bool(self.split);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        buffer.read_u8_arr(&mut self.exram);
+
+        self.prg_mode = buffer.read_u8();
+        self.chr_mode = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.prg_ram_protect);
+        self.exram_mode = buffer.read_u8();
+        self.nametable_mapping = buffer.read_u8();
+        self.fill_tile = buffer.read_u8();
+        self.fill_attribute = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.prg_banks);
+        for bank in self.chr_banks.iter_mut() {
+            *bank = buffer.read_u16();
+        }
+        self.chr_upper = buffer.read_u8();
+        self.last_chr_write_bg = buffer.read_bool();
+        self.split_control = buffer.read_u8();
+        self.split_scroll = buffer.read_u8();
+        self.split_bank = buffer.read_u8();
+        self.irq_compare = buffer.read_u8();
+        self.irq_enabled = buffer.read_bool();
+        self.multiplicand = buffer.read_u8();
+        self.multiplier = buffer.read_u8();
+
+        self.sprite_8x16 = buffer.read_bool();
+        self.rendering_enabled = buffer.read_bool();
+
+        self.last_ppu_addr = buffer.read_u16();
+        self.match_count = buffer.read_u8();
+        self.in_frame = buffer.read_bool();
+        self.scanline = buffer.read_u8();
+        self.irq_pending = buffer.read_bool();
+
+        self.tile = buffer.read_u8();
+        self.pattern_reads = buffer.read_u8();
+        self.sprite_fetch = buffer.read_bool();
+        self.exattr = buffer.read_u8();
+        self.split_y = buffer.read_u8();
+        self.split = buffer.read_bool();
+    }
+}
@@ -0,0 +1,99 @@
+use super::Mapper;
+use crate::{buffer::Buffer, rom::ROM};
+
+// GxROM (GNROM, MHROM)
+// switchable 32KB PRG ROM bank and 8KB CHR ROM bank
+#[derive(Clone, Debug)]
+pub struct Mapper66 {
+    chr_ram: Vec<u8>,
+    prg_bank: u8,
+    chr_bank: u8,
+    rom: ROM,
+}
+
+impl Mapper66 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper66 {
+            chr_ram: vec![0; rom.chr_ram_len()],
+            prg_bank: 0,
+            chr_bank: 0,
+            rom,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = (self.rom.prg_rom_banks as usize / 2).max(1);
+        let bank = (self.prg_bank as usize % bank_count) * 0x8000;
+        let addr = (addr as usize - 0x8000) % (self.rom.prg_rom_banks as usize * 0x4000);
+        bank + addr
+    }
+}
+
+impl Mapper for Mapper66 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                self.chr_ram[addr as usize % self.chr_ram.len()]
+            }
+
+            // CHR ROM
+            0x0000..=0x1FFF => {
+                let bank = (self.chr_bank as usize % self.rom.chr_rom_banks as usize) * 0x2000;
+                self.rom.chr_rom[bank + addr as usize]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr as usize % len] = val;
+            }
+
+            // Bank select
+            // bits 0-1: 8KB CHR ROM bank
+            // bits 4-5: 32KB PRG ROM bank
+            0x8000..=0xFFFF => {
+                self.chr_bank = val & 0b11;
+                self.prg_bank = (val >> 4) & 0b11;
+            }
+
+            _ => {}
+        }
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        true
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.prg_bank);
+        buffer.write_u8(self.chr_bank);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.prg_bank = buffer.read_u8();
+        self.chr_bank = buffer.read_u8();
+    }
+}
@@ -0,0 +1,236 @@
+use super::{copy_trainer, sunsoft5b_audio::Sunsoft5bAudio, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// Sunsoft FME-7 and 5B (mapper 69, Gimmick!, Batman: Return of the Joker)
+// registers are written through a command (0x8000-0x9FFF) and parameter (0xA000-0xBFFF) port
+// 0x6000-0x7FFF can be mapped to PRG ROM or PRG RAM
+// 16-bit IRQ counter decremented every CPU cycle
+// the 5B variant adds 3 channel expansion audio
+#[derive(Clone, Debug)]
+pub struct Mapper69 {
+    command: u8,
+    chr_banks: [u8; 8],
+    prg_banks: [u8; 4], // 0x6000, 0x8000, 0xA000, 0xC000
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_triggered: bool,
+
+    audio: Sunsoft5bAudio,
+    rom: ROM,
+}
+
+impl Mapper69 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper69 {
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_triggered: false,
+            audio: Sunsoft5bAudio::new(),
+            rom,
+        }
+    }
+
+    fn write_parameter(&mut self, val: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = val,
+            // bit 7: RAM enable, bit 6: RAM (1) or ROM (0), bits 0-5: bank
+            0x8 => self.prg_banks[0] = val,
+            0x9..=0xB => self.prg_banks[(self.command - 0x8) as usize] = val & 0x3F,
+            0xC => {
+                self.rom.mirroring = match val & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::OneScreenLower,
+                    _ => Mirroring::OneScreenUpper,
+                }
+            }
+            // any write to the IRQ control acknowledges the IRQ
+            0xD => {
+                self.irq_enabled = val & 0x01 != 0;
+                self.irq_counter_enabled = val & 0x80 != 0;
+                self.irq_triggered = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | val as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (val as u16) << 8,
+        }
+    }
+
+    // 0x6000-0x7FFF (when ROM is mapped there) and 0x8000-0xDFFF are switchable,
+    // 0xE000-0xFFFF is the last bank
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x6000..=0x7FFF => self.prg_banks[0] & 0x3F,
+            0x8000..=0xDFFF => self.prg_banks[((addr - 0x6000) / 0x2000) as usize],
+            _ => (self.rom.prg_rom_banks * 2 - 1) as u8,
+        };
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        (bank as usize % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn prg_ram_index(&self, addr: u16) -> usize {
+        let bank_count = (self.prg_ram.len() / 0x2000).max(1);
+        let bank = (self.prg_banks[0] & 0x3F) as usize % bank_count;
+        (bank * 0x2000 + (addr & 0x1FFF) as usize) % self.prg_ram.len()
+    }
+
+    // 0x6000-0x7FFF is RAM when bit 6 is set, and only accessible when bit 7 is also set
+    fn prg_ram_mapped(&self) -> bool {
+        self.prg_banks[0] & 0x40 != 0
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && self.prg_banks[0] & 0xC0 == 0xC0
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[(addr / 0x400) as usize] as usize * 0x400 + (addr & 0x3FF) as usize
+    }
+}
+
+impl Mapper for Mapper69 {
+    fn cpu_clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_triggered = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                if self.rom.chr_rom_banks == 0 {
+                    self.chr_ram[offset % self.chr_ram.len()]
+                } else {
+                    let len = self.rom.chr_rom_banks as usize * 0x2000;
+                    self.rom.chr_rom[offset % len]
+                }
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[self.prg_ram_index(addr)],
+            0x6000..=0x7FFF if self.prg_ram_mapped() => 0,
+
+            // PRG ROM
+            0x6000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_mapped() => None,
+            0x6000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let offset = self.chr_offset(addr);
+                let len = self.chr_ram.len();
+                self.chr_ram[offset % len] = val;
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let index = self.prg_ram_index(addr);
+                self.prg_ram[index] = val;
+            }
+
+            // Registers
+            0x8000..=0x9FFF => self.command = val & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(val),
+
+            // Audio
+            0xC000..=0xDFFF => self.audio.select(val),
+            0xE000..=0xFFFF => self.audio.write(val),
+
+            _ => {}
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    // the IRQ line stays asserted until the IRQ control register is written
+    fn irq_triggered(&mut self) -> bool {
+        self.irq_triggered
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            // PRG RAM or ROM, mapped RAM that is disabled is not driven
+            0x6000..=0x7FFF => self.prg_ram_enabled() || !self.prg_ram_mapped(),
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        if self.prg_ram.len() < 0x2000 {
+            return false;
+        }
+        let index = self.prg_ram_index(0x7000);
+        copy_trainer(&mut self.prg_ram, index, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.command);
+        buffer.write_u8_arr(&self.chr_banks);
+        buffer.write_u8_arr(&self.prg_banks);
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_bool(self.irq_enabled);
+        buffer.write_bool(self.irq_counter_enabled);
+        buffer.write_u16(self.irq_counter);
+        buffer.write_bool(self.irq_triggered);
+        self.audio.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.command = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.chr_banks);
+        buffer.read_u8_arr(&mut self.prg_banks);
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.irq_enabled = buffer.read_bool();
+        self.irq_counter_enabled = buffer.read_bool();
+        self.irq_counter = buffer.read_u16();
+        self.irq_triggered = buffer.read_bool();
+        self.audio.decode(buffer);
+    }
+}
@@ -0,0 +1,103 @@
+use super::Mapper;
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// AxROM (ANROM, AN1ROM, AMROM, AOROM)
+// switchable 32KB PRG ROM bank, 8KB CHR RAM, one-screen mirroring selected by the bank register
+#[derive(Clone, Debug)]
+pub struct Mapper7 {
+    chr_ram: Vec<u8>,
+    prg_bank: u8,
+    // only AMROM boards have bus conflicts (NES 2.0 submapper 2)
+    bus_conflicts: bool,
+    rom: ROM,
+}
+
+impl Mapper7 {
+    pub fn new(mut rom: ROM) -> Self {
+        rom.mirroring = Mirroring::OneScreenLower;
+        Mapper7 {
+            chr_ram: vec![0; rom.chr_ram_len()],
+            prg_bank: 0,
+            bus_conflicts: rom.submapper == 2,
+            rom,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = (self.rom.prg_rom_banks as usize / 2).max(1);
+        let bank = (self.prg_bank as usize % bank_count) * 0x8000;
+        let addr = (addr as usize - 0x8000) % (self.rom.prg_rom_banks as usize * 0x4000);
+        bank + addr
+    }
+}
+
+impl Mapper for Mapper7 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                self.chr_ram[addr as usize % self.chr_ram.len()]
+            }
+
+            // CHR ROM
+            0x0000..=0x1FFF => self.rom.chr_rom[addr as usize],
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr as usize % len] = val;
+            }
+
+            // Bank select
+            // bits 0-3: 32KB PRG ROM bank
+            // bit 4: one-screen nametable page
+            0x8000..=0xFFFF => {
+                self.prg_bank = val & 0x0F;
+                self.rom.mirroring = if val & 0x10 == 0 {
+                    Mirroring::OneScreenLower
+                } else {
+                    Mirroring::OneScreenUpper
+                };
+            }
+
+            _ => {}
+        }
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.prg_bank);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.prg_bank = buffer.read_u8();
+    }
+}
@@ -0,0 +1,103 @@
+use super::Mapper;
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// Camerica / Codemasters (BF9093, BF9097)
+// like UxROM: switchable 16KB PRG ROM bank at 0x8000, last bank fixed at 0xC000, 8KB CHR RAM
+// BF9097 (Fire Hawk) also selects one-screen mirroring through 0x8000-0x9FFF
+#[derive(Clone, Debug)]
+pub struct Mapper71 {
+    chr_ram: Vec<u8>,
+    bank: u8,
+    rom: ROM,
+}
+
+impl Mapper71 {
+    pub fn new(mut rom: ROM) -> Self {
+        // NES 2.0 submapper 1 is the BF9097 board
+        if rom.submapper == 1 {
+            rom.mirroring = Mirroring::OneScreenLower;
+        }
+        Mapper71 {
+            chr_ram: vec![0; rom.chr_ram_len()],
+            bank: 0,
+            rom,
+        }
+    }
+
+    // switchable bank at 0x8000, last bank at 0xC000
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank as usize % self.rom.prg_rom_banks as usize,
+            _ => (self.rom.prg_rom_banks - 1) as usize,
+        };
+        bank * 0x4000 + (addr & 0x3FFF) as usize
+    }
+}
+
+impl Mapper for Mapper71 {
+    fn peek(&self, addr: u16) -> u8 {
+        let addr = addr as usize;
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                self.chr_ram[addr % self.chr_ram.len()]
+            }
+
+            // CHR ROM
+            0x0000..=0x1FFF => self.rom.chr_rom[addr],
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr as u16)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let len = self.chr_ram.len();
+                self.chr_ram[addr as usize % len] = val;
+            }
+
+            // Mirroring (BF9097 only, other boards never write here)
+            0x9000..=0x9FFF => {
+                self.rom.mirroring = if val & 0x10 == 0 {
+                    Mirroring::OneScreenLower
+                } else {
+                    Mirroring::OneScreenUpper
+                };
+            }
+
+            // Bank select (no bus conflicts)
+            0xC000..=0xFFFF => self.bank = val & 0x0F,
+
+            _ => {}
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.chr_ram);
+        buffer.write_u8(self.bank);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.bank = buffer.read_u8();
+    }
+}
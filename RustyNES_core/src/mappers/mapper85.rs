@@ -0,0 +1,199 @@
+use super::{copy_trainer, vrc_irq::VrcIrq, Mapper};
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// Konami VRC7 (mapper 85)
+// the second register of each pair is selected by A3 (VRC7b, submapper 1)
+// or A4 (VRC7a, submapper 2), submapper 0 listens on both
+// the FM expansion audio (0x9010, 0x9030) is not emulated
+#[derive(Clone, Debug)]
+pub struct Mapper85 {
+    select_line: u16,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8, // 0xE000, mirroring and PRG RAM enable
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    irq: VrcIrq,
+    rom: ROM,
+}
+
+impl Mapper85 {
+    pub fn new(rom: ROM) -> Self {
+        let select_line = match rom.submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+        Mapper85 {
+            select_line,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            prg_ram: vec![0; rom.prg_ram_len()],
+            chr_ram: vec![0; rom.chr_ram_len()],
+            irq: VrcIrq::new(),
+            rom,
+        }
+    }
+
+    // reduce a CPU address to 0xX000 or 0xX010
+    fn register(&self, addr: u16) -> u16 {
+        let second = if addr & self.select_line != 0 {
+            0x10
+        } else {
+            0
+        };
+        (addr & 0xF000) | second
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[((addr - 0x8000) / 0x2000) as usize] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[(addr / 0x400) as usize] as usize * 0x400 + (addr & 0x3FF) as usize
+    }
+
+    fn write_control(&mut self, val: u8) {
+        self.control = val;
+        self.rom.mirroring = match val & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        };
+    }
+
+    // bit 7 of 0xE000 enables PRG RAM
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && self.control & 0x80 != 0
+    }
+}
+
+impl Mapper for Mapper85 {
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM/RAM
+            0x0000..=0x1FFF => {
+                let offset = self.chr_offset(addr);
+                if self.rom.chr_rom_banks == 0 {
+                    self.chr_ram[offset % self.chr_ram.len()]
+                } else {
+                    let len = self.rom.chr_rom_banks as usize * 0x2000;
+                    self.rom.chr_rom[offset % len]
+                }
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // CHR RAM
+            0x0000..=0x1FFF if self.rom.chr_rom_banks == 0 => {
+                let offset = self.chr_offset(addr);
+                let len = self.chr_ram.len();
+                self.chr_ram[offset % len] = val;
+            }
+
+            // PRG RAM
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = val;
+            }
+
+            // Registers
+            0x8000..=0xFFFF => match self.register(addr) {
+                0x8000 => self.prg_banks[0] = val & 0x3F,
+                0x8010 => self.prg_banks[1] = val & 0x3F,
+                0x9000 => self.prg_banks[2] = val & 0x3F,
+                reg @ 0xA000..=0xD010 => {
+                    let index = ((reg >> 12) - 0xA) * 2 + (reg >> 4 & 1);
+                    self.chr_banks[index as usize] = val;
+                }
+                0xE000 => self.write_control(val),
+                0xE010 => self.irq.latch = val,
+                0xF000 => self.irq.write_control(val),
+                0xF010 => self.irq.acknowledge(),
+                _ => {}
+            },
+
+            _ => {}
+        }
+    }
+
+    // the IRQ line stays asserted until it is acknowledged
+    fn irq_triggered(&mut self) -> bool {
+        self.irq.pending
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram_enabled(),
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn battery_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn load_trainer(&mut self, trainer: &[u8]) -> bool {
+        copy_trainer(&mut self.prg_ram, 0x1000, trainer)
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.prg_banks);
+        buffer.write_u8_arr(&self.chr_banks);
+        buffer.write_u8(self.control);
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.chr_ram);
+        self.irq.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.prg_banks);
+        buffer.read_u8_arr(&mut self.chr_banks);
+        self.control = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.chr_ram);
+        self.irq.decode(buffer);
+    }
+}
@@ -0,0 +1,114 @@
+use super::Mapper;
+use crate::{
+    buffer::Buffer,
+    rom::{Mirroring, ROM},
+};
+
+// MMC2 (PxROM, Punch-Out!!)
+// 8KB switchable PRG ROM bank at 0x8000, last three 8KB banks fixed at 0xA000-0xFFFF
+// two 4KB CHR ROM windows, each with two banks selected by a latch
+// the latches flip when the PPU fetches tile 0xFD or 0xFE from the pattern tables
+#[derive(Clone, Debug)]
+pub struct Mapper9 {
+    prg_bank: u8,
+    // CHR banks: [window][latch], latch 0 = 0xFD, latch 1 = 0xFE
+    chr_banks: [[u8; 2]; 2],
+    latches: [u8; 2],
+    rom: ROM,
+}
+
+impl Mapper9 {
+    pub fn new(rom: ROM) -> Self {
+        Mapper9 {
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [1, 1],
+            rom,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank_count = self.rom.prg_rom_banks as usize * 2;
+        let bank = match addr {
+            0x8000..=0x9FFF => self.prg_bank as usize % bank_count,
+            // last three banks
+            _ => bank_count - 4 + ((addr - 0x8000) / 0x2000) as usize,
+        };
+        bank * 0x2000 + (addr & 0x1FFF) as usize
+    }
+}
+
+impl Mapper for Mapper9 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            // CHR ROM
+            0x0000..=0x1FFF => {
+                let window = (addr / 0x1000) as usize;
+                let bank = self.chr_banks[window][self.latches[window] as usize] as usize;
+                let bank_count = self.rom.chr_rom_banks as usize * 2;
+                let offset = (bank % bank_count) * 0x1000 + (addr & 0xFFF) as usize;
+                self.rom.chr_rom[offset]
+            }
+
+            // PRG ROM
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xA000..=0xAFFF => self.prg_bank = val & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = val & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = val & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = val & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = val & 0x1F,
+            0xF000..=0xFFFF => {
+                self.rom.mirroring = if val & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    // the latch changes after the fetch, so the tile 0xFD/0xFE itself uses the old bank
+    // MMC2 only reacts to 0x0FD8/0x0FE8 in the first window and 0x1FD8-0x1FEF in the second
+    fn chr_fetched(&mut self, addr: u16) {
+        match addr {
+            0x0FD8 => self.latches[0] = 0,
+            0x0FE8 => self.latches[0] = 1,
+            0x1FD8..=0x1FDF => self.latches[1] = 0,
+            0x1FE8..=0x1FEF => self.latches[1] = 1,
+            _ => {}
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.prg_bank);
+        buffer.write_u8_arr(&self.chr_banks[0]);
+        buffer.write_u8_arr(&self.chr_banks[1]);
+        buffer.write_u8_arr(&self.latches);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.prg_bank = buffer.read_u8();
+        buffer.read_u8_arr(&mut self.chr_banks[0]);
+        buffer.read_u8_arr(&mut self.chr_banks[1]);
+        buffer.read_u8_arr(&mut self.latches);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::buffer::Buffer;
+
+// Nintendo MMC5 expansion audio
+// two pulse channels like the APU's without the sweep unit and a raw 8 bit PCM channel
+// 0x5000-0x5003: pulse 1, 0x5004-0x5007: pulse 2, 0x5010-0x5011: PCM, 0x5015: status
+// the envelopes and length counters are clocked at a fixed 240Hz instead of by the frame counter,
+// the PCM read mode (samples taken from reads of 0x8000-0xBFFF) is not emulated
+#[derive(Clone, Debug)]
+pub struct Mmc5Audio {
+    pulses: [Mmc5Pulse; 2],
+    pcm_read_mode: bool,
+    pcm: u8,
+    frame_timer: u16,
+    odd_cycle: bool, // the pulse timers run at half the CPU clock like the APU's
+}
+
+// CPU cycles between envelope and length counter clocks (240Hz)
+const FRAME_PERIOD: u16 = 7457;
+
+const DUTY: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const LENGTH: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Clone, Debug, Default)]
+struct Mmc5Pulse {
+    enabled: bool,
+    duty: u8,
+    step: u8,    // 0-7
+    period: u16, // 11 bits
+    timer: u16,
+    length: u8,
+    halt: bool, // also loops the envelope
+    constant_volume: bool,
+    volume: u8, // constant volume or envelope period
+    envelope_start: bool,
+    envelope_counter: u8,
+    envelope_volume: u8,
+}
+
+impl Mmc5Pulse {
+    fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.duty = val >> 6;
+                self.halt = val & 0x20 != 0;
+                self.constant_volume = val & 0x10 != 0;
+                self.volume = val & 0x0F;
+            }
+            2 => self.period = (self.period & 0x0700) | val as u16,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((val & 0b111) as u16) << 8;
+                self.step = 0;
+                self.envelope_start = true;
+                if self.enabled {
+                    self.length = LENGTH[(val >> 3) as usize];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_frame(&mut self) {
+        if self.envelope_start {
+            self.envelope_start = false;
+            self.envelope_volume = 15;
+            self.envelope_counter = self.volume;
+        } else if self.envelope_counter == 0 {
+            self.envelope_counter = self.volume;
+            if self.envelope_volume > 0 {
+                self.envelope_volume -= 1;
+            } else if self.halt {
+                self.envelope_volume = 15;
+            }
+        } else {
+            self.envelope_counter -= 1;
+        }
+        if !self.halt && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || DUTY[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else if self.constant_volume {
+            self.volume
+        } else {
+            self.envelope_volume
+        }
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_bool(self.enabled);
+        buffer.write_u8(self.duty);
+        buffer.write_u8(self.step);
+        buffer.write_u16(self.period);
+        buffer.write_u16(self.timer);
+        buffer.write_u8(self.length);
+        buffer.write_bool(self.halt);
+        buffer.write_bool(self.constant_volume);
+        buffer.write_u8(self.volume);
+        buffer.write_bool(self.envelope_start);
+        buffer.write_u8(self.envelope_counter);
+        buffer.write_u8(self.envelope_volume);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.enabled = buffer.read_bool();
+        self.duty = buffer.read_u8();
+        self.step = buffer.read_u8();
+        self.period = buffer.read_u16();
+        self.timer = buffer.read_u16();
+        self.length = buffer.read_u8();
+        self.halt = buffer.read_bool();
+        self.constant_volume = buffer.read_bool();
+        self.volume = buffer.read_u8();
+        self.envelope_start = buffer.read_bool();
+        self.envelope_counter = buffer.read_u8();
+        self.envelope_volume = buffer.read_u8();
+    }
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Mmc5Audio {
+            pulses: [Mmc5Pulse::default(), Mmc5Pulse::default()],
+            pcm_read_mode: false,
+            pcm: 0,
+            frame_timer: 0,
+            odd_cycle: false,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulses[0].write(addr - 0x5000, val),
+            0x5004..=0x5007 => self.pulses[1].write(addr - 0x5004, val),
+            0x5010 => self.pcm_read_mode = val & 0x01 != 0,
+            // writing 0 has no effect (it would raise the IRQ in read mode)
+            0x5011 if !self.pcm_read_mode && val != 0 => self.pcm = val,
+            0x5015 => {
+                self.pulses[0].set_enabled(val & 0x01 != 0);
+                self.pulses[1].set_enabled(val & 0x02 != 0);
+            }
+            _ => {}
+        }
+    }
+
+    // 0x5015: bits 0-1 are set while the length counters of the pulses are not 0
+    pub fn read_status(&self) -> u8 {
+        (self.pulses[0].length > 0) as u8 | ((self.pulses[1].length > 0) as u8) << 1
+    }
+
+    // called once per CPU cycle
+    pub fn clock(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock();
+            }
+        }
+        self.frame_timer += 1;
+        if self.frame_timer == FRAME_PERIOD {
+            self.frame_timer = 0;
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_frame();
+            }
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        // the pulses use the APU's square channel levels, the PCM channel is 8 bits
+        let pulses = self.pulses[0].output() + self.pulses[1].output();
+        pulses as f32 * 0.00752 + self.pcm as f32 * 0.00168
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        self.pulses[0].encode(buffer);
+        self.pulses[1].encode(buffer);
+        buffer.write_bool(self.pcm_read_mode);
+        buffer.write_u8(self.pcm);
+        buffer.write_u16(self.frame_timer);
+        buffer.write_bool(self.odd_cycle);
+    }
+
+    pub fn decode(&mut self, buffer: &mut Buffer) {
+        self.pulses[0].decode(buffer);
+        self.pulses[1].decode(buffer);
+        self.pcm_read_mode = buffer.read_bool();
+        self.pcm = buffer.read_u8();
+        self.frame_timer = buffer.read_u16();
+        self.odd_cycle = buffer.read_bool();
+    }
+}
@@ -1,24 +1,173 @@
+mod fds_audio;
 mod mapper0;
+mod mapper1;
+mod mapper10;
+mod mapper11;
+mod mapper19;
 mod mapper2;
+mod mapper20;
+mod mapper21;
+mod mapper24;
+mod mapper3;
+mod mapper34;
 mod mapper4;
+mod mapper5;
+mod mapper66;
+mod mapper69;
+mod mapper7;
+mod mapper71;
+mod mapper85;
+mod mapper9;
+mod mmc5_audio;
+mod namco163_audio;
+mod nsf;
+mod registry;
+mod sunsoft5b_audio;
+mod vrc6_audio;
+mod vrc_irq;
 
 pub use mapper0::Mapper0;
+pub use mapper1::Mapper1;
+pub use mapper10::Mapper10;
+pub use mapper11::Mapper11;
+pub use mapper19::Mapper19;
 pub use mapper2::Mapper2;
+pub use mapper20::Mapper20;
+pub use mapper21::Mapper21;
+pub use mapper24::Mapper24;
+pub use mapper3::Mapper3;
+pub use mapper34::Mapper34;
 pub use mapper4::Mapper4;
+pub use mapper5::Mapper5;
+pub use mapper66::Mapper66;
+pub use mapper69::Mapper69;
+pub use mapper7::Mapper7;
+pub use mapper71::Mapper71;
+pub use mapper85::Mapper85;
+pub use mapper9::Mapper9;
+pub use nsf::{NsfMapper, NSF_IDLE_ADDR};
+pub use registry::{MapperConstructor, MapperRegistry};
 
 use crate::{buffer, rom::ROM};
 pub trait Mapper {
-    fn read(&mut self, addr: u16) -> u8;
+    // CPU (0x4020-0xFFFF) and PPU (0x0000-0x1FFF) reads
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    // the value read returns without changing any state, used by debuggers
+    // mappers with side effects on reads override read as well
+    fn peek(&self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, val: u8);
     fn data(&self) -> &ROM;
 
+    // offset in PRG ROM of the byte the CPU reads at addr, None if no PRG ROM is mapped there,
+    // lets debuggers tell the PRG banks apart
+    fn prg_rom_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
     // only used in MMC3
     fn step(&mut self) {}
 
+    // called once per CPU cycle, used by mappers with CPU cycle based IRQ counters
+    fn cpu_clock(&mut self) {}
+
+    // called after every PPU read from the pattern tables (0x0000-0x1FFF)
+    // used by MMC2/MMC4 to switch CHR banks when tiles 0xFD/0xFE are fetched
+    fn chr_fetched(&mut self, _addr: u16) {}
+
+    // called after every CPU write to a PPU register (addr is 0-7)
+    // used by MMC5 to snoop sprite size and rendering enable
+    fn ppu_register_written(&mut self, _addr: u16, _val: u8) {}
+
+    // nametable reads and writes (0x2000-0x3EFF) go through the cartridge first,
+    // vram is the PPU's internal 2KB nametable RAM
+    // returning None/false falls back to the internal VRAM and mirroring
+    fn read_nametable(&mut self, addr: u16, vram: &[u8]) -> Option<u8> {
+        self.peek_nametable(addr, vram)
+    }
+
+    fn peek_nametable(&self, _addr: u16, _vram: &[u8]) -> Option<u8> {
+        None
+    }
+
+    fn write_nametable(&mut self, _addr: u16, _val: u8, _vram: &mut [u8]) -> bool {
+        false
+    }
+
+    // memory kept by the cartridge's battery (usually PRG RAM)
+    // only persisted when the rom's header has the battery flag
+    fn battery_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn battery_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    // copies the 512 byte trainer to the PRG RAM that is mapped at 0x7000-0x71FF on power up,
+    // returns false when the cartridge has no PRG RAM there
+    fn load_trainer(&mut self, _trainer: &[u8]) -> bool {
+        false
+    }
+
+    // expansion audio output, mixed with the APU channels
+    // on the same scale as the APU mixer output (0.0 - 1.0)
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
     fn irq_triggered(&mut self) -> bool {
         false
     }
 
+    // whether the cartridge drives the data bus on a CPU read of addr (0x4020-0xFFFF),
+    // the CPU reads the open bus value otherwise
+    // by default PRG ROM and the PRG RAM returned by battery_ram are driven
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x6000..=0x7FFF => !self.battery_ram().is_empty(),
+            0x8000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    // discrete boards without a ROM enable on writes have bus conflicts
+    fn has_bus_conflicts(&self) -> bool {
+        false
+    }
+
+    // Famicom Disk System drive, cartridges have no disk sides
+    fn disk_side_count(&self) -> usize {
+        0
+    }
+
+    // side in the drive, None when the drive is empty
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+
+    fn insert_disk_side(&mut self, _side: Option<usize>) {}
+
+    // disk sides in .fds format (without the header), including the data written by the game
+    fn disk_image(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_disk_image(&mut self, _image: &[u8]) {}
+
     fn encode(&self, buffer: &mut buffer::Buffer);
     fn decode(&mut self, buffer: &mut buffer::Buffer);
 }
+
+// load_trainer for PRG RAM where 0x7000 is at index, false if the trainer does not fit
+fn copy_trainer(prg_ram: &mut [u8], index: usize, trainer: &[u8]) -> bool {
+    match prg_ram.get_mut(index..index + trainer.len()) {
+        Some(ram) => {
+            ram.copy_from_slice(trainer);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,129 @@
+use crate::buffer::Buffer;
+
+// Namco 163 expansion audio
+// 128 bytes of internal RAM holding the waveforms and registers of up to 8 wavetable channels
+// the RAM is accessed through the data port (0x4800) with the address set at 0xF800
+#[derive(Clone, Debug)]
+pub struct Namco163Audio {
+    internal_ram: [u8; 0x80],
+    ram_addr: u8,
+    auto_increment: bool,
+
+    // one channel is updated every 15 CPU cycles
+    divider: u8,
+    channel: u8,
+    channel_outputs: [i16; 8],
+}
+
+impl Namco163Audio {
+    pub fn new() -> Self {
+        Namco163Audio {
+            internal_ram: [0; 0x80],
+            ram_addr: 0,
+            auto_increment: false,
+            divider: 0,
+            channel: 0,
+            channel_outputs: [0; 8],
+        }
+    }
+
+    // 0xF800: bits 0-6 address, bit 7 auto increment
+    pub fn write_address(&mut self, val: u8) {
+        self.ram_addr = val & 0x7F;
+        self.auto_increment = val & 0x80 != 0;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let val = self.peek_data();
+        self.step_ram_addr();
+        val
+    }
+
+    pub fn peek_data(&self) -> u8 {
+        self.internal_ram[self.ram_addr as usize]
+    }
+
+    pub fn write_data(&mut self, val: u8) {
+        self.internal_ram[self.ram_addr as usize] = val;
+        self.step_ram_addr();
+    }
+
+    fn step_ram_addr(&mut self) {
+        if self.auto_increment {
+            self.ram_addr = (self.ram_addr + 1) & 0x7F;
+        }
+    }
+
+    // channels occupy 8 bytes each from the top of internal RAM,
+    // 0x7F bits 4-6 hold the number of active channels - 1
+    fn channel_count(&self) -> u8 {
+        ((self.internal_ram[0x7F] >> 4) & 0b111) + 1
+    }
+
+    // called once per CPU cycle
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < 15 {
+            return;
+        }
+        self.divider = 0;
+
+        let count = self.channel_count();
+        self.channel = (self.channel + 1) % count;
+        // channel 8 is always first
+        let channel = 7 - self.channel as usize;
+        let base = 0x40 + channel * 8;
+        let ram = &mut self.internal_ram;
+
+        let freq =
+            ram[base] as u32 | (ram[base + 2] as u32) << 8 | ((ram[base + 4] & 0b11) as u32) << 16;
+        let length = (256 - (ram[base + 4] & 0xFC) as u32) << 16;
+        let mut phase =
+            ram[base + 1] as u32 | (ram[base + 3] as u32) << 8 | (ram[base + 5] as u32) << 16;
+        phase = (phase + freq) % length;
+        ram[base + 1] = phase as u8;
+        ram[base + 3] = (phase >> 8) as u8;
+        ram[base + 5] = (phase >> 16) as u8;
+
+        // 4 bit samples, packed low nibble first
+        let sample_addr = (ram[base + 6] as u32 + (phase >> 16)) & 0xFF;
+        let byte = ram[(sample_addr / 2) as usize & 0x7F];
+        let sample = if sample_addr & 1 == 0 {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        };
+        let volume = (ram[base + 7] & 0x0F) as i16;
+        self.channel_outputs[channel] = (sample as i16 - 8) * volume;
+    }
+
+    pub fn output(&self) -> f32 {
+        // the chip outputs one channel at a time, so more channels means quieter channels
+        let count = self.channel_count() as usize;
+        let sum: i16 = self.channel_outputs[8 - count..].iter().sum();
+        // the largest output of a channel (+-120) is roughly as loud as an APU square channel
+        sum as f32 / count as f32 / 120.0 * 0.12
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.internal_ram);
+        buffer.write_u8(self.ram_addr);
+        buffer.write_bool(self.auto_increment);
+        buffer.write_u8(self.divider);
+        buffer.write_u8(self.channel);
+        for output in self.channel_outputs.iter() {
+            buffer.write_u16(*output as u16);
+        }
+    }
+
+    pub fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.internal_ram);
+        self.ram_addr = buffer.read_u8();
+        self.auto_increment = buffer.read_bool();
+        self.divider = buffer.read_u8();
+        self.channel = buffer.read_u8();
+        for output in self.channel_outputs.iter_mut() {
+            *output = buffer.read_u16() as i16;
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use super::{
+    fds_audio::FdsAudio, mmc5_audio::Mmc5Audio, namco163_audio::Namco163Audio,
+    sunsoft5b_audio::Sunsoft5bAudio, vrc6_audio::Vrc6Audio, Mapper,
+};
+use crate::{
+    buffer::Buffer,
+    nsf::{self, Nsf},
+    rom::ROM,
+};
+
+// address of the idle loop the INIT and PLAY routines return to
+pub const NSF_IDLE_ADDR: u16 = 0x5400;
+// JMP NSF_IDLE_ADDR
+const IDLE_LOOP: [u8; 3] = [0x4C, NSF_IDLE_ADDR as u8, (NSF_IDLE_ADDR >> 8) as u8];
+
+// the NSF "cartridge", there is no real board behind it
+// - the program data is split into 4KB banks, selected for 0x8000-0xFFFF at 0x5FF8-0x5FFF
+// - 8KB PRG RAM at 0x6000-0x7FFF
+// - FDS tunes have RAM at 0x6000-0xFFFF instead, writing 0x5FF6-0x5FFF copies a bank into it
+// - the expansion audio chips of the tune are mapped at their usual addresses,
+//   the VRC7 is not emulated and its tunes are rejected by Nsf::new
+#[derive(Clone, Debug)]
+pub struct NsfMapper {
+    banks: [u8; 10], // 0x6000-0xFFFF
+    bankswitched: bool,
+    fds: bool,
+    expansion: u8,
+    prg_ram: Vec<u8>,
+
+    // MMC5 ExRAM (0x5C00-0x5FF5) and 8 bit multiplier (0x5205, 0x5206)
+    exram: Vec<u8>,
+    multiplicand: u8,
+    multiplier: u8,
+
+    mmc5: Mmc5Audio,
+    vrc6: Vrc6Audio,
+    fds_audio: FdsAudio,
+    namco163: Namco163Audio,
+    sunsoft5b: Sunsoft5bAudio,
+
+    // the program data is kept as PRG ROM
+    rom: ROM,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Self {
+        let fds = nsf.expansion & nsf::FDS != 0;
+        // FDS tunes start at 0x6000, the others at 0x8000
+        let base = if fds { 0x6000 } else { 0x8000 };
+        let first_slot = if fds { 0 } else { 2 };
+
+        // bankswitched data starts at the load address in its bank,
+        // other data is placed at the load address with banks in order
+        let (padding, banks) = match nsf.banks {
+            Some(banks) => {
+                let mut slots = [0; 10];
+                slots[2..].copy_from_slice(&banks);
+                slots[0] = banks[6];
+                slots[1] = banks[7];
+                ((nsf.load_addr & 0x0FFF) as usize, slots)
+            }
+            None => {
+                let mut slots = [0; 10];
+                for (bank, slot) in slots[first_slot..].iter_mut().enumerate() {
+                    *slot = bank as u8;
+                }
+                ((nsf.load_addr - base) as usize, slots)
+            }
+        };
+        let mut prg_rom = vec![0; padding];
+        prg_rom.extend_from_slice(&nsf.data);
+        prg_rom.resize(prg_rom.len().next_multiple_of(0x1000), 0);
+
+        let rom = ROM {
+            prg_rom_banks: prg_rom.len().div_ceil(0x4000) as u16,
+            prg_rom,
+            prg_ram_size: if fds { 0xA000 } else { 0x2000 },
+            timing: nsf.timing,
+            ..Default::default()
+        };
+        let mut mapper = NsfMapper {
+            banks,
+            bankswitched: nsf.banks.is_some(),
+            fds,
+            expansion: nsf.expansion,
+            prg_ram: vec![0; rom.prg_ram_size as usize],
+            exram: vec![0; 0x400],
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            mmc5: Mmc5Audio::new(),
+            vrc6: Vrc6Audio::new(),
+            fds_audio: FdsAudio::new(),
+            namco163: Namco163Audio::new(),
+            sunsoft5b: Sunsoft5bAudio::new(),
+            rom,
+        };
+        if fds {
+            for slot in 0..10 {
+                mapper.load_fds_bank(slot);
+            }
+        }
+        mapper
+    }
+
+    fn has(&self, chip: u8) -> bool {
+        self.expansion & chip != 0
+    }
+
+    fn bank_offset(&self, bank: u8) -> usize {
+        let bank_count = self.rom.prg_rom.len() / 0x1000;
+        (bank as usize % bank_count) * 0x1000
+    }
+
+    // 4KB slots at 0x8000-0xFFFF
+    fn prg_offset(&self, addr: u16) -> usize {
+        let slot = ((addr - 0x6000) / 0x1000) as usize;
+        self.bank_offset(self.banks[slot]) + (addr & 0x0FFF) as usize
+    }
+
+    // copies the selected bank into the FDS RAM
+    fn load_fds_bank(&mut self, slot: usize) {
+        let offset = self.bank_offset(self.banks[slot]);
+        self.prg_ram[slot * 0x1000..(slot + 1) * 0x1000]
+            .copy_from_slice(&self.rom.prg_rom[offset..offset + 0x1000]);
+    }
+
+    fn write_bank(&mut self, addr: u16, val: u8) {
+        let slot = (addr - 0x5FF6) as usize;
+        // 0x5FF6 and 0x5FF7 only exist for FDS tunes
+        if slot < 2 && !self.fds {
+            return;
+        }
+        self.banks[slot] = val;
+        if self.fds {
+            self.load_fds_bank(slot);
+        }
+    }
+
+    fn write_expansion(&mut self, addr: u16, val: u8) {
+        if self.has(nsf::FDS) && (0x4040..=0x408A).contains(&addr) {
+            self.fds_audio.write(addr, val);
+        }
+        if self.has(nsf::NAMCO163) {
+            match addr {
+                0x4800..=0x4FFF => self.namco163.write_data(val),
+                0xF800..=0xFFFF => self.namco163.write_address(val),
+                _ => {}
+            }
+        }
+        if self.has(nsf::MMC5) {
+            match addr {
+                0x5000..=0x5015 => self.mmc5.write(addr, val),
+                0x5205 => self.multiplicand = val,
+                0x5206 => self.multiplier = val,
+                0x5C00..=0x5FF5 => self.exram[(addr - 0x5C00) as usize] = val,
+                _ => {}
+            }
+        }
+        if self.has(nsf::VRC6) {
+            if let 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 = addr {
+                self.vrc6.write(addr, val);
+            }
+        }
+        if self.has(nsf::SUNSOFT5B) {
+            match addr {
+                0xC000..=0xDFFF => self.sunsoft5b.select(val),
+                0xE000..=0xFFFF => self.sunsoft5b.write(val),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn cpu_clock(&mut self) {
+        if self.has(nsf::MMC5) {
+            self.mmc5.clock();
+        }
+        if self.has(nsf::VRC6) {
+            self.vrc6.clock();
+        }
+        if self.has(nsf::FDS) {
+            self.fds_audio.clock();
+        }
+        if self.has(nsf::NAMCO163) {
+            self.namco163.clock();
+        }
+        if self.has(nsf::SUNSOFT5B) {
+            self.sunsoft5b.clock();
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // the data port moves to the next byte of the internal RAM
+            0x4800..=0x4FFF if self.has(nsf::NAMCO163) => self.namco163.read_data(),
+            _ => self.peek(addr),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F | 0x4090 | 0x4092 if self.has(nsf::FDS) => self.fds_audio.read(addr),
+            0x4800..=0x4FFF if self.has(nsf::NAMCO163) => self.namco163.peek_data(),
+
+            // MMC5 audio status, multiplier and ExRAM
+            0x5015 if self.has(nsf::MMC5) => self.mmc5.read_status(),
+            0x5205 if self.has(nsf::MMC5) => {
+                (self.multiplicand as u16 * self.multiplier as u16) as u8
+            }
+            0x5206 if self.has(nsf::MMC5) => {
+                ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8
+            }
+            0x5C00..=0x5FF5 if self.has(nsf::MMC5) => self.exram[(addr - 0x5C00) as usize],
+
+            // idle loop
+            NSF_IDLE_ADDR..=0x5402 => IDLE_LOOP[(addr - NSF_IDLE_ADDR) as usize],
+
+            // FDS RAM
+            0x6000..=0xFFFF if self.fds => self.prg_ram[(addr - 0x6000) as usize],
+
+            // PRG RAM
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+
+            // PRG ROM banks
+            0x8000..=0xFFFF => self.rom.prg_rom[self.prg_offset(addr)],
+
+            _ => 0,
+        }
+    }
+
+    // FDS tunes run from RAM
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF if !self.fds => Some(self.prg_offset(addr)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.write_expansion(addr, val);
+        match addr {
+            0x5FF6..=0x5FFF if self.bankswitched || self.fds => self.write_bank(addr, val),
+            0x6000..=0xFFFF if self.fds => self.prg_ram[(addr - 0x6000) as usize] = val,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = val,
+            _ => {}
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let mut output = 0.0;
+        if self.has(nsf::MMC5) {
+            output += self.mmc5.output();
+        }
+        if self.has(nsf::VRC6) {
+            output += self.vrc6.output();
+        }
+        if self.has(nsf::FDS) {
+            output += self.fds_audio.output();
+        }
+        if self.has(nsf::NAMCO163) {
+            output += self.namco163.output();
+        }
+        if self.has(nsf::SUNSOFT5B) {
+            output += self.sunsoft5b.output();
+        }
+        output
+    }
+
+    fn drives_cpu_bus(&self, addr: u16) -> bool {
+        match addr {
+            0x4040..=0x407F | 0x4090 | 0x4092 => self.has(nsf::FDS),
+            0x4800..=0x4FFF => self.has(nsf::NAMCO163),
+            0x5015 | 0x5205..=0x5206 | 0x5C00..=0x5FF5 => self.has(nsf::MMC5),
+            NSF_IDLE_ADDR..=0x5402 => true,
+            0x6000..=0xFFFF => true,
+            _ => false,
+        }
+    }
+
+    fn data(&self) -> &ROM {
+        &self.rom
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.banks);
+        buffer.write_u8_arr(&self.prg_ram);
+        buffer.write_u8_arr(&self.exram);
+        buffer.write_u8(self.multiplicand);
+        buffer.write_u8(self.multiplier);
+        self.mmc5.encode(buffer);
+        self.vrc6.encode(buffer);
+        self.fds_audio.encode(buffer);
+        self.namco163.encode(buffer);
+        self.sunsoft5b.encode(buffer);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.banks);
+        buffer.read_u8_arr(&mut self.prg_ram);
+        buffer.read_u8_arr(&mut self.exram);
+        self.multiplicand = buffer.read_u8();
+        self.multiplier = buffer.read_u8();
+        self.mmc5.decode(buffer);
+        self.vrc6.decode(buffer);
+        self.fds_audio.decode(buffer);
+        self.namco163.decode(buffer);
+        self.sunsoft5b.decode(buffer);
+    }
+}
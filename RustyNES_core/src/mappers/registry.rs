@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use super::{
+    Mapper0, Mapper1, Mapper10, Mapper11, Mapper19, Mapper2, Mapper20, Mapper21, Mapper24, Mapper3,
+    Mapper34, Mapper4, Mapper5, Mapper66, Mapper69, Mapper7, Mapper71, Mapper85, Mapper9,
+};
+use crate::rom::{Cartridge, RomError, ROM};
+
+// builds the cartridge for a parsed rom
+pub type MapperConstructor = fn(ROM) -> Cartridge;
+
+// maps mapper ids (optionally narrowed down to a submapper) to the constructors of their boards
+// MapperRegistry::new() comes with all the built-in mappers registered,
+// other crates can add their own boards or replace the built-in ones
+#[derive(Clone, Debug)]
+pub struct MapperRegistry {
+    // a submapper of None matches any submapper
+    constructors: HashMap<(u16, Option<u8>), MapperConstructor>,
+}
+
+impl Default for MapperRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapperRegistry {
+    // registry with the built-in mappers
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(0, |rom| Box::new(Mapper0::new(rom)));
+        registry.register(1, |rom| Box::new(Mapper1::new(rom)));
+        registry.register(2, |rom| Box::new(Mapper2::new(rom)));
+        registry.register(3, |rom| Box::new(Mapper3::new(rom)));
+        registry.register(4, |rom| Box::new(Mapper4::new(rom)));
+        registry.register(5, |rom| Box::new(Mapper5::new(rom)));
+        registry.register(7, |rom| Box::new(Mapper7::new(rom)));
+        registry.register(9, |rom| Box::new(Mapper9::new(rom)));
+        registry.register(10, |rom| Box::new(Mapper10::new(rom)));
+        registry.register(11, |rom| Box::new(Mapper11::new(rom)));
+        registry.register(19, |rom| Box::new(Mapper19::new(rom)));
+        registry.register(20, |rom| Box::new(Mapper20::new(rom)));
+        for id in [21, 22, 23, 25] {
+            registry.register(id, |rom| Box::new(Mapper21::new(rom)));
+        }
+        for id in [24, 26] {
+            registry.register(id, |rom| Box::new(Mapper24::new(rom)));
+        }
+        registry.register(34, |rom| Box::new(Mapper34::new(rom)));
+        registry.register(66, |rom| Box::new(Mapper66::new(rom)));
+        registry.register(69, |rom| Box::new(Mapper69::new(rom)));
+        registry.register(71, |rom| Box::new(Mapper71::new(rom)));
+        registry.register(85, |rom| Box::new(Mapper85::new(rom)));
+        registry
+    }
+
+    // registry without any mapper
+    pub fn empty() -> Self {
+        MapperRegistry {
+            constructors: HashMap::new(),
+        }
+    }
+
+    // register a board for every submapper of a mapper id,
+    // replaces any board previously registered for it
+    pub fn register(&mut self, mapper_id: u16, constructor: MapperConstructor) {
+        self.constructors.insert((mapper_id, None), constructor);
+    }
+
+    // register a board for one submapper only,
+    // it takes precedence over a board registered for the whole mapper id
+    pub fn register_submapper(
+        &mut self,
+        mapper_id: u16,
+        submapper: u8,
+        constructor: MapperConstructor,
+    ) {
+        self.constructors
+            .insert((mapper_id, Some(submapper)), constructor);
+    }
+
+    pub fn get(&self, mapper_id: u16, submapper: u8) -> Option<MapperConstructor> {
+        self.constructors
+            .get(&(mapper_id, Some(submapper)))
+            .or_else(|| self.constructors.get(&(mapper_id, None)))
+            .copied()
+    }
+
+    pub fn contains(&self, mapper_id: u16, submapper: u8) -> bool {
+        self.get(mapper_id, submapper).is_some()
+    }
+
+    // build the cartridge for the rom's mapper id and submapper,
+    // the trainer is copied to the PRG RAM at 0x7000-0x71FF
+    pub fn create(&self, rom: ROM) -> Result<Cartridge, RomError> {
+        let constructor = self
+            .get(rom.mapper_id, rom.submapper)
+            .ok_or(RomError::UnsupportedMapper(rom.mapper_id))?;
+        let mut cartridge = constructor(rom);
+        let trainer = cartridge.data().trainer.clone();
+        if !trainer.is_empty() && !cartridge.load_trainer(&trainer) {
+            return Err(RomError::UnsupportedTrainer(cartridge.data().mapper_id));
+        }
+        Ok(cartridge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(mapper_id: u16) -> ROM {
+        ROM {
+            prg_rom: vec![0; 0x8000],
+            prg_rom_banks: 2,
+            chr_rom: vec![0; 0x2000],
+            chr_rom_banks: 1,
+            prg_ram_size: 0x2000,
+            mapper_id,
+            trainer: (0..0x200).map(|i| i as u8).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn create_loads_trainer() {
+        let mut cartridge = MapperRegistry::new().create(rom(0)).unwrap();
+        assert!((0..0x200u16).all(|i| cartridge.read(0x7000 + i) == i as u8));
+    }
+
+    #[test]
+    fn create_rejects_unsupported() {
+        // CNROM has no PRG RAM for the trainer
+        assert_eq!(
+            MapperRegistry::new().create(rom(3)).err(),
+            Some(RomError::UnsupportedTrainer(3))
+        );
+        assert_eq!(
+            MapperRegistry::empty().create(rom(0)).err(),
+            Some(RomError::UnsupportedMapper(0))
+        );
+    }
+}
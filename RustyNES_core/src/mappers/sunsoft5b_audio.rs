@@ -0,0 +1,205 @@
+use crate::buffer::Buffer;
+
+// Sunsoft 5B expansion audio, a YM2149F (AY-3-8910 family) inside the FME-7
+// 3 square wave channels, a shared noise generator and a shared envelope
+// registers are selected by writing 0xC000-0xDFFF and written through 0xE000-0xFFFF
+#[derive(Clone, Debug)]
+pub struct Sunsoft5bAudio {
+    registers: [u8; 16],
+    selected: u8,
+
+    // all generators run at CPU / 16
+    divider: u8,
+
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+
+    noise_counter: u8,
+    noise_shift: u32, // 17 bit LFSR
+
+    envelope_counter: u16,
+    envelope_step: u8, // 0-31
+    envelope_holding: bool,
+    envelope_attack: bool,
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Self {
+        Sunsoft5bAudio {
+            registers: [0; 16],
+            selected: 0,
+            divider: 0,
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_holding: false,
+            envelope_attack: false,
+        }
+    }
+
+    pub fn select(&mut self, val: u8) {
+        self.selected = val & 0x0F;
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.registers[self.selected as usize] = val;
+        // writing the envelope shape restarts the envelope
+        if self.selected == 0x0D {
+            self.envelope_step = 0;
+            self.envelope_counter = 0;
+            self.envelope_holding = false;
+            self.envelope_attack = val & 0b0100 != 0;
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let low = self.registers[channel * 2] as u16;
+        let high = (self.registers[channel * 2 + 1] & 0x0F) as u16;
+        (high << 8 | low).max(1)
+    }
+
+    fn envelope_period(&self) -> u16 {
+        (self.registers[0x0C] as u16 * 256 + self.registers[0x0B] as u16).max(1)
+    }
+
+    // called once per CPU cycle
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < 16 {
+            return;
+        }
+        self.divider = 0;
+
+        // tones toggle once per period, giving a frequency of CPU / (32 * period)
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.tone_period(channel) {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        // noise is clocked at half the tone rate
+        self.noise_counter += 1;
+        let noise_period = (self.registers[0x06] & 0x1F).max(1) * 2;
+        if self.noise_counter >= noise_period {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | feedback << 16;
+        }
+
+        // the envelope has 32 steps, 2 per period
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period() / 2 {
+            self.envelope_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < 31 {
+            self.envelope_step += 1;
+            return;
+        }
+
+        // end of a ramp, bits of the shape register: continue, attack, alternate, hold
+        let shape = self.registers[0x0D];
+        let continue_ = shape & 0b1000 != 0;
+        let alternate = shape & 0b0010 != 0;
+        let hold = shape & 0b0001 != 0;
+        if !continue_ {
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+            self.envelope_step = 31;
+        } else if hold {
+            self.envelope_holding = true;
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 31;
+        } else {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    // 5 bit envelope level, rising when attacking
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            31 - self.envelope_step
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let mixer = self.registers[0x07];
+        let noise = self.noise_shift & 1 != 0;
+        let mut output = 0.0;
+        for channel in 0..3 {
+            let tone_disabled = mixer & (1 << channel) != 0;
+            let noise_disabled = mixer & (8 << channel) != 0;
+            let tone_out = self.tone_outputs[channel] || tone_disabled;
+            let noise_out = noise || noise_disabled;
+            if !(tone_out && noise_out) {
+                continue;
+            }
+            let volume = self.registers[0x08 + channel];
+            // levels are logarithmic, 1.5dB per envelope step and 3dB per volume step
+            let level = if volume & 0x10 != 0 {
+                self.envelope_level()
+            } else {
+                (volume & 0x0F) * 2 + 1
+            };
+            if level > 1 {
+                output += 10f32.powf((level as f32 - 31.0) * 1.5 / 20.0);
+            }
+        }
+        // full volume on a channel is roughly as loud as an APU square channel
+        output * 0.12
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8_arr(&self.registers);
+        buffer.write_u8(self.selected);
+        buffer.write_u8(self.divider);
+        for counter in self.tone_counters.iter() {
+            buffer.write_u16(*counter);
+        }
+        for output in self.tone_outputs.iter() {
+            buffer.write_bool(*output);
+        }
+        buffer.write_u8(self.noise_counter);
+        buffer.write_u32(self.noise_shift);
+        buffer.write_u16(self.envelope_counter);
+        buffer.write_u8(self.envelope_step);
+        buffer.write_bool(self.envelope_holding);
+        buffer.write_bool(self.envelope_attack);
+    }
+
+    pub fn decode(&mut self, buffer: &mut Buffer) {
+        buffer.read_u8_arr(&mut self.registers);
+        self.selected = buffer.read_u8();
+        self.divider = buffer.read_u8();
+        for counter in self.tone_counters.iter_mut() {
+            *counter = buffer.read_u16();
+        }
+        for output in self.tone_outputs.iter_mut() {
+            *output = buffer.read_bool();
+        }
+        self.noise_counter = buffer.read_u8();
+        self.noise_shift = buffer.read_u32();
+        self.envelope_counter = buffer.read_u16();
+        self.envelope_step = buffer.read_u8();
+        self.envelope_holding = buffer.read_bool();
+        self.envelope_attack = buffer.read_bool();
+    }
+}
@@ -0,0 +1,218 @@
+use crate::buffer::Buffer;
+
+// Konami VRC6 expansion audio
+// two pulse channels with 8 duty cycles and a sawtooth channel
+// 0x9000-0x9002: pulse 1, 0xA000-0xA002: pulse 2, 0xB000-0xB002: sawtooth,
+// 0x9003: frequency control of all channels
+#[derive(Clone, Debug)]
+pub struct Vrc6Audio {
+    pulses: [Vrc6Pulse; 2],
+    saw: Vrc6Saw,
+    halted: bool,
+    frequency_shift: u8, // 0x9003 bits 1-2, periods are divided by 16 or 256
+}
+
+#[derive(Clone, Debug, Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool, // constant volume output
+    period: u16,       // 12 bits
+    enabled: bool,
+    timer: u16,
+    step: u8, // counts down from 15
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.volume = val & 0x0F;
+                self.duty = (val >> 4) & 0b111;
+                self.ignore_duty = val & 0x80 != 0;
+            }
+            1 => self.period = (self.period & 0x0F00) | val as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((val & 0x0F) as u16) << 8;
+                self.enabled = val & 0x80 != 0;
+                // disabling the channel resets the duty cycle
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.volume);
+        buffer.write_u8(self.duty);
+        buffer.write_bool(self.ignore_duty);
+        buffer.write_u16(self.period);
+        buffer.write_bool(self.enabled);
+        buffer.write_u16(self.timer);
+        buffer.write_u8(self.step);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.volume = buffer.read_u8();
+        self.duty = buffer.read_u8();
+        self.ignore_duty = buffer.read_bool();
+        self.period = buffer.read_u16();
+        self.enabled = buffer.read_bool();
+        self.timer = buffer.read_u16();
+        self.step = buffer.read_u8();
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Vrc6Saw {
+    rate: u8, // added to the accumulator every other step
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8, // 0-13
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => self.rate = val & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | val as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((val & 0x0F) as u16) << 8;
+                self.enabled = val & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    // the accumulator is increased 6 times and reset on the 7th time
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    // high 5 bits of the accumulator
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+
+    fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.rate);
+        buffer.write_u16(self.period);
+        buffer.write_bool(self.enabled);
+        buffer.write_u16(self.timer);
+        buffer.write_u8(self.step);
+        buffer.write_u8(self.accumulator);
+    }
+
+    fn decode(&mut self, buffer: &mut Buffer) {
+        self.rate = buffer.read_u8();
+        self.period = buffer.read_u16();
+        self.enabled = buffer.read_bool();
+        self.timer = buffer.read_u16();
+        self.step = buffer.read_u8();
+        self.accumulator = buffer.read_u8();
+    }
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Vrc6Audio {
+            pulses: [Vrc6Pulse::default(), Vrc6Pulse::default()],
+            saw: Vrc6Saw::default(),
+            halted: false,
+            frequency_shift: 0,
+        }
+    }
+
+    // reg is the register as seen by VRC6a (0x9000-0xB002)
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0x9003 => {
+                self.halted = val & 0x01 != 0;
+                self.frequency_shift = if val & 0x04 != 0 {
+                    8
+                } else if val & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000..=0x9002 => self.pulses[0].write(reg & 0b11, val),
+            0xA000..=0xA002 => self.pulses[1].write(reg & 0b11, val),
+            0xB000..=0xB002 => self.saw.write(reg & 0b11, val),
+            _ => {}
+        }
+    }
+
+    // called once per CPU cycle
+    pub fn clock(&mut self) {
+        if self.halted {
+            return;
+        }
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock(self.frequency_shift);
+        }
+        self.saw.clock(self.frequency_shift);
+    }
+
+    pub fn output(&self) -> f32 {
+        // 0-61, a pulse channel at full volume is about as loud as an APU square channel
+        let level = self.pulses[0].output() + self.pulses[1].output() + self.saw.output();
+        level as f32 * 0.00752
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        self.pulses[0].encode(buffer);
+        self.pulses[1].encode(buffer);
+        self.saw.encode(buffer);
+        buffer.write_bool(self.halted);
+        buffer.write_u8(self.frequency_shift);
+    }
+
+    pub fn decode(&mut self, buffer: &mut Buffer) {
+        self.pulses[0].decode(buffer);
+        self.pulses[1].decode(buffer);
+        self.saw.decode(buffer);
+        self.halted = buffer.read_bool();
+        self.frequency_shift = buffer.read_u8();
+    }
+}
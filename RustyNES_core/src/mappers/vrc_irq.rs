@@ -0,0 +1,91 @@
+use crate::buffer::Buffer;
+
+// IRQ counter shared by the Konami VRC4, VRC6 and VRC7
+// an 8-bit up counter clocked by the CPU, either every cycle (cycle mode)
+// or through a prescaler that approximates one scanline (341 PPU dots = 113.67 CPU cycles)
+#[derive(Clone, Debug)]
+pub struct VrcIrq {
+    pub latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enabled_after_ack: bool,
+    cycle_mode: bool,
+    pub pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            enabled: false,
+            enabled_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_control(&mut self, val: u8) {
+        self.enabled_after_ack = val & 0b001 != 0;
+        self.enabled = val & 0b010 != 0;
+        self.cycle_mode = val & 0b100 != 0;
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enabled_after_ack;
+    }
+
+    // called once per CPU cycle
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            // 3 PPU dots per CPU cycle
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u8(self.latch);
+        buffer.write_u8(self.counter);
+        buffer.write_u16(self.prescaler as u16);
+        buffer.write_bool(self.enabled);
+        buffer.write_bool(self.enabled_after_ack);
+        buffer.write_bool(self.cycle_mode);
+        buffer.write_bool(self.pending);
+    }
+
+    pub fn decode(&mut self, buffer: &mut Buffer) {
+        self.latch = buffer.read_u8();
+        self.counter = buffer.read_u8();
+        self.prescaler = buffer.read_u16() as i16;
+        self.enabled = buffer.read_bool();
+        self.enabled_after_ack = buffer.read_bool();
+        self.cycle_mode = buffer.read_bool();
+        self.pending = buffer.read_bool();
+    }
+}
@@ -0,0 +1,536 @@
+// NSF and NSFe music rips
+// the music code of a game with an INIT routine (called once per track)
+// and a PLAY routine (called at the play rate, usually once per frame)
+pub mod player;
+
+pub use player::NsfPlayer;
+
+use crate::rom::Timing;
+use std::fmt;
+
+// expansion chip flags (header byte 0x7B)
+pub const VRC6: u8 = 0x01;
+pub const VRC7: u8 = 0x02;
+pub const FDS: u8 = 0x04;
+pub const MMC5: u8 = 0x08;
+pub const NAMCO163: u8 = 0x10;
+pub const SUNSOFT5B: u8 = 0x20;
+
+// play rates in microseconds of NTSC and PAL frames
+const NTSC_SPEED: u16 = 16639;
+const PAL_SPEED: u16 = 19997;
+
+#[derive(Clone, Debug)]
+pub struct Nsf {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+
+    pub track_count: u8,
+    pub starting_track: u8, // starts at 0
+    // NSFe only, empty strings and None for tracks without a label or length
+    pub track_titles: Vec<String>,
+    pub track_lengths: Vec<Option<u32>>, // milliseconds
+
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    // time between PLAY calls in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // initial 4KB banks of 0x8000-0xFFFF, None if the tune does not use bankswitching
+    pub banks: Option<[u8; 8]>,
+    pub timing: Timing,
+    pub expansion: u8,
+
+    pub data: Vec<u8>,
+}
+
+// errors reported while reading an NSF or NSFe file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NsfError {
+    // file does not start with "NESM\x1A" or "NSFE"
+    BadMagic,
+    // header or chunks are truncated or invalid
+    Invalid(String),
+    // track number out of range
+    InvalidTrack(u8),
+    // the tune needs an expansion chip that is not emulated (VRC7)
+    UnsupportedExpansion(&'static str),
+}
+
+impl fmt::Display for NsfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NsfError::BadMagic => write!(f, "not an NSF or NSFe file"),
+            NsfError::Invalid(reason) => write!(f, "invalid NSF file: {}", reason),
+            NsfError::InvalidTrack(track) => write!(f, "track {} does not exist", track + 1),
+            NsfError::UnsupportedExpansion(chip) => {
+                write!(f, "{} expansion audio is not supported", chip)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NsfError {}
+
+pub fn is_nsf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"NESM\x1A") || bytes.starts_with(b"NSFE")
+}
+
+impl Nsf {
+    pub fn new(bytes: &[u8]) -> Result<Nsf, NsfError> {
+        if bytes.starts_with(b"NESM\x1A") {
+            Nsf::parse_nsf(bytes)
+        } else if bytes.starts_with(b"NSFE") {
+            Nsf::parse_nsfe(bytes)
+        } else {
+            Err(NsfError::BadMagic)
+        }
+    }
+
+    /*
+    NSF header (128 bytes):
+    0x00: "NESM\x1A", 0x05: version, 0x06: track count, 0x07: starting track (from 1)
+    0x08: load address, 0x0A: init address, 0x0C: play address
+    0x0E: title, 0x2E: artist, 0x4E: copyright (32 bytes each, zero terminated)
+    0x6E: NTSC speed, 0x70: initial banks (8 bytes), 0x78: PAL speed
+    0x7A: bit 0 PAL, bit 1 NTSC and PAL
+    0x7B: expansion chips
+    0x7D: NSF2 data length (0 = to the end of the file)
+    */
+    fn parse_nsf(bytes: &[u8]) -> Result<Nsf, NsfError> {
+        if bytes.len() < 0x80 {
+            return Err(NsfError::Invalid(format!(
+                "file is {} bytes, header needs 128",
+                bytes.len()
+            )));
+        }
+        let u16_at = |index: usize| u16::from_le_bytes([bytes[index], bytes[index + 1]]);
+        let banks: [u8; 8] = bytes[0x70..0x78].try_into().unwrap();
+        let data_len =
+            bytes[0x7D] as usize | (bytes[0x7E] as usize) << 8 | (bytes[0x7F] as usize) << 16;
+        let data = match data_len {
+            0 => &bytes[0x80..],
+            len => bytes.get(0x80..0x80 + len).unwrap_or(&bytes[0x80..]),
+        };
+        let nsf = Nsf {
+            title: string(&bytes[0x0E..0x2E]),
+            artist: string(&bytes[0x2E..0x4E]),
+            copyright: string(&bytes[0x4E..0x6E]),
+            track_count: bytes[0x06],
+            starting_track: bytes[0x07].saturating_sub(1),
+            track_titles: Vec::new(),
+            track_lengths: Vec::new(),
+            load_addr: u16_at(0x08),
+            init_addr: u16_at(0x0A),
+            play_addr: u16_at(0x0C),
+            ntsc_speed: u16_at(0x6E),
+            pal_speed: u16_at(0x78),
+            banks: banks.iter().any(|&bank| bank != 0).then_some(banks),
+            timing: timing(bytes[0x7A]),
+            expansion: bytes[0x7B],
+            data: data.to_vec(),
+        };
+        nsf.validate()
+    }
+
+    /*
+    NSFe: "NSFE", then chunks: length (u32), id (4 bytes), data
+    INFO: load, init, play address, timing, expansion chips, track count, starting track (from 0)
+    DATA: program data
+    BANK: initial banks (up to 8 bytes)
+    RATE: NTSC speed, PAL speed
+    auth: title, artist, copyright, ripper (zero terminated strings)
+    tlbl: track titles (zero terminated strings)
+    time: track lengths in milliseconds (i32, negative = unknown)
+    NEND: end of the file
+    */
+    fn parse_nsfe(bytes: &[u8]) -> Result<Nsf, NsfError> {
+        let mut nsf = Nsf {
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            track_count: 0,
+            starting_track: 0,
+            track_titles: Vec::new(),
+            track_lengths: Vec::new(),
+            load_addr: 0,
+            init_addr: 0,
+            play_addr: 0,
+            ntsc_speed: NTSC_SPEED,
+            pal_speed: PAL_SPEED,
+            banks: None,
+            timing: Timing::Ntsc,
+            expansion: 0,
+            data: Vec::new(),
+        };
+        let mut has_info = false;
+
+        let mut index = 4;
+        while index + 8 <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[index..index + 4].try_into().unwrap()) as usize;
+            let id = &bytes[index + 4..index + 8];
+            // len comes from the file, the end is checked so it can not overflow
+            let end = (index + 8)
+                .checked_add(len)
+                .filter(|&end| end <= bytes.len())
+                .ok_or_else(|| {
+                    NsfError::Invalid(format!(
+                        "NSFe chunk {} is truncated",
+                        String::from_utf8_lossy(id)
+                    ))
+                })?;
+            let data = &bytes[index + 8..end];
+            index = end;
+
+            match id {
+                b"INFO" => {
+                    if data.len() < 9 {
+                        return Err(NsfError::Invalid("INFO chunk is too short".to_string()));
+                    }
+                    let u16_at = |index: usize| u16::from_le_bytes([data[index], data[index + 1]]);
+                    nsf.load_addr = u16_at(0);
+                    nsf.init_addr = u16_at(2);
+                    nsf.play_addr = u16_at(4);
+                    nsf.timing = timing(data[6]);
+                    nsf.expansion = data[7];
+                    nsf.track_count = data[8];
+                    nsf.starting_track = data.get(9).copied().unwrap_or(0);
+                    has_info = true;
+                }
+                b"DATA" => nsf.data = data.to_vec(),
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    let len = data.len().min(8);
+                    banks[..len].copy_from_slice(&data[..len]);
+                    nsf.banks = Some(banks);
+                }
+                b"RATE" if data.len() >= 2 => {
+                    nsf.ntsc_speed = u16::from_le_bytes([data[0], data[1]]);
+                    if data.len() >= 4 {
+                        nsf.pal_speed = u16::from_le_bytes([data[2], data[3]]);
+                    }
+                }
+                b"auth" => {
+                    let mut strings = data.split(|&b| b == 0).map(string);
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => nsf.track_titles = data.split(|&b| b == 0).map(string).collect(),
+                b"time" => {
+                    nsf.track_lengths = data
+                        .chunks_exact(4)
+                        .map(|time| {
+                            let time = i32::from_le_bytes(time.try_into().unwrap());
+                            (time >= 0).then_some(time as u32)
+                        })
+                        .collect()
+                }
+                b"NEND" => break,
+                // chunks starting with an uppercase letter must be understood to play the file
+                [first, ..] if first.is_ascii_uppercase() => {
+                    return Err(NsfError::Invalid(format!(
+                        "unknown NSFe chunk {}",
+                        String::from_utf8_lossy(id)
+                    )));
+                }
+                // other optional chunks (plst, fade, text ...) are not needed to play the file
+                _ => {}
+            }
+        }
+
+        if !has_info {
+            return Err(NsfError::Invalid("no INFO chunk".to_string()));
+        }
+        nsf.track_titles
+            .resize(nsf.track_count as usize, String::new());
+        nsf.track_lengths.resize(nsf.track_count as usize, None);
+        nsf.validate()
+    }
+
+    fn validate(mut self) -> Result<Nsf, NsfError> {
+        if self.track_count == 0 {
+            return Err(NsfError::Invalid("no tracks".to_string()));
+        }
+        if self.data.is_empty() {
+            return Err(NsfError::Invalid("no program data".to_string()));
+        }
+        // the VRC7 FM synthesizer is not emulated, the tune would play without its music
+        if self.expansion & VRC7 != 0 {
+            return Err(NsfError::UnsupportedExpansion("VRC7"));
+        }
+        // FDS tunes can load into its RAM at 0x6000, other tunes are in ROM at 0x8000-0xFFFF
+        let lowest_addr = if self.expansion & FDS != 0 {
+            0x6000
+        } else {
+            0x8000
+        };
+        if self.banks.is_none() && self.load_addr < lowest_addr {
+            return Err(NsfError::Invalid(format!(
+                "load address {:04X} is below {:04X}",
+                self.load_addr, lowest_addr
+            )));
+        }
+        if self.starting_track >= self.track_count {
+            self.starting_track = 0;
+        }
+        if self.ntsc_speed == 0 {
+            self.ntsc_speed = NTSC_SPEED;
+        }
+        if self.pal_speed == 0 {
+            self.pal_speed = PAL_SPEED;
+        }
+        Ok(self)
+    }
+
+    // the tune only plays at the PAL play rate
+    pub fn is_pal(&self) -> bool {
+        self.timing == Timing::Pal
+    }
+
+    // microseconds between PLAY calls
+    pub fn play_speed(&self) -> u16 {
+        if self.is_pal() {
+            self.pal_speed
+        } else {
+            self.ntsc_speed
+        }
+    }
+
+    pub fn track_title(&self, track: u8) -> Option<&str> {
+        self.track_titles
+            .get(track as usize)
+            .map(|title| title.as_str())
+            .filter(|title| !title.is_empty())
+    }
+}
+
+// zero terminated string
+fn string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// 0x7A: bit 0 PAL, bit 1 NTSC and PAL
+fn timing(flags: u8) -> Timing {
+    if flags & 0b10 != 0 {
+        Timing::Multi
+    } else if flags & 0b01 != 0 {
+        Timing::Pal
+    } else {
+        Timing::Ntsc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappers::{Mapper, NsfMapper};
+
+    // 2 tracks starting at the second one, 0x9000 bytes of data loaded at 0x8000
+    fn nsf() -> Vec<u8> {
+        let mut file = b"NESM\x1A\x01\x02\x02".to_vec();
+        for addr in [0x8000u16, 0x8003, 0x8006] {
+            file.extend_from_slice(&addr.to_le_bytes());
+        }
+        for text in [&b"Title"[..], b"Artist", b"Copyright"] {
+            let mut field = text.to_vec();
+            field.resize(32, 0);
+            file.extend_from_slice(&field);
+        }
+        file.extend_from_slice(&16000u16.to_le_bytes());
+        file.extend_from_slice(&[0; 8]); // no bankswitching
+        file.extend_from_slice(&20000u16.to_le_bytes());
+        file.extend_from_slice(&[0b01, VRC6 | NAMCO163, 0, 0, 0, 0]);
+        assert_eq!(file.len(), 0x80);
+        // the first byte of every 4KB bank is its number
+        for bank in 0..9 {
+            let mut data = vec![0xEA; 0x1000];
+            data[0] = bank;
+            file.extend_from_slice(&data);
+        }
+        file
+    }
+
+    fn chunk(file: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(id);
+        file.extend_from_slice(data);
+    }
+
+    // load, init and play address, NTSC, no expansion chips, 3 tracks starting at the first
+    const INFO: [u8; 10] = [0x00, 0x80, 0x00, 0x80, 0x03, 0x80, 0, 0, 3, 0];
+
+    fn nsfe(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut file = b"NSFE".to_vec();
+        for (id, data) in chunks {
+            chunk(&mut file, id, data);
+        }
+        chunk(&mut file, b"NEND", &[]);
+        file
+    }
+
+    #[test]
+    fn nsf_header() {
+        let file = nsf();
+        assert!(is_nsf(&file));
+        let nsf = Nsf::new(&file).unwrap();
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "Copyright");
+        assert_eq!(nsf.track_count, 2);
+        assert_eq!(nsf.starting_track, 1);
+        assert_eq!(
+            (nsf.load_addr, nsf.init_addr, nsf.play_addr),
+            (0x8000, 0x8003, 0x8006)
+        );
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (16000, 20000));
+        assert!(nsf.is_pal());
+        assert_eq!(nsf.play_speed(), 20000);
+        assert_eq!(nsf.expansion, VRC6 | NAMCO163);
+        assert_eq!(nsf.banks, None);
+        assert_eq!(nsf.data.len(), 0x9000);
+        assert_eq!(nsf.track_title(0), None);
+    }
+
+    #[test]
+    fn nsf_defaults() {
+        let mut file = nsf();
+        // a starting track past the last one and speeds of 0 are replaced
+        file[0x07] = 5;
+        file[0x6E..0x70].copy_from_slice(&[0, 0]);
+        file[0x78..0x7A].copy_from_slice(&[0, 0]);
+        let nsf = Nsf::new(&file).unwrap();
+        assert_eq!(nsf.starting_track, 0);
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (NTSC_SPEED, PAL_SPEED));
+
+        // NSF2 data length, the rest of the file is metadata
+        let mut file = self::nsf();
+        file[0x7D..0x80].copy_from_slice(&[0x00, 0x10, 0x00]);
+        assert_eq!(Nsf::new(&file).unwrap().data.len(), 0x1000);
+    }
+
+    #[test]
+    fn nsf_invalid() {
+        let file = nsf();
+        assert_eq!(Nsf::new(b"NES\x1A").unwrap_err(), NsfError::BadMagic);
+        assert!(matches!(Nsf::new(&file[..0x7F]), Err(NsfError::Invalid(_))));
+        assert_eq!(
+            Nsf::new(&file[..0x80]).unwrap_err(),
+            NsfError::Invalid("no program data".to_string())
+        );
+
+        let mut no_tracks = file.clone();
+        no_tracks[0x06] = 0;
+        assert_eq!(
+            Nsf::new(&no_tracks).unwrap_err(),
+            NsfError::Invalid("no tracks".to_string())
+        );
+
+        let mut low_load = file.clone();
+        low_load[0x08..0x0A].copy_from_slice(&0x6000u16.to_le_bytes());
+        assert!(matches!(Nsf::new(&low_load), Err(NsfError::Invalid(_))));
+
+        let mut vrc7 = file;
+        vrc7[0x7B] = VRC7;
+        assert_eq!(
+            Nsf::new(&vrc7).unwrap_err(),
+            NsfError::UnsupportedExpansion("VRC7")
+        );
+    }
+
+    #[test]
+    fn nsf_banks() {
+        let mut file = nsf();
+        // bank 8 at 0x8000, then banks 1-7, loaded 0x100 bytes into the first bank
+        file[0x08..0x0A].copy_from_slice(&0x8100u16.to_le_bytes());
+        file[0x70..0x78].copy_from_slice(&[8, 1, 2, 3, 4, 5, 6, 7]);
+        let nsf = Nsf::new(&file).unwrap();
+        assert_eq!(nsf.banks, Some([8, 1, 2, 3, 4, 5, 6, 7]));
+
+        let mut mapper = NsfMapper::new(&nsf);
+        assert_eq!(mapper.peek(0x8100), 8);
+        assert_eq!(mapper.peek(0x9100), 1);
+        assert_eq!(mapper.peek(0xF100), 7);
+        // 0x5FF8-0x5FFF switch the banks
+        mapper.write(0x5FF8, 3);
+        assert_eq!(mapper.peek(0x8100), 3);
+    }
+
+    #[test]
+    fn nsfe_chunks() {
+        let mut bank = INFO;
+        bank[6] = 0b10; // NTSC and PAL
+        let file = nsfe(&[
+            (b"INFO", &bank),
+            (b"DATA", &[0xEA; 0x100]),
+            (b"BANK", &[0, 1, 2]),
+            (b"RATE", &[0x10, 0x27, 0x20, 0x4E]),
+            (b"auth", b"Title\0Artist\0Copyright\0Ripper\0"),
+            (b"tlbl", b"One\0\0Three\0"),
+            (b"time", &[0xE8, 0x03, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]),
+            (b"fade", &[0; 12]),
+        ]);
+        assert!(is_nsf(&file));
+        let nsf = Nsf::new(&file).unwrap();
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "Copyright");
+        assert_eq!((nsf.track_count, nsf.starting_track), (3, 0));
+        assert_eq!(
+            (nsf.load_addr, nsf.init_addr, nsf.play_addr),
+            (0x8000, 0x8000, 0x8003)
+        );
+        assert_eq!(nsf.timing, Timing::Multi);
+        assert_eq!(nsf.banks, Some([0, 1, 2, 0, 0, 0, 0, 0]));
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (10000, 20000));
+        assert_eq!(nsf.track_title(0), Some("One"));
+        assert_eq!(nsf.track_title(1), None);
+        assert_eq!(nsf.track_title(2), Some("Three"));
+        assert_eq!(nsf.track_lengths, vec![Some(1000), None, None]);
+        assert_eq!(nsf.data.len(), 0x100);
+    }
+
+    #[test]
+    fn nsfe_missing_chunks() {
+        let file = nsfe(&[(b"DATA", &[0xEA; 0x100])]);
+        assert_eq!(
+            Nsf::new(&file).unwrap_err(),
+            NsfError::Invalid("no INFO chunk".to_string())
+        );
+
+        let file = nsfe(&[(b"INFO", &INFO)]);
+        assert_eq!(
+            Nsf::new(&file).unwrap_err(),
+            NsfError::Invalid("no program data".to_string())
+        );
+
+        let file = nsfe(&[(b"INFO", &INFO[..8]), (b"DATA", &[0xEA; 0x100])]);
+        assert_eq!(
+            Nsf::new(&file).unwrap_err(),
+            NsfError::Invalid("INFO chunk is too short".to_string())
+        );
+
+        // unknown chunks starting with an uppercase letter are required
+        let file = nsfe(&[(b"INFO", &INFO), (b"DATA", &[0xEA]), (b"VRC7", &[0])]);
+        assert!(matches!(Nsf::new(&file), Err(NsfError::Invalid(_))));
+    }
+
+    #[test]
+    fn nsfe_truncated() {
+        let file = nsfe(&[(b"INFO", &INFO), (b"DATA", &[0xEA; 0x100])]);
+        assert!(Nsf::new(&file).is_ok());
+        // cut inside the INFO chunk and inside the DATA chunk
+        for len in [4 + 8 + 5, 4 + 18 + 8 + 0x80] {
+            assert!(matches!(Nsf::new(&file[..len]), Err(NsfError::Invalid(_))));
+        }
+
+        let mut file = nsfe(&[(b"INFO", &INFO), (b"DATA", &[0xEA; 0x100])]);
+        file.truncate(file.len() - 8);
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        file.extend_from_slice(b"DATA");
+        assert!(matches!(Nsf::new(&file), Err(NsfError::Invalid(_))));
+    }
+}
@@ -0,0 +1,121 @@
+use super::{Nsf, NsfError};
+use crate::{
+    apu::BUFFER_SIZE,
+    bus::BUS,
+    controller::Controller,
+    mappers::{NsfMapper, NSF_IDLE_ADDR},
+    ppu::PPU,
+    region::Region,
+    rom::Cartridge,
+    CPU,
+};
+
+// plays the tracks of an NSF or NSFe file on the CPU and APU,
+// the PPU runs with rendering and NMI off (tunes are timed by the play rate instead of NMI),
+// PAL only tunes run at the PAL CPU clock and APU rates
+pub struct NsfPlayer {
+    nsf: Nsf,
+    cpu: CPU,
+    track: u8,
+
+    // PLAY is called every play_period CPU cycles,
+    // when the previous call (or INIT) has returned
+    play_period: f64,
+    play_timer: f64,
+    play_pending: bool,
+}
+
+impl NsfPlayer {
+    // starts at the starting track of the file
+    pub fn new(bytes: &[u8]) -> Result<NsfPlayer, NsfError> {
+        let nsf = Nsf::new(bytes)?;
+        let region = if nsf.is_pal() {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+        let play_period = nsf.play_speed() as f64 * region.cpu_freq() as f64 / 1_000_000.0;
+        let track = nsf.starting_track;
+        let mut player = NsfPlayer {
+            nsf,
+            cpu: CPU::default(),
+            track,
+            play_period,
+            play_timer: 0.0,
+            play_pending: false,
+        };
+        player.set_track(track)?;
+        Ok(player)
+    }
+
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+
+    pub fn track_count(&self) -> u8 {
+        self.nsf.track_count
+    }
+
+    // current track, starts at 0
+    pub fn track(&self) -> u8 {
+        self.track
+    }
+
+    // restarts the console and calls INIT for the track (starting at 0)
+    pub fn set_track(&mut self, track: u8) -> Result<(), NsfError> {
+        if track >= self.nsf.track_count {
+            return Err(NsfError::InvalidTrack(track));
+        }
+        let cartridge: Cartridge = Box::new(NsfMapper::new(&self.nsf));
+        let bus = BUS::new_bus(PPU::new_ppu(cartridge), Controller::new_controller());
+        let mut cpu = CPU::new_cpu(bus);
+        cpu.set_region(None);
+
+        // silence the APU, enable the channels and disable the frame counter IRQ
+        for addr in 0x4000..=0x4013 {
+            cpu.bus.write(addr, 0);
+        }
+        cpu.bus.write(0x4015, 0x00);
+        cpu.bus.write(0x4015, 0x0F);
+        cpu.bus.write(0x4017, 0x40);
+
+        // A = track, X = 0 for NTSC and 1 for PAL
+        cpu.call(
+            self.nsf.init_addr,
+            NSF_IDLE_ADDR,
+            track,
+            self.nsf.is_pal() as u8,
+        );
+
+        self.cpu = cpu;
+        self.track = track;
+        self.play_timer = 0.0;
+        self.play_pending = false;
+        Ok(())
+    }
+
+    // fills buffer with mono samples at SAMPLE_RATE
+    pub fn render(&mut self, buffer: &mut [f32]) {
+        for chunk in buffer.chunks_mut(BUFFER_SIZE / 2) {
+            while self.cpu.bus.apu.buffered_samples() < chunk.len() {
+                self.step();
+            }
+            self.cpu.load_samples(chunk);
+        }
+    }
+
+    // runs one CPU instruction, or calls PLAY if it is time and the tune is idle
+    fn step(&mut self) {
+        if self.play_pending && self.cpu.pc() == NSF_IDLE_ADDR {
+            self.play_pending = false;
+            self.cpu.call(self.nsf.play_addr, NSF_IDLE_ADDR, 0, 0);
+        }
+        let cpu_cycles = self.cpu.step();
+
+        self.play_timer += cpu_cycles as f64;
+        if self.play_timer >= self.play_period {
+            self.play_timer -= self.play_period;
+            self.play_pending = true;
+        }
+    }
+}
@@ -36,6 +36,13 @@ impl PPU {
         self.nametable_latch = self.read_nametable(nt_addr);
     }
 
+    // the PPU fetches the next nametable byte twice more at dots 337 and 339,
+    // the data is unused but MMC5 watches these reads to detect scanlines
+    pub fn fetch_unused_nt(&mut self) {
+        let nt_addr = 0x2000 | (self.v & 0x0FFF);
+        self.read_nametable(nt_addr);
+    }
+
     fn fetch_at(&mut self) {
         let v = self.v;
         let attr_addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0b111);
@@ -117,12 +124,12 @@ impl PPU {
             // iterate over each pixel in the row
             // and combine the two bit planes into a single byte
             let mut tile_row = [0u8; 8];
-            for i in 0..8 {
+            for (i, pixel) in tile_row.iter_mut().enumerate() {
                 let pixel_index = 1 << if flip_horizontally { i } else { 7 - i };
                 let p1 = (chr_low & pixel_index != 0) as u8;
                 let p2 = (chr_high & pixel_index != 0) as u8;
                 let pattern = (palette_idx << 2) | (p2 << 1) | p1;
-                tile_row[i] = pattern;
+                *pixel = pattern;
             }
 
             // store x coordinate, tile index, palette index, behind background, and the chr
@@ -1,4 +1,5 @@
 use super::PPU;
+use crate::cpu::debugger::{Access, MemorySpace, StopReason};
 
 // read register
 impl PPU {
@@ -7,7 +8,8 @@ impl PPU {
             2 => self.read_status(),
             4 => self.read_oam_data(),
             7 => self.read_ppu_data(),
-            _ => unreachable!(),
+            // write only registers return the last value written to a register
+            _ => self.open_bus,
         }
     }
 
@@ -15,7 +17,10 @@ impl PPU {
         let res = (self.status & 0b1110_0000) | (self.open_bus & 0b0001_1111);
         self.w = false;
         self.status &= !0x80;
-        self.update_nmi_state();
+        // reading on the dot before vblank starts reads it as clear and it is not set this frame
+        if self.line == self.region.vblank_line() && self.dot == 1 {
+            self.suppress_vblank = true;
+        }
         res
     }
 
@@ -28,7 +33,11 @@ impl PPU {
         let res = match addr {
             0x0000..=0x1fff => self.read_chr_delayed(addr),
             0x2000..=0x3eff => self.read_nametable_delayed(addr),
-            0x3f00..=0x3fff => self.read_palette(addr),
+            0x3f00..=0x3fff => {
+                let data = self.read_palette(addr);
+                self.watch(Access::Read, addr, data);
+                data
+            }
             _ => unreachable!(),
         };
         self.v = self.v.wrapping_add(self.vram_addr_increment()) & 0x3fff;
@@ -56,7 +65,6 @@ impl PPU {
     pub fn write_ctrl(&mut self, data: u8) {
         self.ctrl = data;
         self.t = (self.t & 0xF3FF) | (((data as u16) & 0b11) << 10);
-        self.update_nmi_state();
     }
 
     pub fn write_mask(&mut self, data: u8) {
@@ -98,6 +106,7 @@ impl PPU {
 
     pub fn write_ppu_data(&mut self, data: u8) {
         let addr = self.v;
+        self.watch(Access::Write, addr, data);
         match addr {
             0x0000..=0x1fff => self.write_chr(addr, data),
             0x2000..=0x3eff => self.write_nametable(addr, data),
@@ -112,7 +121,10 @@ impl PPU {
 impl PPU {
     // CHR ROM (Cartridge) /////////////////
     pub fn read_chr(&mut self, addr: u16) -> u8 {
-        self.cartridge.read(addr)
+        let data = self.cartridge.read(addr);
+        self.cartridge.chr_fetched(addr);
+        self.watch(Access::Read, addr, data);
+        data
     }
 
     pub fn write_chr(&mut self, addr: u16, data: u8) {
@@ -126,12 +138,21 @@ impl PPU {
     }
 
     // NAMETABLE (VRAM) /////////////////
-    pub fn read_nametable(&self, addr: u16) -> u8 {
-        let addr = self.map_vram_addr(addr);
-        self.vram[addr as usize]
+    // the cartridge gets the first chance to supply nametable data (MMC5),
+    // otherwise the internal VRAM is used with the cartridge's mirroring
+    pub fn read_nametable(&mut self, addr: u16) -> u8 {
+        let data = match self.cartridge.read_nametable(addr, &self.vram) {
+            Some(data) => data,
+            None => self.vram[self.map_vram_addr(addr) as usize],
+        };
+        self.watch(Access::Read, addr, data);
+        data
     }
 
     pub fn write_nametable(&mut self, addr: u16, data: u8) {
+        if self.cartridge.write_nametable(addr, data, &mut self.vram) {
+            return;
+        }
         let addr = self.map_vram_addr(addr);
         self.vram[addr as usize] = data;
     }
@@ -148,7 +169,7 @@ impl PPU {
         self.frame_palette[addr] = data;
     }
 
-    pub fn read_palette(&mut self, addr: u16) -> u8 {
+    pub fn read_palette(&self, addr: u16) -> u8 {
         let addr = self.map_palette_addr(addr) as usize;
         self.frame_palette[addr]
     }
@@ -169,6 +190,79 @@ impl PPU {
     }
 }
 
+// PPU watchpoints see the rendering fetches and the accesses through 0x2007,
+// the palette lookups of the rendered pixels are not bus accesses and are not watched
+impl PPU {
+    fn watch(&mut self, access: Access, addr: u16, val: u8) {
+        if self.watchpoints.is_empty() || self.watch_hit.is_some() {
+            return;
+        }
+        let hit = self.watchpoints.iter().any(|watchpoint| {
+            let watched = match access {
+                Access::Read => watchpoint.read,
+                Access::Write => watchpoint.write,
+            };
+            watched && watchpoint.range.contains(&addr)
+        });
+        if hit {
+            self.watch_hit = Some(StopReason::Watchpoint {
+                space: MemorySpace::Ppu,
+                access,
+                addr,
+                val,
+            });
+        }
+    }
+}
+
+// peek and poke, for debuggers
+impl PPU {
+    // the value a CPU read of the register (0-7) returns, without side effects
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            2 => (self.status & 0b1110_0000) | (self.open_bus & 0b0001_1111),
+            4 => self.oam[self.oam_addr as usize],
+            // reads below the palette return the buffered value
+            7 if self.v < 0x3F00 => self.data_latch,
+            7 => self.read_palette(self.v),
+            _ => self.open_bus,
+        }
+    }
+
+    // PPU address space (0x0000-0x3FFF): pattern tables, nametables and palette
+    pub fn peek_ppu(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => self.cartridge.peek(addr),
+            0x2000..=0x3EFF => {
+                if let Some(data) = self.cartridge.peek_nametable(addr, &self.vram) {
+                    return data;
+                }
+                self.vram[self.map_vram_addr(addr) as usize]
+            }
+            _ => self.read_palette(addr),
+        }
+    }
+
+    // writes CHR RAM, nametables or the palette without touching the registers
+    pub fn poke_ppu(&mut self, addr: u16, data: u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => self.write_chr(addr, data),
+            0x2000..=0x3EFF => self.write_nametable(addr, data),
+            _ => self.write_palette(addr, data),
+        }
+    }
+
+    pub fn peek_oam(&self, index: u8) -> u8 {
+        self.oam[index as usize]
+    }
+
+    pub fn poke_oam(&mut self, index: u8, data: u8) {
+        self.oam[index as usize] = data;
+    }
+}
+
 // utils to extract info from ppu registers
 impl PPU {
     // ctrl bits /////////////////
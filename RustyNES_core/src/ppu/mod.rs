@@ -2,11 +2,19 @@ mod fetch;
 mod io;
 mod render;
 
-use crate::{buffer::Buffer, cpu::Interrupt, mappers::Mapper0, rom::Cartridge};
+use crate::{
+    buffer::Buffer,
+    cpu::debugger::{StopReason, Watchpoint},
+    mappers::Mapper0,
+    region::Region,
+    rom::Cartridge,
+};
 
 pub struct PPU {
     dot: u16,  // 0-340
-    line: u16, // 0-261, 0-239=visible, 240=post, 241-260=vblank, 261=pre
+    line: u16, // NTSC: 0-261, 0-239=visible, 240=post, 241-260=vblank, 261=pre
+    region: Region,
+    master_clock: u64, // master clock of the last dot
 
     // PPU Registers
     pub ctrl: u8,
@@ -52,21 +60,22 @@ pub struct PPU {
     // pub frame_buffer: [u8; 256 * 240 * 4],
     pub frame_buffer: Box<[u8; 256 * 240 * 4]>,
     pub frame_complete: bool,
-
-    // nmi
-    nmi_previous_state: bool,
-    nmi_triggering_allowed: bool,
-    nmi_triggered: bool,
+    // reading the status on the dot before vblank starts keeps the flag from being set
+    suppress_vblank: bool,
 
     open_bus: u8,
     data_latch: u8,
-    pub dma_triggered: bool,
     pub cartridge: Cartridge,
+
+    // the debugger's PPU watchpoints and the first access that hit one,
+    // set and taken by the CPU while it runs the debugger
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    pub(crate) watch_hit: Option<StopReason>,
 }
 
 impl Default for PPU {
     fn default() -> Self {
-        let cart: Cartridge = Box::new(Mapper0::default());
+        let cart: Cartridge = Box::<Mapper0>::default();
         Self::new_ppu(cart)
     }
 }
@@ -77,6 +86,8 @@ impl PPU {
             // state
             dot: 0,
             line: 0,
+            region: Region::Ntsc,
+            master_clock: 0,
 
             // registers
             ctrl: 0,
@@ -109,16 +120,14 @@ impl PPU {
             frame_counter: 0,
             frame_buffer: Box::new([0; 256 * 240 * 4]),
             frame_complete: false,
-
-            // nmi
-            nmi_previous_state: false,
-            nmi_triggering_allowed: false,
-            nmi_triggered: false,
+            suppress_vblank: false,
 
             open_bus: 0,
             data_latch: 0,
-            dma_triggered: false,
             cartridge,
+
+            watchpoints: Vec::new(),
+            watch_hit: None,
         };
         // start ppu from line where vblank starts
         // during vblank, cpu writes rendering data to ppu memory
@@ -126,10 +135,54 @@ impl PPU {
         ppu
     }
 
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // scanline and dot the PPU renders next
+    pub fn line(&self) -> u16 {
+        self.line
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    // frames started since power on
+    pub fn frame_count(&self) -> u64 {
+        self.frame_counter
+    }
+
+    // the VRAM address 0x2007 reads and writes
+    pub fn vram_addr(&self) -> u16 {
+        self.v & 0x3FFF
+    }
+
+    // the master clock restarts at 0, the CPU's clock has to be restarted with it
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.master_clock = 0;
+        if self.line >= region.scanlines() {
+            self.line = 0;
+        }
+    }
+
+    // runs the dots up to the master clock of the CPU
+    pub fn run_to(&mut self, master_clock: u64) {
+        let clocks_per_dot = self.region.master_clocks_per_dot();
+        while self.master_clock + clocks_per_dot <= master_clock {
+            self.master_clock += clocks_per_dot;
+            self.step();
+        }
+    }
+
     pub fn step(&mut self) {
+        let pre_line_number = self.region.scanlines() - 1;
+        let vblank_line = self.region.vblank_line();
+
         // which line?
         let visible_line = self.line < 240;
-        let preline = self.line == 261;
+        let preline = self.line == pre_line_number;
         let fetch_line = visible_line || preline;
 
         // which dot?
@@ -140,6 +193,7 @@ impl PPU {
         // which time?
         let render_time = visible_line && visible_dot;
         let fetch_time = fetch_line && fetch_dot;
+        let unused_fetch_time = fetch_line && (self.dot == 337 || self.dot == 339);
         let sp_fetch_time = self.dot == 256 && visible_line;
         let cartridge_step_time = self.dot == 260 && visible_line;
 
@@ -156,6 +210,10 @@ impl PPU {
                 self.fetch_bg()
             }
 
+            if unused_fetch_time {
+                self.fetch_unused_nt();
+            }
+
             ////// fetch sprites //////
             if sp_fetch_time {
                 self.fetch_sprites();
@@ -171,29 +229,25 @@ impl PPU {
         }
 
         ////// enter vblank //////
-        if self.line == 241 && self.dot == 1 {
+        if self.line == vblank_line && self.dot == 1 {
             self.frame_complete = true;
-            self.set_vblank_started();
-            self.update_nmi_state();
+            if !self.suppress_vblank {
+                self.set_vblank_started();
+            }
+            self.suppress_vblank = false;
         }
 
         ////// exit vblank  //////
-        if self.line == 261 && self.dot == 1 {
+        if preline && self.dot == 1 {
             self.clear_vblank_started();
             self.clear_sprite_0_hit();
             self.clear_sprite_overflow();
-            self.update_nmi_state();
-        }
-
-        ////// nmi handling //////
-        if self.nmi_triggering_allowed && self.current_nmi_state() {
-            self.nmi_triggered = true;
-            self.nmi_triggering_allowed = false;
         }
 
         ////// dot, line and frame counters (increment) and (reset) and special case of (skipping) //////
-        if rendering_enabled && self.odd && self.line == 261 && self.dot == 339 {
-            // skip cycle 339 of pre-render scanline when odd frame
+        let skip_time = self.region == Region::Ntsc && self.odd && preline && self.dot == 339;
+        if rendering_enabled && skip_time {
+            // skip cycle 339 of pre-render scanline when odd frame (NTSC only)
             self.dot = 0;
             self.line = 0;
             self.odd = !self.odd;
@@ -206,9 +260,9 @@ impl PPU {
         if self.dot > 340 {
             self.dot = 0;
 
-            // increment line, reset at 262
+            // increment line, reset after the pre-render line
             self.line += 1;
-            if self.line > 261 {
+            if self.line > pre_line_number {
                 self.line = 0;
                 self.odd = !self.odd;
                 self.frame_counter += 1;
@@ -217,17 +271,9 @@ impl PPU {
     }
 
     // nmi handling /////////////////////////////
-    fn update_nmi_state(&mut self) {
-        let nmi_current_state = self.current_nmi_state();
-
-        if !self.nmi_previous_state && nmi_current_state {
-            self.nmi_triggering_allowed = true;
-        }
-
-        self.nmi_previous_state = nmi_current_state;
-    }
-
-    fn current_nmi_state(&self) -> bool {
+    // the NMI output is low (active) while vblank is set and NMI is enabled,
+    // the CPU triggers the NMI on the edge
+    pub fn nmi_line(&self) -> bool {
         self.genrate_nmi() && self.vblank_started()
     }
 
@@ -278,28 +324,6 @@ impl PPU {
         }
     }
 
-    pub fn dma_triggered(&mut self) -> bool {
-        let triggered = self.dma_triggered;
-        self.dma_triggered = false;
-        triggered
-    }
-
-    pub fn nmi_triggered(&mut self) -> bool {
-        let triggered = self.nmi_triggered;
-        self.nmi_triggered = false;
-        triggered
-    }
-
-    pub fn interrupt_triggered(&mut self) -> Interrupt {
-        if self.nmi_triggered() {
-            Interrupt::NMI
-        } else if self.cartridge.irq_triggered() {
-            Interrupt::IRQ
-        } else {
-            Interrupt::None
-        }
-    }
-
     pub fn frame_complete(&mut self) -> bool {
         let complete = self.frame_complete;
         self.frame_complete = false;
@@ -352,6 +376,8 @@ impl PPU {
     pub fn encode(&self, buffer: &mut Buffer) {
         buffer.write_u16(self.dot);
         buffer.write_u16(self.line);
+        buffer.write_u8(self.region as u8);
+        buffer.write_u64(self.master_clock);
 
         buffer.write_u8(self.ctrl);
         buffer.write_u8(self.mask);
@@ -383,19 +409,17 @@ impl PPU {
         buffer.write_u64(self.frame_counter);
         buffer.write_u8_arr(self.frame_buffer.as_ref());
         buffer.write_bool(self.frame_complete);
-
-        buffer.write_bool(self.nmi_previous_state);
-        buffer.write_bool(self.nmi_triggering_allowed);
-        buffer.write_bool(self.nmi_triggered);
+        buffer.write_bool(self.suppress_vblank);
 
         buffer.write_u8(self.open_bus);
         buffer.write_u8(self.data_latch);
-        buffer.write_bool(self.dma_triggered);
     }
 
     pub fn decode(&mut self, buffer: &mut Buffer) {
         self.dot = buffer.read_u16();
         self.line = buffer.read_u16();
+        self.region = Region::from_bits(buffer.read_u8());
+        self.master_clock = buffer.read_u64();
 
         self.ctrl = buffer.read_u8();
         self.mask = buffer.read_u8();
@@ -427,13 +451,9 @@ impl PPU {
         self.frame_counter = buffer.read_u64();
         buffer.read_u8_arr(self.frame_buffer.as_mut());
         self.frame_complete = buffer.read_bool();
-
-        self.nmi_previous_state = buffer.read_bool();
-        self.nmi_triggering_allowed = buffer.read_bool();
-        self.nmi_triggered = buffer.read_bool();
+        self.suppress_vblank = buffer.read_bool();
 
         self.open_bus = buffer.read_u8();
         self.data_latch = buffer.read_u8();
-        self.dma_triggered = buffer.read_bool();
     }
 }
@@ -0,0 +1,83 @@
+use crate::rom::Timing;
+
+// console region, sets the clock rate of the CPU and the timings of the PPU and APU
+// - NTSC: 262 lines, 3 PPU dots per CPU cycle
+// - PAL: 312 lines (70 lines of vblank), 3.2 PPU dots per CPU cycle, slower APU rates
+// - Dendy (Famiclone): 312 lines with vblank starting at line 291, NTSC ratio and APU rates
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc = 0,
+    Pal = 1,
+    Dendy = 2,
+}
+
+impl Region {
+    // region of the ROM's timing (NES 2.0 header or game database),
+    // games that run on both NTSC and PAL consoles use NTSC
+    pub fn from_timing(timing: Timing) -> Region {
+        match timing {
+            Timing::Ntsc | Timing::Multi => Region::Ntsc,
+            Timing::Pal => Region::Pal,
+            Timing::Dendy => Region::Dendy,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Region {
+        match bits {
+            1 => Region::Pal,
+            2 => Region::Dendy,
+            _ => Region::Ntsc,
+        }
+    }
+
+    // CPU clock in Hz
+    pub fn cpu_freq(self) -> f32 {
+        match self {
+            Region::Ntsc => 1789773.0,
+            Region::Pal => 1662607.0,
+            Region::Dendy => 1773448.0,
+        }
+    }
+
+    // frames per second, for pacing the frontend
+    pub fn frame_rate(self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    // the CPU and PPU clocks are divided from the master clock,
+    // a CPU cycle is 3 PPU dots on NTSC and Dendy and 3.2 dots on PAL
+    pub(crate) fn master_clocks_per_cpu_cycle(self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    pub(crate) fn master_clocks_per_dot(self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    // lines per frame, the last one is the pre-render line
+    pub(crate) fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // first line of vblank
+    pub(crate) fn vblank_line(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+}
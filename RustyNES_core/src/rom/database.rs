@@ -0,0 +1,176 @@
+// embedded game database
+// many dumps have a wrong or dirty header (wrong mapper, wrong mirroring, garbage in bytes 7-15),
+// known games are identified by the checksums of their PRG ROM + CHR ROM data
+// (the header and trainer are not part of it) and their header values are replaced
+use super::{Mirroring, Timing};
+use crate::hash::{crc32, sha1};
+
+#[derive(Clone, Debug)]
+pub struct GameInfo {
+    pub title: &'static str,
+
+    // checksums of PRG ROM + CHR ROM
+    pub crc32: u32,
+    // lowercase hex
+    pub sha1: &'static str,
+
+    pub mapper_id: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub timing: Timing,
+
+    // memory sizes in bytes
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+}
+
+const GAMES: &[GameInfo] = &[
+    GameInfo {
+        title: "Alter Ego",
+        crc32: 0xB84035A7,
+        sha1: "54fc1a9a424298f3c5fe12f9c1a03b297cccb2bd",
+        mapper_id: 0,
+        submapper: 0,
+        mirroring: Mirroring::Vertical,
+        battery: false,
+        timing: Timing::Ntsc,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+    },
+    GameInfo {
+        title: "Contra (USA)",
+        crc32: 0xF6035030,
+        sha1: "979494e7869ac7ab4815fdbd1dc99f893f713fbf",
+        mapper_id: 2,
+        submapper: 0,
+        mirroring: Mirroring::Vertical,
+        battery: false,
+        timing: Timing::Ntsc,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0x2000,
+        chr_nvram_size: 0,
+    },
+    GameInfo {
+        title: "Super Mario Bros. (World)",
+        crc32: 0x8E2BD25C,
+        sha1: "71fdb80c3583010422652cc5aae8e2e4131e49f3",
+        mapper_id: 0,
+        submapper: 0,
+        mirroring: Mirroring::Vertical,
+        battery: false,
+        timing: Timing::Ntsc,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+    },
+    GameInfo {
+        title: "Super Mario Bros. 3 (USA)",
+        crc32: 0x2E6301ED,
+        sha1: "bb894d104c796f69ba16587eb66c0275f5c2fc02",
+        mapper_id: 4,
+        submapper: 0,
+        mirroring: Mirroring::Horizontal,
+        battery: false,
+        timing: Timing::Ntsc,
+        prg_ram_size: 0x2000,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+    },
+    GameInfo {
+        title: "nestest",
+        crc32: 0x158B0388,
+        sha1: "4131307f0f69f2a5c54b7d438328c5b2a5ed0820",
+        mapper_id: 0,
+        submapper: 0,
+        mirroring: Mirroring::Horizontal,
+        battery: false,
+        timing: Timing::Ntsc,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+    },
+];
+
+// all games in the database
+pub fn games() -> &'static [GameInfo] {
+    GAMES
+}
+
+pub fn find_by_crc32(crc32: u32) -> Option<&'static GameInfo> {
+    GAMES.iter().find(|game| game.crc32 == crc32)
+}
+
+pub fn find_by_sha1(sha1: &[u8; 20]) -> Option<&'static GameInfo> {
+    let hex = to_hex(sha1);
+    GAMES.iter().find(|game| game.sha1 == hex)
+}
+
+// find the game for PRG ROM + CHR ROM data,
+// the SHA-1 is checked too so a CRC-32 collision does not pick the wrong game
+pub fn lookup(prg_chr: &[u8]) -> Option<&'static GameInfo> {
+    let game = find_by_crc32(crc32(prg_chr))?;
+    if game.sha1 == to_hex(&sha1(prg_chr)) {
+        Some(game)
+    } else {
+        None
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::ROM;
+
+    const ALTER_EGO: &[u8] = include_bytes!("../../../RustyNES_web_ui/public/roms/alter_ego.nes");
+
+    #[test]
+    fn lookup_games() {
+        let game = lookup(&ALTER_EGO[16..]).unwrap();
+        assert_eq!(game.title, "Alter Ego");
+        assert!(find_by_crc32(crc32(&ALTER_EGO[16..])).is_some());
+        assert!(find_by_sha1(&sha1(&ALTER_EGO[16..])).is_some());
+
+        let mut modified = ALTER_EGO[16..].to_vec();
+        modified[0] ^= 0xFF;
+        assert!(lookup(&modified).is_none());
+    }
+
+    #[test]
+    fn header_overrides() {
+        // wrong mapper, mirroring, battery, PRG RAM size and TV system in the header
+        let mut file = ALTER_EGO.to_vec();
+        file[6] = 0x12;
+        file[7] = 0x30;
+        file[8] = 4;
+        file[9] = 1;
+        let rom = ROM::new(file).unwrap();
+        assert_eq!(rom.title(), Some("Alter Ego"));
+        assert_eq!(rom.mapper_id, 0);
+        assert!(matches!(rom.mirroring, Mirroring::Vertical));
+        assert!(!rom.battery);
+        assert_eq!(rom.timing, Timing::Ntsc);
+        assert_eq!(rom.prg_ram_len(), 0);
+
+        // a rom that is not in the database keeps its header values
+        let mut file = ALTER_EGO.to_vec();
+        file[6] = 0x12;
+        file[16] ^= 0xFF;
+        let rom = ROM::new(file).unwrap();
+        assert_eq!(rom.title(), None);
+        assert_eq!(rom.mapper_id, 1);
+        assert!(rom.battery);
+    }
+}
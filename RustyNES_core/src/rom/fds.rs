@@ -0,0 +1,148 @@
+// Famicom Disk System disk images (.fds)
+// the console boots from the FDS BIOS (8KB) which loads the game from the disk,
+// the disk data is kept on the ROM and run through the RAM adapter (mapper 20)
+use super::{Mirroring, RomError, ROM};
+
+// size of a disk side in a .fds image
+pub const SIDE_SIZE: usize = 65500;
+
+pub const BIOS_SIZE: usize = 0x2000;
+
+/*
+.fds file format:
+optional header (16 bytes): "FDS<EOF>", number of sides, 11 bytes of zeros
+disk sides (65500 bytes each)
+
+each side is a list of blocks (without the gaps and CRCs of the real disk):
+1: disk info (56 bytes), starts with "*NINTENDO-HVC*"
+2: file amount (2 bytes)
+3: file header (16 bytes), bytes 13-14 are the size of the file
+4: file data (1 + file size bytes)
+the rest of the side is zeros
+*/
+pub fn is_fds(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"FDS\x1A") || bytes.starts_with(b"\x01*NINTENDO-HVC*")
+}
+
+// split a disk image into its sides, the header is optional
+pub fn parse_sides(image: &[u8]) -> Result<Vec<Vec<u8>>, RomError> {
+    let data = if image.starts_with(b"FDS\x1A") {
+        image.get(16..).unwrap_or(&[])
+    } else {
+        image
+    };
+    let sides: Vec<Vec<u8>> = data
+        .chunks(SIDE_SIZE)
+        .map(|side| {
+            let mut side = side.to_vec();
+            side.resize(SIDE_SIZE, 0);
+            side
+        })
+        .collect();
+    if sides.is_empty()
+        || sides
+            .iter()
+            .any(|side| !side.starts_with(b"\x01*NINTENDO-HVC*"))
+    {
+        return Err(RomError::InvalidHeader(
+            "not a Famicom Disk System image".to_string(),
+        ));
+    }
+    Ok(sides)
+}
+
+impl ROM {
+    // disk image (with or without the fwNES header) and the FDS BIOS (disksys.rom)
+    pub fn new_fds(image: &[u8], bios: Vec<u8>) -> Result<ROM, RomError> {
+        if bios.len() != BIOS_SIZE {
+            return Err(RomError::InvalidHeader(format!(
+                "FDS BIOS is {} bytes, expected {}",
+                bios.len(),
+                BIOS_SIZE
+            )));
+        }
+        let disk_sides = parse_sides(image)?;
+        println!("fds sides {}", disk_sides.len());
+
+        // the RAM adapter has 32KB PRG RAM at 0x6000-0xDFFF and 8KB CHR RAM
+        Ok(ROM {
+            prg_rom: bios,
+            prg_rom_banks: 1,
+            mapper_id: 20,
+            mirroring: Mirroring::Horizontal,
+            prg_ram_size: 0x8000,
+            chr_ram_size: 0x2000,
+            disk_sides,
+            ..Default::default()
+        })
+    }
+}
+
+// the drive reads a continuous bit stream with gaps before the blocks,
+// a start mark (0x80) and a CRC after each block
+// the raw side is built from the .fds side with a fake CRC, the drive never reports CRC errors
+const START_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+
+pub fn to_raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; START_GAP];
+    let mut index = 0;
+    while let Some(len) = side
+        .get(index)
+        .and_then(|&kind| block_len(kind, &side[..index]))
+    {
+        if index + len > side.len() {
+            break;
+        }
+        raw.push(0x80);
+        raw.extend_from_slice(&side[index..index + len]);
+        raw.extend_from_slice(&[0x4D, 0x62]);
+        raw.extend(std::iter::repeat_n(0, BLOCK_GAP));
+        index += len;
+    }
+    // room for the blocks the game writes after the last one
+    raw.resize(raw.len().max(SIDE_SIZE + START_GAP + 4096), 0);
+    raw
+}
+
+// the blocks of a raw side without the gaps, start marks and CRCs
+pub fn from_raw_side(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut index = 0;
+    loop {
+        // skip the gap up to the start mark
+        match raw[index..].iter().position(|&b| b != 0) {
+            Some(offset) if raw[index + offset] == 0x80 => index += offset + 1,
+            _ => break,
+        }
+        let Some(len) = raw.get(index).and_then(|&kind| block_len(kind, &side)) else {
+            break;
+        };
+        match raw.get(index..index + len) {
+            Some(block) => side.extend_from_slice(block),
+            None => break,
+        }
+        // skip the CRC
+        index += len + 2;
+        if index >= raw.len() {
+            break;
+        }
+    }
+    side.resize(SIDE_SIZE, 0);
+    side
+}
+
+// length of a block from its type, None for an invalid type (the end of the data)
+// a file data block takes its size from the file header at the end of the previous blocks
+fn block_len(kind: u8, previous: &[u8]) -> Option<usize> {
+    match kind {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 if previous.len() >= 16 => {
+            let header = &previous[previous.len() - 16..];
+            Some(1 + u16::from_le_bytes([header[13], header[14]]) as usize)
+        }
+        _ => None,
+    }
+}
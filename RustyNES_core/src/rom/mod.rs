@@ -0,0 +1,811 @@
+use crate::{
+    buffer::Buffer,
+    mappers::{Mapper, MapperRegistry},
+};
+
+pub mod database;
+pub mod fds;
+pub mod patch;
+pub mod unif;
+
+use database::GameInfo;
+
+// mapper is a chip on the cartridge that controls
+// how the program code and graphics data are read from the PRG ROM and CHR ROM
+// read and write to Cartridge data (ROM file) is done through the mapper
+pub type Cartridge = Box<dyn Mapper + Send>;
+
+#[derive(Default, Clone, Debug)]
+pub struct ROM {
+    // PRG ROM stores the game's program code
+    // CHR ROM stores the game's graphics data
+    // both are padded to whole banks
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+
+    //// Parsed metadata from the header of the ROM file ////
+
+    // number of 16KB PRG ROM banks
+    pub prg_rom_banks: u16,
+
+    // number of 8KB CHR ROM banks
+    pub chr_rom_banks: u16,
+
+    // mapper determines from which bank to read the program code and graphics data
+    // 8 bits in iNES 1.0, 12 bits in NES 2.0
+    pub mapper_id: u16,
+
+    // board variant of the mapper, only present in NES 2.0 headers (0 otherwise)
+    pub submapper: u8,
+
+    // mirroring mode determines how the nametables are mirrored
+    pub mirroring: Mirroring,
+
+    // trainer, 512 bytes copied to PRG RAM at 0x7000-0x71FF on power up (empty if there is none)
+    // used by some hacked and older dumps, usually to emulate an unsupported mapper
+    pub trainer: Vec<u8>,
+
+    // cartridge has battery backed memory (save RAM) that keeps its contents when powered off
+    pub battery: bool,
+
+    // true if the header is in NES 2.0 format
+    pub nes2: bool,
+
+    // memory sizes in bytes
+    // volatile PRG RAM at 0x6000-0x7FFF
+    pub prg_ram_size: usize,
+    // battery backed PRG RAM (save RAM)
+    pub prg_nvram_size: usize,
+    // CHR RAM, used when the cartridge has no CHR ROM
+    pub chr_ram_size: usize,
+    // battery backed CHR RAM
+    pub chr_nvram_size: usize,
+
+    // video system the game was made for
+    pub timing: Timing,
+
+    // console the game runs on
+    pub console_type: ConsoleType,
+
+    // number of miscellaneous ROMs after CHR ROM (NES 2.0 only)
+    pub misc_roms: u8,
+
+    // default expansion device (controllers etc.), see NES 2.0 spec for the values
+    pub expansion_device: u8,
+
+    // Famicom Disk System disk sides in .fds format (the PRG ROM is the BIOS)
+    pub disk_sides: Vec<Vec<u8>>,
+
+    // entry of the game database that matched the PRG ROM + CHR ROM data,
+    // its values replace the ones from the header
+    pub game: Option<&'static GameInfo>,
+}
+
+/*
+rom file format:
+header (16 bytes)
+trainer (0 or 512 bytes)
+prg rom (16KB * number of prg rom banks)
+chr rom (8KB * number of chr rom banks)
+
+header:
+0-3: "NES<EOF>"
+4:   PRG ROM size (LSB in NES 2.0)
+5:   CHR ROM size (LSB in NES 2.0)
+6:   flags 6 (mapper D0-D3, four screen, trainer, battery, mirroring)
+7:   flags 7 (mapper D4-D7, NES 2.0 identifier, console type)
+-- NES 2.0 --
+8:   mapper D8-D11 and submapper
+9:   PRG ROM and CHR ROM size MSB
+10:  PRG RAM and PRG NVRAM shift counts
+11:  CHR RAM and CHR NVRAM shift counts
+12:  CPU/PPU timing
+13:  Vs. System type or extended console type
+14:  miscellaneous ROMs
+15:  default expansion device
+-- iNES 1.0 --
+8:   PRG RAM size in 8KB units
+9:   TV system
+10-15: unused (often garbage in old dumps)
+*/
+impl ROM {
+    // builds the cartridge with the built-in mappers
+    pub fn new_cartridge(bytes: Vec<u8>) -> Result<Cartridge, RomError> {
+        let rom = ROM::new(bytes)?;
+        MapperRegistry::new().create(rom)
+    }
+
+    // parses an iNES / NES 2.0 (.nes) or UNIF (.unf) file
+    pub fn new(bytes: Vec<u8>) -> Result<ROM, RomError> {
+        if fds::is_fds(&bytes) {
+            return Err(RomError::FdsBiosRequired);
+        }
+        let mut rom = if unif::is_unif(&bytes) {
+            unif::parse(&bytes)?
+        } else {
+            ROM::parse_ines(&bytes)?
+        };
+
+        // Correct the header values of known games
+        rom.game = database::lookup(&[rom.prg_rom.as_slice(), &rom.chr_rom].concat());
+        if let Some(game) = rom.game {
+            rom.apply_game_info(game);
+        }
+
+        // the trainer needs PRG RAM at 0x7000
+        if !rom.trainer.is_empty() && rom.prg_ram_len() < 0x2000 {
+            rom.prg_ram_size = 0x2000 - rom.prg_nvram_size;
+        }
+
+        // Sizes that are not whole banks (NES 2.0 exponent notation) are padded
+        rom.prg_rom.resize(rom.prg_rom_banks as usize * 0x4000, 0);
+        rom.chr_rom.resize(rom.chr_rom_banks as usize * 0x2000, 0);
+
+        println!("nes2 {}", &rom.nes2);
+        println!("title {:?}", rom.title());
+        println!("prg_rom_banks {}", &rom.prg_rom_banks);
+        println!("chr_rom_banks {}", &rom.chr_rom_banks);
+        println!("mirroring {:?}", &rom.mirroring);
+        println!("mapper_id {}", &rom.mapper_id);
+        println!("submapper {}", &rom.submapper);
+        println!("trainer {}", !rom.trainer.is_empty());
+        println!("battery {}", &rom.battery);
+        println!("timing {:?}", &rom.timing);
+
+        Ok(rom)
+    }
+
+    fn parse_ines(bytes: &[u8]) -> Result<ROM, RomError> {
+        // Check header size
+        if bytes.len() < 16 {
+            return Err(RomError::InvalidHeader(format!(
+                "file is {} bytes, header needs 16",
+                bytes.len()
+            )));
+        }
+
+        // Check file signature
+        let signature = &bytes[0..4];
+        if signature != [78, 69, 83, 26] {
+            return Err(RomError::BadMagic);
+        }
+
+        // Check header version
+        // bits 2-3 of flags 7 are 0b10 in NES 2.0 headers
+        let nes2 = (bytes[7] & 0b0000_1100) == 0b0000_1000;
+
+        // Old dumps have garbage (e.g. "DiskDude!") in bytes 7-15,
+        // ignore them if an iNES 1.0 header has non-zero bytes 12-15
+        let archaic = !nes2 && bytes[12..16].iter().any(|&b| b != 0);
+        let flags7 = if archaic { 0 } else { bytes[7] };
+
+        // size of PRG ROM and CHR ROM in bytes
+        let (prg_rom_size, chr_rom_size) = if nes2 {
+            (
+                nes2_rom_size(bytes[4], bytes[9] & 0x0F, 0x4000)?,
+                nes2_rom_size(bytes[5], bytes[9] >> 4, 0x2000)?,
+            )
+        } else {
+            (bytes[4] as usize * 0x4000, bytes[5] as usize * 0x2000)
+        };
+        if prg_rom_size == 0 {
+            return Err(RomError::InvalidHeader("no PRG ROM banks".to_string()));
+        }
+
+        // number of PRG ROM and CHR ROM banks
+        let prg_rom_banks = prg_rom_size.div_ceil(0x4000) as u16;
+        let chr_rom_banks = chr_rom_size.div_ceil(0x2000) as u16;
+
+        // Check if trainer is present
+        let has_trainer = (bytes[6] & 0b0000_0100) != 0;
+
+        // Check if cartridge has battery backed memory
+        let battery = (bytes[6] & 0b0000_0010) != 0;
+
+        // Skip header bytes (16 bytes) and trainer bytes(0 or 512 bytes)
+        // PRG ROM starts after the header and trainer
+        let prg_rom_start: usize = 16 + if has_trainer { 512 } else { 0 };
+        // saturates instead of overflowing, a size past the end of the file is truncated anyway
+        let prg_rom_end = prg_rom_start.saturating_add(prg_rom_size);
+        if bytes.len() < prg_rom_end {
+            return Err(RomError::TruncatedPrgRom {
+                expected: prg_rom_size,
+                found: bytes.len().saturating_sub(prg_rom_start),
+            });
+        }
+
+        let trainer = bytes[16..prg_rom_start].to_vec();
+
+        // CHR ROM starts after the PRG ROM
+        let chr_rom_start = prg_rom_end;
+        let chr_rom_end = chr_rom_start.saturating_add(chr_rom_size);
+        if bytes.len() < chr_rom_end {
+            return Err(RomError::TruncatedChrRom {
+                expected: chr_rom_size,
+                found: bytes.len() - chr_rom_start,
+            });
+        }
+
+        // Mirroring mode
+        let mirroring = if (bytes[6] & 0b0000_1000) != 0 {
+            Mirroring::FourScreen
+        } else if (bytes[6] & 0b1) != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        // Construct the mapper id from the header
+        let mut mapper_id = ((flags7 & 0b1111_0000) | (bytes[6] >> 4)) as u16;
+        let mut submapper = 0;
+
+        // Console type (bits 0-1 of flags 7)
+        let mut console_type = match flags7 & 0b11 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu: 0,
+                hardware: 0,
+            },
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(0),
+        };
+
+        let rom = if nes2 {
+            mapper_id |= ((bytes[8] & 0x0F) as u16) << 8;
+            submapper = bytes[8] >> 4;
+
+            // byte 13 holds the Vs. System type or the extended console type
+            console_type = match console_type {
+                ConsoleType::VsSystem { .. } => ConsoleType::VsSystem {
+                    ppu: bytes[13] & 0x0F,
+                    hardware: bytes[13] >> 4,
+                },
+                ConsoleType::Extended(_) => ConsoleType::Extended(bytes[13] & 0x0F),
+                other => other,
+            };
+
+            // boards without CHR ROM always have CHR RAM,
+            // assume 8KB if the header does not give a size
+            let mut chr_ram_size = nes2_ram_size(bytes[11] & 0x0F);
+            let chr_nvram_size = nes2_ram_size(bytes[11] >> 4);
+            if chr_rom_size == 0 && chr_ram_size + chr_nvram_size == 0 {
+                chr_ram_size = 0x2000;
+            }
+
+            ROM {
+                prg_ram_size: nes2_ram_size(bytes[10] & 0x0F),
+                prg_nvram_size: nes2_ram_size(bytes[10] >> 4),
+                chr_ram_size,
+                chr_nvram_size,
+                timing: Timing::from_bits(bytes[12]),
+                misc_roms: bytes[14] & 0b11,
+                expansion_device: bytes[15] & 0b0011_1111,
+                ..Default::default()
+            }
+        } else {
+            // iNES 1.0 has no ram sizes for most games,
+            // assume 8KB PRG RAM and 8KB CHR RAM if there is no CHR ROM
+            let prg_ram_banks = if archaic { 1 } else { bytes[8].max(1) };
+            let pal = !archaic && bytes[9] & 1 != 0;
+            ROM {
+                prg_ram_size: prg_ram_banks as usize * 0x2000,
+                chr_ram_size: if chr_rom_size == 0 { 0x2000 } else { 0 },
+                timing: if pal { Timing::Pal } else { Timing::Ntsc },
+                ..Default::default()
+            }
+        };
+
+        // Create ROM
+        Ok(ROM {
+            prg_rom: bytes[prg_rom_start..prg_rom_end].to_vec(),
+            chr_rom: bytes[chr_rom_start..chr_rom_end].to_vec(),
+            prg_rom_banks,
+            chr_rom_banks,
+            mapper_id,
+            submapper,
+            mirroring,
+            trainer,
+            battery,
+            nes2,
+            console_type,
+            ..rom
+        })
+    }
+
+    // replace the header values with the ones from the game database
+    pub fn apply_game_info(&mut self, game: &GameInfo) {
+        self.mapper_id = game.mapper_id;
+        self.submapper = game.submapper;
+        // four screen boards have extra VRAM the header always reports
+        if !matches!(self.mirroring, Mirroring::FourScreen) {
+            self.mirroring = game.mirroring.clone();
+        }
+        self.battery = game.battery;
+        self.timing = game.timing;
+        self.prg_ram_size = game.prg_ram_size;
+        self.prg_nvram_size = game.prg_nvram_size;
+        self.chr_ram_size = game.chr_ram_size;
+        self.chr_nvram_size = game.chr_nvram_size;
+    }
+
+    // title of the game if it is in the game database
+    pub fn title(&self) -> Option<&'static str> {
+        self.game.map(|game| game.title)
+    }
+
+    // total PRG RAM at 0x6000-0x7FFF (volatile and battery backed)
+    pub fn prg_ram_len(&self) -> usize {
+        self.prg_ram_size + self.prg_nvram_size
+    }
+
+    // total CHR RAM (volatile and battery backed),
+    // boards without CHR ROM always have CHR RAM, 8KB if no size is given
+    pub fn chr_ram_len(&self) -> usize {
+        match self.chr_ram_size + self.chr_nvram_size {
+            0 if self.chr_rom_banks == 0 => 0x2000,
+            len => len,
+        }
+    }
+
+    pub fn encode(&self, buffer: &mut Buffer) {
+        buffer.write_u64(self.prg_rom.len() as u64);
+        buffer.write_u8_arr(&self.prg_rom);
+        buffer.write_u64(self.chr_rom.len() as u64);
+        buffer.write_u8_arr(&self.chr_rom);
+        buffer.write_u16(self.prg_rom_banks);
+        buffer.write_u16(self.chr_rom_banks);
+        buffer.write_u16(self.mapper_id);
+        buffer.write_u8(self.submapper);
+        match self.mirroring {
+            Mirroring::Horizontal => buffer.write_u8(0),
+            Mirroring::Vertical => buffer.write_u8(1),
+            Mirroring::OneScreenLower => buffer.write_u8(2),
+            Mirroring::OneScreenUpper => buffer.write_u8(3),
+            Mirroring::FourScreen => buffer.write_u8(4),
+        }
+        buffer.write_u64(self.trainer.len() as u64);
+        buffer.write_u8_arr(&self.trainer);
+        buffer.write_bool(self.battery);
+        buffer.write_bool(self.nes2);
+        buffer.write_u32(self.prg_ram_size as u32);
+        buffer.write_u32(self.prg_nvram_size as u32);
+        buffer.write_u32(self.chr_ram_size as u32);
+        buffer.write_u32(self.chr_nvram_size as u32);
+        buffer.write_u8(self.timing as u8);
+        match self.console_type {
+            ConsoleType::Nes => buffer.write_u8_arr(&[0, 0, 0]),
+            ConsoleType::VsSystem { ppu, hardware } => buffer.write_u8_arr(&[1, ppu, hardware]),
+            ConsoleType::Playchoice10 => buffer.write_u8_arr(&[2, 0, 0]),
+            ConsoleType::Extended(kind) => buffer.write_u8_arr(&[3, kind, 0]),
+        }
+        buffer.write_u8(self.misc_roms);
+        buffer.write_u8(self.expansion_device);
+        buffer.write_u8(self.disk_sides.len() as u8);
+        for side in self.disk_sides.iter() {
+            buffer.write_u64(side.len() as u64);
+            buffer.write_u8_arr(side);
+        }
+    }
+
+    pub fn decode(buffer: &mut Buffer) -> ROM {
+        // decode PRG ROM and CHR ROM
+        let mut prg_rom = vec![0; buffer.read_u64() as usize];
+        buffer.read_u8_arr(&mut prg_rom);
+        let mut chr_rom = vec![0; buffer.read_u64() as usize];
+        buffer.read_u8_arr(&mut chr_rom);
+        // decode rest
+        let prg_rom_banks = buffer.read_u16();
+        let chr_rom_banks = buffer.read_u16();
+        let mapper_id = buffer.read_u16();
+        let submapper = buffer.read_u8();
+        let mirroring = match buffer.read_u8() {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::OneScreenLower,
+            3 => Mirroring::OneScreenUpper,
+            4 => Mirroring::FourScreen,
+            _ => panic!("Invalid mirroring mode"),
+        };
+        let mut trainer = vec![0; buffer.read_u64() as usize];
+        buffer.read_u8_arr(&mut trainer);
+        let battery = buffer.read_bool();
+        let nes2 = buffer.read_bool();
+        let prg_ram_size = buffer.read_u32() as usize;
+        let prg_nvram_size = buffer.read_u32() as usize;
+        let chr_ram_size = buffer.read_u32() as usize;
+        let chr_nvram_size = buffer.read_u32() as usize;
+        let timing = Timing::from_bits(buffer.read_u8());
+        let mut console = [0; 3];
+        buffer.read_u8_arr(&mut console);
+        let console_type = match console[0] {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu: console[1],
+                hardware: console[2],
+            },
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(console[1]),
+        };
+        let misc_roms = buffer.read_u8();
+        let expansion_device = buffer.read_u8();
+        let disk_sides = (0..buffer.read_u8())
+            .map(|_| {
+                let mut side = vec![0; buffer.read_u64() as usize];
+                buffer.read_u8_arr(&mut side);
+                side
+            })
+            .collect();
+        let mut rom = ROM {
+            prg_rom,
+            chr_rom,
+            prg_rom_banks,
+            chr_rom_banks,
+            mapper_id,
+            submapper,
+            mirroring,
+            trainer,
+            battery,
+            nes2,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            timing,
+            console_type,
+            misc_roms,
+            expansion_device,
+            disk_sides,
+            game: None,
+        };
+        // the saved values already have the database corrections applied,
+        // only the reference to the entry is restored
+        rom.game = database::lookup(&[rom.prg_rom.as_slice(), &rom.chr_rom].concat());
+        rom
+    }
+}
+
+// NES 2.0 rom size from the LSB byte and MSB nibble of the header
+// MSB nibble 0xF selects exponent-multiplier notation: 2^E * (MM * 2 + 1),
+// sizes that do not fit in 32 bits are an invalid header,
+// the same headers are accepted on wasm (32 bit usize) and on 64 bit targets
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> Result<usize, RomError> {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as u32 * 2 + 1;
+        1u32.checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .map(|size| size as usize)
+            .ok_or_else(|| {
+                RomError::InvalidHeader(format!(
+                    "rom size 2^{} * {} is too large",
+                    exponent, multiplier
+                ))
+            })
+    } else {
+        Ok((((msb as usize) << 8) | lsb as usize) * unit)
+    }
+}
+
+// NES 2.0 ram size from a shift count: 0 means no ram, otherwise 64 << shift bytes
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+// CPU/PPU timing from byte 12 of the NES 2.0 header
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    #[default]
+    Ntsc = 0,
+    Pal = 1,
+    // runs on both NTSC and PAL consoles
+    Multi = 2,
+    Dendy = 3,
+}
+
+impl Timing {
+    pub fn from_bits(bits: u8) -> Timing {
+        match bits & 0b11 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::Multi,
+            _ => Timing::Dendy,
+        }
+    }
+}
+
+// console type from flags 7 (and byte 13 of the NES 2.0 header)
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleType {
+    #[default]
+    Nes,
+    // Vs. Unisystem / Vs. Dualsystem with PPU type and hardware type
+    VsSystem {
+        ppu: u8,
+        hardware: u8,
+    },
+    Playchoice10,
+    // extended console type (Famiclone with decimal mode, VT0x, ...)
+    Extended(u8),
+}
+
+// errors reported while parsing a rom file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RomError {
+    // file does not start with "NES<EOF>" or "UNIF"
+    BadMagic,
+    // header contains values that can not be loaded
+    InvalidHeader(String),
+    // file ends before all PRG ROM banks declared in the header (sizes in bytes)
+    TruncatedPrgRom { expected: usize, found: usize },
+    // file ends before all CHR ROM banks declared in the header (sizes in bytes)
+    TruncatedChrRom { expected: usize, found: usize },
+    // mapper id from the header has no implementation
+    UnsupportedMapper(u16),
+    // UNIF board name that does not map to any mapper
+    UnsupportedBoard(String),
+    // Famicom Disk System images need the FDS BIOS (see ROM::new_fds)
+    FdsBiosRequired,
+    // rom has a trainer but the mapper has no PRG RAM at 0x7000 to load it into
+    UnsupportedTrainer(u16),
+}
+
+impl std::fmt::Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RomError::BadMagic => write!(f, "Invalid file signature, not an iNES or UNIF rom"),
+            RomError::InvalidHeader(reason) => write!(f, "Invalid rom header: {}", reason),
+            RomError::TruncatedPrgRom { expected, found } => write!(
+                f,
+                "PRG ROM is truncated: expected {} bytes, found {}",
+                expected, found
+            ),
+            RomError::TruncatedChrRom { expected, found } => write!(
+                f,
+                "CHR ROM is truncated: expected {} bytes, found {}",
+                expected, found
+            ),
+            RomError::UnsupportedMapper(id) => write!(f, "Mapper not implemented: {}", id),
+            RomError::UnsupportedBoard(name) => write!(f, "UNIF board not supported: {}", name),
+            RomError::FdsBiosRequired => {
+                write!(
+                    f,
+                    "Famicom Disk System image, an FDS BIOS is needed to load it"
+                )
+            }
+            RomError::UnsupportedTrainer(id) => {
+                write!(f, "Mapper {} has no PRG RAM at 0x7000 for the trainer", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+#[derive(Default, Clone, Debug)]
+pub enum Mirroring {
+    #[default]
+    Horizontal,
+    Vertical,
+    OneScreenLower,
+    OneScreenUpper,
+    FourScreen,
+}
+
+impl Mirroring {
+    pub fn get_address(&self, addr: u16) -> u16 {
+        let addr = addr & 0x2FFF;
+        match self {
+            Mirroring::Horizontal => match addr {
+                0x2000..=0x23FF => addr - 0x2000,
+                0x2400..=0x27FF => addr - 0x2400,
+                0x2800..=0x2BFF => addr - 0x2800 + 0x400,
+                0x2C00..=0x2FFF => addr - 0x2C00 + 0x400,
+                _ => unreachable!("Invalid address for horizontal mirroring: {:#X}", addr),
+            },
+
+            Mirroring::Vertical => match addr {
+                0x2000..=0x23FF => addr - 0x2000,
+                0x2400..=0x27FF => addr - 0x2400 + 0x400,
+                0x2800..=0x2BFF => addr - 0x2800,
+                0x2C00..=0x2FFF => addr - 0x2C00 + 0x400,
+                _ => unreachable!("Invalid address for vertical mirroring: {:#X}", addr),
+            },
+
+            Mirroring::OneScreenLower => match addr {
+                0x2000..=0x23FF => addr - 0x2000,
+                0x2400..=0x27FF => addr - 0x2400,
+                0x2800..=0x2BFF => addr - 0x2800,
+                0x2C00..=0x2FFF => addr - 0x2C00,
+                _ => unreachable!(
+                    "Invalid address for one screen lower mirroring: {:#X}",
+                    addr
+                ),
+            },
+
+            Mirroring::OneScreenUpper => match addr {
+                0x2000..=0x23FF => addr - 0x2000 + 0x400,
+                0x2400..=0x27FF => addr - 0x2400 + 0x400,
+                0x2800..=0x2BFF => addr - 0x2800 + 0x400,
+                0x2C00..=0x2FFF => addr - 0x2C00 + 0x400,
+                _ => unreachable!(
+                    "Invalid address for one screen upper mirroring: {:#X}",
+                    addr
+                ),
+            },
+
+            Mirroring::FourScreen => addr - 0x2000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // header bytes 4-15, followed by the PRG and CHR ROM sizes in bytes
+    fn ines(header: [u8; 12], prg_rom_size: usize, chr_rom_size: usize) -> Vec<u8> {
+        let mut file = b"NES\x1A".to_vec();
+        file.extend_from_slice(&header);
+        file.extend((0..prg_rom_size).map(|i| i as u8));
+        file.extend((0..chr_rom_size).map(|i| !i as u8));
+        file
+    }
+
+    #[test]
+    fn rom_sizes() {
+        assert_eq!(nes2_rom_size(0x02, 0x0, 0x4000), Ok(0x8000));
+        assert_eq!(nes2_rom_size(0x01, 0x1, 0x2000), Ok(0x101 * 0x2000));
+        // exponent-multiplier notation: 2^E * (MM * 2 + 1)
+        assert_eq!(nes2_rom_size(0, 0xF, 0x4000), Ok(1));
+        assert_eq!(nes2_rom_size(10 << 2 | 1, 0xF, 0x4000), Ok(3 << 10));
+        assert_eq!(nes2_rom_size(13 << 2 | 3, 0xF, 0x4000), Ok(7 << 13));
+        assert_eq!(nes2_rom_size(31 << 2, 0xF, 0x4000), Ok(1 << 31));
+        // too large for a 32 bit usize
+        for lsb in [31 << 2 | 1, 32 << 2, 63 << 2 | 3] {
+            assert!(matches!(
+                nes2_rom_size(lsb, 0xF, 0x4000),
+                Err(RomError::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn nes2_header() {
+        // 24KB of PRG ROM in exponent-multiplier notation, 8KB CHR ROM, mapper 0x101 submapper 2
+        let header = [
+            13 << 2 | 1,
+            1,
+            0x13,
+            0x08,
+            0x21,
+            0x0F,
+            0x70,
+            0x07,
+            0x01,
+            0,
+            0,
+            0x01,
+        ];
+        let rom = ROM::parse_ines(&ines(header, 0x6000, 0x2000)).unwrap();
+        assert!(rom.nes2);
+        assert_eq!(rom.mapper_id, 0x101);
+        assert_eq!(rom.submapper, 2);
+        assert_eq!(rom.prg_rom.len(), 0x6000);
+        assert_eq!(rom.prg_rom_banks, 2);
+        assert_eq!(rom.chr_rom_banks, 1);
+        assert_eq!(rom.chr_rom[0], 0xFF);
+        assert!(matches!(rom.mirroring, Mirroring::Vertical));
+        assert!(rom.battery);
+        assert_eq!(rom.timing, Timing::Pal);
+        assert_eq!(rom.expansion_device, 1);
+    }
+
+    #[test]
+    fn nes2_ram_sizes() {
+        // shift counts: 64 << shift bytes, 0 is no ram
+        let header = [1, 0, 0, 0x08, 0, 0, 0x97, 0x70, 0, 0, 0, 0];
+        let rom = ROM::parse_ines(&ines(header, 0x4000, 0)).unwrap();
+        assert_eq!(rom.prg_ram_size, 64 << 7);
+        assert_eq!(rom.prg_nvram_size, 64 << 9);
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.chr_nvram_size, 64 << 7);
+
+        // boards without CHR ROM and a CHR RAM size get 8KB
+        let header = [1, 0, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0];
+        let rom = ROM::parse_ines(&ines(header, 0x4000, 0)).unwrap();
+        assert_eq!(rom.prg_ram_len(), 0);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+    }
+
+    #[test]
+    fn ines_header() {
+        // mapper 0x42, 2 PRG RAM banks, PAL
+        let header = [2, 1, 0x23, 0x40, 2, 1, 0, 0, 0, 0, 0, 0];
+        let rom = ROM::parse_ines(&ines(header, 0x8000, 0x2000)).unwrap();
+        assert!(!rom.nes2);
+        assert_eq!(rom.mapper_id, 0x42);
+        assert_eq!(rom.prg_ram_size, 0x4000);
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.timing, Timing::Pal);
+        assert!(matches!(rom.mirroring, Mirroring::Vertical));
+    }
+
+    #[test]
+    fn archaic_ines_header() {
+        // "DiskDude!" in bytes 7-15, the mapper high nibble and bytes 8-9 are ignored
+        let mut header = [1, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header[3..].copy_from_slice(b"DiskDude!");
+        let rom = ROM::parse_ines(&ines(header, 0x4000, 0)).unwrap();
+        assert!(!rom.nes2);
+        assert_eq!(rom.mapper_id, 1);
+        assert_eq!(rom.prg_ram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+        assert_eq!(rom.timing, Timing::Ntsc);
+        assert!(matches!(rom.console_type, ConsoleType::Nes));
+    }
+
+    #[test]
+    fn default_chr_ram() {
+        // the default cartridge has neither CHR ROM nor a CHR RAM size
+        assert_eq!(ROM::default().chr_ram_len(), 0x2000);
+        let mut cartridge = crate::mappers::Mapper0::default();
+        cartridge.write(0x1FFF, 0x42);
+        assert_eq!(cartridge.peek(0x1FFF), 0x42);
+
+        let rom = ROM {
+            chr_rom_banks: 1,
+            ..Default::default()
+        };
+        assert_eq!(rom.chr_ram_len(), 0);
+    }
+
+    #[test]
+    fn truncated_rom() {
+        let header = [2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            ROM::parse_ines(&ines(header, 0x4000, 0)).unwrap_err(),
+            RomError::TruncatedPrgRom {
+                expected: 0x8000,
+                found: 0x4000
+            }
+        );
+        assert_eq!(
+            ROM::parse_ines(&ines(header, 0x8000, 0x1000)).unwrap_err(),
+            RomError::TruncatedChrRom {
+                expected: 0x2000,
+                found: 0x1000
+            }
+        );
+
+        // a trainer that is not in the file
+        let header = [1, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            ROM::parse_ines(&ines(header, 0x4000, 0)),
+            Err(RomError::TruncatedPrgRom { .. })
+        ));
+
+        // the largest sizes the header can give
+        let header = [0xFF, 0xFF, 0, 0x08, 0, 0xEE, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            ROM::parse_ines(&ines(header, 0x4000, 0)),
+            Err(RomError::TruncatedPrgRom { .. })
+        ));
+        let header = [0xFF, 0xFF, 0, 0x08, 0, 0xFF, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            ROM::parse_ines(&ines(header, 0x4000, 0)),
+            Err(RomError::InvalidHeader(_))
+        ));
+
+        assert!(matches!(
+            ROM::parse_ines(&ines(header, 0, 0)[..15]),
+            Err(RomError::InvalidHeader(_))
+        ));
+        let mut bad_magic = ines(header, 0, 0);
+        bad_magic[3] = 0;
+        assert_eq!(ROM::parse_ines(&bad_magic).unwrap_err(), RomError::BadMagic);
+    }
+}
@@ -0,0 +1,702 @@
+// soft patching: applies IPS, UPS and BPS patches to the bytes of a rom file
+// before it is parsed, the original file is never modified
+use crate::hash::crc32;
+use std::fmt;
+
+// largest target a UPS or BPS patch may ask for, larger sizes are treated as corrupt
+// so a damaged size can not make the output buffer huge
+const MAX_TARGET_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    // detect the format from the magic bytes at the start of the patch
+    pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+        if patch.starts_with(b"PATCH") {
+            Some(PatchFormat::Ips)
+        } else if patch.starts_with(b"UPS1") {
+            Some(PatchFormat::Ups)
+        } else if patch.starts_with(b"BPS1") {
+            Some(PatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+}
+
+// errors reported while applying a patch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    // patch does not start with the magic bytes of IPS, UPS or BPS
+    UnknownFormat,
+    // patch ends in the middle of a record or its data points outside of the rom
+    Corrupt(PatchFormat, String),
+    // the rom to patch does not have the size the patch was made for
+    SourceSize { expected: usize, found: usize },
+    // the rom to patch is not the one the patch was made for
+    SourceChecksum { expected: u32, found: u32 },
+    // the patched rom does not match the checksum stored in the patch
+    TargetChecksum { expected: u32, found: u32 },
+    // the patch file itself is damaged
+    PatchChecksum { expected: u32, found: u32 },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => {
+                write!(f, "unknown patch format (expected IPS, UPS or BPS)")
+            }
+            PatchError::Corrupt(format, reason) => {
+                write!(f, "corrupt {:?} patch: {}", format, reason)
+            }
+            PatchError::SourceSize { expected, found } => write!(
+                f,
+                "patch is for a different rom: expected {} bytes, found {} bytes",
+                expected, found
+            ),
+            PatchError::SourceChecksum { expected, found } => write!(
+                f,
+                "patch is for a different rom: expected CRC32 {:08X}, found {:08X}",
+                expected, found
+            ),
+            PatchError::TargetChecksum { expected, found } => write!(
+                f,
+                "patched rom has a wrong checksum: expected CRC32 {:08X}, found {:08X}",
+                expected, found
+            ),
+            PatchError::PatchChecksum { expected, found } => write!(
+                f,
+                "patch file is damaged: expected CRC32 {:08X}, found {:08X}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// apply a patch in any of the supported formats
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    match PatchFormat::detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(rom, patch),
+        Some(PatchFormat::Ups) => apply_ups(rom, patch),
+        Some(PatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(PatchError::UnknownFormat),
+    }
+}
+
+// reads the patch front to back, failing with a Corrupt error at the end of the data
+struct Reader<'a> {
+    data: &'a [u8],
+    index: usize,
+    format: PatchFormat,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], index: usize, format: PatchFormat) -> Self {
+        Reader {
+            data,
+            index,
+            format,
+        }
+    }
+
+    fn error(&self, reason: &str) -> PatchError {
+        PatchError::Corrupt(self.format, format!("{} at offset {}", reason, self.index))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self
+            .data
+            .get(self.index..)
+            .and_then(|data| data.get(..len))
+            .ok_or_else(|| self.error("unexpected end of patch"))?;
+        self.index += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    // big endian number of len bytes (IPS)
+    fn be(&mut self, len: usize) -> Result<usize, PatchError> {
+        let bytes = self.bytes(len)?;
+        Ok(bytes.iter().fold(0, |n, &b| n << 8 | b as usize))
+    }
+
+    // variable length number (UPS and BPS)
+    // 7 bits per byte, least significant first, the last byte has bit 7 set
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut data: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let x = self.u8()?;
+            data = ((x & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|n| data.checked_add(n))
+                .ok_or_else(|| self.error("number too large"))?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift
+                .checked_mul(128)
+                .ok_or_else(|| self.error("number too large"))?;
+            data = data
+                .checked_add(shift)
+                .ok_or_else(|| self.error("number too large"))?;
+        }
+    }
+
+    // target size of a UPS or BPS patch
+    fn target_size(&mut self) -> Result<usize, PatchError> {
+        let size = self.varint()?;
+        if size > MAX_TARGET_SIZE {
+            return Err(self.error("target size too large"));
+        }
+        Ok(size)
+    }
+}
+
+// checksums at the end of UPS and BPS patches: source, target, patch (little endian)
+struct Footer {
+    source_crc: u32,
+    target_crc: u32,
+}
+
+fn read_footer(patch: &[u8], format: PatchFormat) -> Result<Footer, PatchError> {
+    if patch.len() < 4 + 12 {
+        return Err(PatchError::Corrupt(
+            format,
+            "patch is too short".to_string(),
+        ));
+    }
+    let footer = &patch[patch.len() - 12..];
+    let word = |i: usize| u32::from_le_bytes(footer[i..i + 4].try_into().unwrap());
+    let patch_crc = word(8);
+    let found = crc32(&patch[..patch.len() - 4]);
+    if patch_crc != found {
+        return Err(PatchError::PatchChecksum {
+            expected: patch_crc,
+            found,
+        });
+    }
+    Ok(Footer {
+        source_crc: word(0),
+        target_crc: word(4),
+    })
+}
+
+fn check_source(source: &[u8], size: usize, footer: &Footer) -> Result<(), PatchError> {
+    if source.len() != size {
+        return Err(PatchError::SourceSize {
+            expected: size,
+            found: source.len(),
+        });
+    }
+    let found = crc32(source);
+    if found != footer.source_crc {
+        return Err(PatchError::SourceChecksum {
+            expected: footer.source_crc,
+            found,
+        });
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], footer: &Footer) -> Result<(), PatchError> {
+    let found = crc32(target);
+    if found != footer.target_crc {
+        return Err(PatchError::TargetChecksum {
+            expected: footer.target_crc,
+            found,
+        });
+    }
+    Ok(())
+}
+
+// IPS /////////////////
+// "PATCH", then records until "EOF":
+//   3 byte offset, 2 byte size, size bytes of data
+//   or 3 byte offset, size 0, 2 byte run length, 1 byte value (RLE)
+// an optional 3 byte size after "EOF" truncates the output
+// IPS has no checksums
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(b"PATCH") {
+        return Err(PatchError::Corrupt(
+            PatchFormat::Ips,
+            "missing PATCH header".to_string(),
+        ));
+    }
+    let mut output = rom.to_vec();
+    let mut reader = Reader::new(patch, 5, PatchFormat::Ips);
+    loop {
+        let offset = reader.be(3)?;
+        if offset == 0x454F46 {
+            // "EOF"
+            break;
+        }
+        let size = reader.be(2)?;
+        let (len, data) = if size == 0 {
+            let len = reader.be(2)?;
+            let value = reader.u8()?;
+            (len, vec![value; len])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+        if output.len() < offset + len {
+            output.resize(offset + len, 0);
+        }
+        output[offset..offset + len].copy_from_slice(&data);
+    }
+    if let Ok(truncate) = reader.be(3) {
+        output.truncate(truncate);
+    }
+    Ok(output)
+}
+
+// IPS patch turning original into modified (both at most 16MB)
+// a record can not start at 0x454F46 ("EOF"), such a record starts one byte earlier
+pub fn create_ips(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    let mut index = 0;
+    while index < modified.len() {
+        if original.get(index) == Some(&modified[index]) {
+            index += 1;
+            continue;
+        }
+        let mut start = index;
+        if start == 0x454F46 {
+            start -= 1;
+        }
+        let mut end = index;
+        while end < modified.len()
+            && end - start < 0xFFFF
+            && original.get(end) != Some(&modified[end])
+        {
+            end += 1;
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..end]);
+        index = end;
+    }
+    patch.extend_from_slice(b"EOF");
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+    patch
+}
+
+// UPS /////////////////
+// "UPS1", source size, target size, then until the footer:
+//   number of bytes to skip, bytes to XOR with the source terminated by 0
+// footer: CRC32 of source, target and patch
+pub fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let format = PatchFormat::Ups;
+    let footer = read_footer(patch, format)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader::new(&patch[..end], 4, format);
+    let source_size = reader.varint()?;
+    let target_size = reader.target_size()?;
+    check_source(rom, source_size, &footer)?;
+
+    let mut output = rom.to_vec();
+    output.resize(source_size.max(target_size), 0);
+    let mut pos = 0;
+    while reader.index < end {
+        pos = reader
+            .varint()?
+            .checked_add(pos)
+            .ok_or_else(|| reader.error("number too large"))?;
+        loop {
+            let xor = reader.u8()?;
+            if xor == 0 {
+                pos = pos
+                    .checked_add(1)
+                    .ok_or_else(|| reader.error("number too large"))?;
+                break;
+            }
+            if pos >= output.len() {
+                return Err(reader.error("write past the end of the target"));
+            }
+            output[pos] ^= xor;
+            pos += 1;
+        }
+    }
+    output.truncate(target_size);
+
+    check_target(&output, &footer)?;
+    Ok(output)
+}
+
+// BPS /////////////////
+// "BPS1", source size, target size, metadata size, metadata, then until the footer:
+//   action = (length - 1) << 2 | command
+//   0: SourceRead, copy from the source at the current output position
+//   1: TargetRead, copy from the patch
+//   2: SourceCopy, copy from a relative position in the source
+//   3: TargetCopy, copy from a relative position in the already written output
+// footer: CRC32 of source, target and patch
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let format = PatchFormat::Bps;
+    let footer = read_footer(patch, format)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader::new(&patch[..end], 4, format);
+    let source_size = reader.varint()?;
+    let target_size = reader.target_size()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    check_source(rom, source_size, &footer)?;
+
+    let mut output: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while reader.index < end {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        if output.len() + len > target_size {
+            return Err(reader.error("write past the end of the target"));
+        }
+        match action & 0b11 {
+            0 => {
+                let start = output.len();
+                let data = rom
+                    .get(start..start + len)
+                    .ok_or_else(|| reader.error("read past the end of the source"))?;
+                output.extend_from_slice(data);
+            }
+            1 => output.extend_from_slice(reader.bytes(len)?),
+            2 => {
+                source_offset = relative_offset(&mut reader, source_offset)?;
+                let data = source_offset
+                    .checked_add(len)
+                    .and_then(|end| rom.get(source_offset..end))
+                    .ok_or_else(|| reader.error("read past the end of the source"))?;
+                output.extend_from_slice(data);
+                source_offset += len;
+            }
+            _ => {
+                target_offset = relative_offset(&mut reader, target_offset)?;
+                // the copy can overlap the bytes it writes, so copy byte by byte
+                for _ in 0..len {
+                    let byte = *output
+                        .get(target_offset)
+                        .ok_or_else(|| reader.error("read past the end of the target"))?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if output.len() != target_size {
+        return Err(reader.error("patch ends before the target is complete"));
+    }
+
+    check_target(&output, &footer)?;
+    Ok(output)
+}
+
+// signed offset, bit 0 is the sign and the rest is the magnitude
+fn relative_offset(reader: &mut Reader, offset: usize) -> Result<usize, PatchError> {
+    let data = reader.varint()?;
+    let delta = data >> 1;
+    let result = if data & 1 != 0 {
+        offset.checked_sub(delta)
+    } else {
+        offset.checked_add(delta)
+    };
+    result.ok_or_else(|| reader.error("relative offset out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // UPS and BPS number, the inverse of Reader::varint
+    fn varint(mut n: usize, patch: &mut Vec<u8>) {
+        loop {
+            let x = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                patch.push(0x80 | x);
+                return;
+            }
+            patch.push(x);
+            n -= 1;
+        }
+    }
+
+    // appends the footer: CRC32 of source, target and patch
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    fn create_ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"UPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        let xor = |i: usize| source.get(i).unwrap_or(&0) ^ target.get(i).unwrap_or(&0);
+        let len = source.len().max(target.len());
+        let (mut index, mut pos) = (0, 0);
+        while index < len {
+            if xor(index) == 0 {
+                index += 1;
+                continue;
+            }
+            varint(index - pos, &mut patch);
+            while index < len && xor(index) != 0 {
+                patch.push(xor(index));
+                index += 1;
+            }
+            patch.push(0);
+            index += 1;
+            pos = index;
+        }
+        with_footer(patch, source, target)
+    }
+
+    // SourceRead of the bytes both start with, TargetRead of the rest
+    fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        let same = source
+            .iter()
+            .zip(target)
+            .take_while(|(s, t)| s == t)
+            .count();
+        if same > 0 {
+            varint((same - 1) << 2, &mut patch);
+        }
+        if same < target.len() {
+            varint((target.len() - same - 1) << 2 | 1, &mut patch);
+            patch.extend_from_slice(&target[same..]);
+        }
+        with_footer(patch, source, target)
+    }
+
+    // replaces the target CRC32 of a UPS or BPS patch, keeping the patch CRC32 valid
+    fn with_target_crc(patch: &[u8], target_crc: u32) -> Vec<u8> {
+        let mut patch = patch[..patch.len() - 4].to_vec();
+        let len = patch.len();
+        patch[len - 4..].copy_from_slice(&target_crc.to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    fn source() -> Vec<u8> {
+        (0..64).map(|i| i * 3).collect()
+    }
+
+    fn target() -> Vec<u8> {
+        let mut target = source();
+        target[10] = 0xAA;
+        target[11] = 0xBB;
+        target[40] = 0;
+        target.extend_from_slice(b"added");
+        target
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(PatchFormat::detect(b"PATCHEOF"), Some(PatchFormat::Ips));
+        assert_eq!(PatchFormat::detect(b"UPS1"), Some(PatchFormat::Ups));
+        assert_eq!(PatchFormat::detect(b"BPS1"), Some(PatchFormat::Bps));
+        assert_eq!(apply(&source(), b"NOPE"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn ips_round_trip() {
+        let patch = create_ips(&source(), &target());
+        assert_eq!(apply(&source(), &patch).unwrap(), target());
+    }
+
+    #[test]
+    fn ips_record_at_eof_offset() {
+        let original = vec![0; 0x454F50];
+        let mut modified = original.clone();
+        modified[0x10] = 1;
+        modified[0x454F46] = 2;
+        modified[0x454F47] = 3;
+        let patch = create_ips(&original, &modified);
+        assert_eq!(apply_ips(&original, &patch).unwrap(), modified);
+    }
+
+    #[test]
+    fn ips_truncation() {
+        let original = target();
+        let mut modified = source();
+        modified[5] = 0xFF;
+        let patch = create_ips(&original, &modified);
+        assert_eq!(apply_ips(&original, &patch).unwrap(), modified);
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x03\xAAEOF";
+        let output = apply_ips(&[0; 8], patch).unwrap();
+        assert_eq!(output, [0, 0, 0xAA, 0xAA, 0xAA, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ips_truncated_patch() {
+        let patch = create_ips(&source(), &target());
+        for len in 0..patch.len() {
+            assert!(matches!(
+                apply_ips(&source(), &patch[..len]),
+                Err(PatchError::Corrupt(PatchFormat::Ips, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn ips_without_header() {
+        for patch in [&b""[..], b"PAT", b"UPS1\x80\x80", b"NOT A PATCH EOF"] {
+            assert!(matches!(
+                apply_ips(&source(), patch),
+                Err(PatchError::Corrupt(PatchFormat::Ips, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn ups_round_trip() {
+        let patch = create_ups(&source(), &target());
+        assert_eq!(apply(&source(), &patch).unwrap(), target());
+        // UPS patches work both ways
+        let patch = create_ups(&target(), &source());
+        assert_eq!(apply(&target(), &patch).unwrap(), source());
+    }
+
+    #[test]
+    fn ups_checksums() {
+        let patch = create_ups(&source(), &target());
+
+        let mut other = source();
+        other[0] ^= 1;
+        assert!(matches!(
+            apply_ups(&other, &patch),
+            Err(PatchError::SourceChecksum { .. })
+        ));
+        assert!(matches!(
+            apply_ups(&source()[1..], &patch),
+            Err(PatchError::SourceSize { .. })
+        ));
+
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert!(matches!(
+            apply_ups(&source(), &damaged),
+            Err(PatchError::PatchChecksum { .. })
+        ));
+
+        let wrong_target = with_target_crc(&patch, crc32(&target()) ^ 1);
+        assert!(matches!(
+            apply_ups(&source(), &wrong_target),
+            Err(PatchError::TargetChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let patch = create_bps(&source(), &target());
+        assert_eq!(apply(&source(), &patch).unwrap(), target());
+    }
+
+    #[test]
+    fn bps_checksums() {
+        let patch = create_bps(&source(), &target());
+
+        let mut other = source();
+        other[0] ^= 1;
+        assert!(matches!(
+            apply_bps(&other, &patch),
+            Err(PatchError::SourceChecksum { .. })
+        ));
+
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert!(matches!(
+            apply_bps(&source(), &damaged),
+            Err(PatchError::PatchChecksum { .. })
+        ));
+
+        let wrong_target = with_target_crc(&patch, crc32(&target()) ^ 1);
+        assert!(matches!(
+            apply_bps(&source(), &wrong_target),
+            Err(PatchError::TargetChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn number_too_large() {
+        for magic in [b"UPS1", b"BPS1"] {
+            let mut patch = magic.to_vec();
+            patch.extend_from_slice(&[0x7F; 16]);
+            patch.push(0x80);
+            let patch = with_footer(patch, &source(), &target());
+            assert!(matches!(
+                apply(&source(), &patch),
+                Err(PatchError::Corrupt(_, reason)) if reason.starts_with("number too large")
+            ));
+        }
+    }
+
+    #[test]
+    fn ups_skip_past_usize() {
+        let mut patch = b"UPS1".to_vec();
+        varint(source().len(), &mut patch);
+        varint(target().len(), &mut patch);
+        varint(usize::MAX, &mut patch);
+        patch.push(0);
+        let patch = with_footer(patch, &source(), &target());
+        assert!(matches!(
+            apply(&source(), &patch),
+            Err(PatchError::Corrupt(_, reason)) if reason.starts_with("number too large")
+        ));
+    }
+
+    #[test]
+    fn target_too_large() {
+        for magic in [b"UPS1", b"BPS1"] {
+            let mut patch = magic.to_vec();
+            varint(source().len(), &mut patch);
+            varint(MAX_TARGET_SIZE + 1, &mut patch);
+            varint(0, &mut patch);
+            let patch = with_footer(patch, &source(), &target());
+            assert!(matches!(
+                apply(&source(), &patch),
+                Err(PatchError::Corrupt(_, reason)) if reason.starts_with("target size too large")
+            ));
+        }
+    }
+
+    #[test]
+    fn bps_source_copy_out_of_range() {
+        let mut patch = b"BPS1".to_vec();
+        varint(source().len(), &mut patch);
+        varint(1, &mut patch);
+        varint(0, &mut patch);
+        // SourceCopy of 1 byte, relative offset usize::MAX / 2 forward
+        varint(2, &mut patch);
+        varint(usize::MAX - 1, &mut patch);
+        let patch = with_footer(patch, &source(), &[0]);
+        assert!(matches!(
+            apply_bps(&source(), &patch),
+            Err(PatchError::Corrupt(PatchFormat::Bps, _))
+        ));
+    }
+}
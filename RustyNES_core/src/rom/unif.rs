@@ -0,0 +1,256 @@
+// UNIF (.unf) rom files
+// instead of a mapper number the board is given by name,
+// the rom data and metadata are stored in chunks
+use super::{Mirroring, RomError, Timing, ROM};
+
+/*
+file format:
+header (32 bytes): "UNIF", revision (u32), 24 bytes of zeros
+chunks: id (4 bytes), length (u32), data
+
+chunks used:
+MAPR:      board name, zero terminated
+PRG0-PRGF: PRG ROM, concatenated in order
+CHR0-CHRF: CHR ROM, concatenated in order
+MIRR:      0 = horizontal, 1 = vertical, 2 = one screen lower, 3 = one screen upper,
+           4 = four screen, 5 = controlled by the mapper
+BATR:      battery backed PRG RAM
+TVCI:      0 = NTSC, 1 = PAL, 2 = both
+*/
+pub fn is_unif(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"UNIF")
+}
+
+pub fn parse(bytes: &[u8]) -> Result<ROM, RomError> {
+    if bytes.len() < 32 {
+        return Err(RomError::InvalidHeader(format!(
+            "file is {} bytes, UNIF header needs 32",
+            bytes.len()
+        )));
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [&[u8]; 16] = [&[]; 16];
+    let mut chr_chunks: [&[u8]; 16] = [&[]; 16];
+    let mut mirroring = None;
+    let mut battery = false;
+    let mut timing = Timing::Ntsc;
+
+    let mut index = 32;
+    while index + 8 <= bytes.len() {
+        let id = &bytes[index..index + 4];
+        let len = u32::from_le_bytes(bytes[index + 4..index + 8].try_into().unwrap()) as usize;
+        // len comes from the file, the end is checked so it can not overflow
+        let end = (index + 8)
+            .checked_add(len)
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| {
+                RomError::InvalidHeader(format!(
+                    "UNIF chunk {} is truncated",
+                    String::from_utf8_lossy(id)
+                ))
+            })?;
+        let data = &bytes[index + 8..end];
+        index = end;
+
+        match id {
+            b"MAPR" => {
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                board = Some(String::from_utf8_lossy(&data[..end]).into_owned());
+            }
+            [b'P', b'R', b'G', n] | [b'C', b'H', b'R', n] if n.is_ascii_hexdigit() => {
+                let n = (*n as char).to_digit(16).unwrap() as usize;
+                if id[0] == b'P' {
+                    prg_chunks[n] = data;
+                } else {
+                    chr_chunks[n] = data;
+                }
+            }
+            b"MIRR" if !data.is_empty() => {
+                mirroring = match data[0] {
+                    0 => Some(Mirroring::Horizontal),
+                    1 => Some(Mirroring::Vertical),
+                    2 => Some(Mirroring::OneScreenLower),
+                    3 => Some(Mirroring::OneScreenUpper),
+                    4 => Some(Mirroring::FourScreen),
+                    _ => None,
+                };
+            }
+            b"BATR" => battery = data.first().is_none_or(|&b| b != 0),
+            b"TVCI" if !data.is_empty() => {
+                timing = match data[0] {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    _ => Timing::Multi,
+                };
+            }
+            // other chunks (NAME, READ, DINF, CTRL, PCK0, CCK0 ...) are not needed for emulation
+            _ => {}
+        }
+    }
+
+    let board = board.ok_or_else(|| RomError::InvalidHeader("no MAPR chunk".to_string()))?;
+    let (mapper_id, board_mirroring) =
+        board_mapper(&board).ok_or_else(|| RomError::UnsupportedBoard(board.clone()))?;
+
+    let prg_rom = prg_chunks.concat();
+    let chr_rom = chr_chunks.concat();
+    if prg_rom.is_empty() {
+        return Err(RomError::InvalidHeader("no PRG ROM chunks".to_string()));
+    }
+
+    println!("unif board {}", &board);
+
+    // UNIF has no ram sizes, assume 8KB PRG RAM and 8KB CHR RAM if there is no CHR ROM
+    Ok(ROM {
+        prg_rom_banks: prg_rom.len().div_ceil(0x4000) as u16,
+        chr_rom_banks: chr_rom.len().div_ceil(0x2000) as u16,
+        chr_ram_size: if chr_rom.is_empty() { 0x2000 } else { 0 },
+        prg_ram_size: 0x2000,
+        prg_rom,
+        chr_rom,
+        mapper_id,
+        mirroring: board_mirroring.or(mirroring).unwrap_or_default(),
+        battery,
+        timing,
+        ..Default::default()
+    })
+}
+
+// mapper id and fixed mirroring (if the board forces one) for a board name
+// the "NES-", "HVC-", "UNL-", "BTL-" and "BMC-" prefixes are ignored
+fn board_mapper(name: &str) -> Option<(u16, Option<Mirroring>)> {
+    let name = name.to_ascii_uppercase();
+    let board = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(&name);
+
+    let mapper = match board {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, None),
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM"
+        | "SLROM" | "SL1ROM" | "SL2ROM" | "SL3ROM" | "SLRROM" | "SNROM" | "SOROM" | "SUROM"
+        | "SXROM" => (1, None),
+        "UNROM" | "UOROM" => (2, None),
+        "CNROM" => (3, None),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TNROM" | "TSROM" | "TVROM"
+        | "B4" => (4, None),
+        // four screen VRAM on the board
+        "TR1ROM" => (4, Some(Mirroring::FourScreen)),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, None),
+        "ANROM" | "AN1ROM" | "AMROM" | "AOROM" => (7, None),
+        "PNROM" | "PEEOROM" => (9, None),
+        "FJROM" | "FKROM" => (10, None),
+        "BNROM" => (34, None),
+        "GNROM" | "MHROM" => (66, None),
+        _ => return None,
+    };
+    Some(mapper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(file: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+        file.extend_from_slice(id);
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(data);
+    }
+
+    fn header() -> Vec<u8> {
+        let mut file = b"UNIF".to_vec();
+        file.extend_from_slice(&7u32.to_le_bytes());
+        file.resize(32, 0);
+        file
+    }
+
+    // NROM-256 with 2 PRG chunks, a CHR chunk and vertical mirroring
+    fn nrom() -> Vec<u8> {
+        let mut file = header();
+        chunk(&mut file, b"MAPR", b"NES-NROM-256\0");
+        chunk(&mut file, b"PRG1", &[2; 0x4000]);
+        chunk(&mut file, b"PRG0", &[1; 0x4000]);
+        chunk(&mut file, b"CHR0", &[3; 0x2000]);
+        chunk(&mut file, b"MIRR", &[1]);
+        chunk(&mut file, b"NAME", b"test\0");
+        file
+    }
+
+    #[test]
+    fn parse_chunks() {
+        let file = nrom();
+        assert!(is_unif(&file));
+        let rom = parse(&file).unwrap();
+        assert_eq!(rom.mapper_id, 0);
+        assert_eq!(rom.prg_rom_banks, 2);
+        assert_eq!(rom.chr_rom_banks, 1);
+        // PRG chunks are concatenated in the order of their numbers
+        assert_eq!(rom.prg_rom[0], 1);
+        assert_eq!(rom.prg_rom[0x4000], 2);
+        assert!(matches!(rom.mirroring, Mirroring::Vertical));
+        assert_eq!(rom.chr_ram_size, 0);
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let file = nrom();
+        for len in [32 + 8, 32 + 20, file.len() - 1] {
+            assert!(matches!(
+                parse(&file[..len]),
+                Err(RomError::InvalidHeader(_))
+            ));
+        }
+        assert!(matches!(
+            parse(&file[..31]),
+            Err(RomError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_chunk_length() {
+        let mut file = nrom();
+        file.extend_from_slice(b"PRG2");
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(parse(&file), Err(RomError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn missing_chunks() {
+        let mut file = header();
+        chunk(&mut file, b"PRG0", &[0; 0x4000]);
+        assert_eq!(
+            parse(&file).unwrap_err(),
+            RomError::InvalidHeader("no MAPR chunk".to_string())
+        );
+
+        let mut file = header();
+        chunk(&mut file, b"MAPR", b"NES-NROM-128\0");
+        chunk(&mut file, b"CHR0", &[0; 0x2000]);
+        assert_eq!(
+            parse(&file).unwrap_err(),
+            RomError::InvalidHeader("no PRG ROM chunks".to_string())
+        );
+    }
+
+    #[test]
+    fn board_names() {
+        assert_eq!(board_mapper("NES-SNROM").map(|(id, _)| id), Some(1));
+        assert_eq!(board_mapper("hvc-tlrom").map(|(id, _)| id), Some(4));
+        assert_eq!(board_mapper("UNROM").map(|(id, _)| id), Some(2));
+        assert_eq!(board_mapper("BMC-GNROM").map(|(id, _)| id), Some(66));
+        assert!(matches!(
+            board_mapper("NES-TR1ROM"),
+            Some((4, Some(Mirroring::FourScreen)))
+        ));
+        assert!(board_mapper("UNL-UNKNOWN").is_none());
+
+        let mut file = header();
+        chunk(&mut file, b"MAPR", b"UNL-UNKNOWN\0");
+        chunk(&mut file, b"PRG0", &[0; 0x4000]);
+        assert_eq!(
+            parse(&file).unwrap_err(),
+            RomError::UnsupportedBoard("UNL-UNKNOWN".to_string())
+        );
+    }
+}
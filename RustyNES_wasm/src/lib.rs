@@ -1,5 +1,8 @@
+#![allow(clippy::upper_case_acronyms)]
+use rusty_nes_core::archive;
 use rusty_nes_core::buffer::Buffer;
-use rusty_nes_core::cpu::CPU;
+use rusty_nes_core::cpu::{CpuEvent, CPU};
+use rusty_nes_core::Region;
 use rusty_nes_core::SAMPLE_RATE;
 use wasm_bindgen::prelude::*;
 
@@ -10,12 +13,13 @@ pub struct NES {
 
 #[wasm_bindgen]
 impl NES {
-    pub fn new_nes(bytes: Vec<u8>) -> NES {
+    pub fn new_nes(bytes: Vec<u8>) -> Result<NES, JsError> {
         set_panic_hook();
-        let cpu = CPU::new_from_rom_bytes(bytes);
+        let bytes = archive::extract_rom(bytes)?;
+        let cpu = CPU::try_new_from_rom_bytes(bytes)?;
         add(1, 2);
         // throw_js_error();
-        NES { cpu }
+        Ok(NES { cpu })
     }
 
     pub fn new_from_save_bytes(&mut self, bytes: Vec<u8>) -> NES {
@@ -28,6 +32,13 @@ impl NES {
         self.cpu.step_till_next_frame();
     }
 
+    // what the CPU ran into during the last frame, undefined if nothing happened
+    pub fn take_event(&mut self) -> Option<String> {
+        self.cpu.take_event().map(|event| match event {
+            CpuEvent::CpuHalted { addr } => format!("CPU halted by a JAM opcode at {:04X}", addr),
+        })
+    }
+
     pub fn frame_buffer_pointer(&self) -> *const u8 {
         self.cpu.bus.ppu.frame_buffer.as_ptr()
     }
@@ -44,8 +55,71 @@ impl NES {
         SAMPLE_RATE
     }
 
-    pub fn change_rom(&mut self, bytes: Vec<u8>) {
-        self.cpu = CPU::new_from_rom_bytes(bytes);
+    // frames per second of the current region
+    pub fn frame_rate(&self) -> f64 {
+        self.cpu.region().frame_rate()
+    }
+
+    // "auto" (region of the rom), "ntsc", "pal" or "dendy"
+    pub fn set_region(&mut self, region: &str) -> Result<(), JsError> {
+        let region = match region {
+            "auto" => None,
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => return Err(JsError::new(&format!("unknown region \"{}\"", region))),
+        };
+        self.cpu.set_region(region);
+        Ok(())
+    }
+
+    // keeps the current rom running if the new one can not be loaded
+    // bytes can be a .nes file or a zip/gzip archive containing one
+    pub fn change_rom(&mut self, bytes: Vec<u8>) -> Result<(), JsError> {
+        let bytes = archive::extract_rom(bytes)?;
+        self.cpu = CPU::try_new_from_rom_bytes(bytes)?;
+        Ok(())
+    }
+
+    // Famicom Disk System disk (or a zip/gzip archive of one) and the FDS BIOS
+    pub fn change_rom_fds(&mut self, bytes: Vec<u8>, bios: Vec<u8>) -> Result<(), JsError> {
+        let bytes = archive::extract_rom(bytes)?;
+        self.cpu = CPU::try_new_fds(&bytes, bios)?;
+        Ok(())
+    }
+
+    // 0 if the game is not on a disk
+    pub fn disk_side_count(&self) -> usize {
+        self.cpu.disk_side_count()
+    }
+
+    // inserts a disk side, undefined ejects the disk
+    pub fn insert_disk_side(&mut self, side: Option<usize>) {
+        self.cpu.insert_disk_side(side);
+    }
+
+    // data written to the disk as an IPS patch, undefined if nothing was written
+    pub fn disk_diff(&self) -> Option<Vec<u8>> {
+        self.cpu.disk_diff()
+    }
+
+    pub fn load_disk_diff(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        self.cpu.load_disk_diff(bytes)?;
+        Ok(())
+    }
+
+    // title from the game database, undefined if the rom is not in it
+    pub fn title(&self) -> Option<String> {
+        self.cpu.title().map(|title| title.to_string())
+    }
+
+    // battery backed save RAM, undefined if the cartridge has no battery
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        self.cpu.battery_ram().map(|ram| ram.to_vec())
+    }
+
+    pub fn load_battery_ram(&mut self, bytes: &[u8]) {
+        self.cpu.load_battery_ram(bytes);
     }
 
     pub fn get_state(&mut self) -> Vec<u8> {
@@ -31,6 +31,7 @@ let onRomChange!: (romData: Uint8Array) => void;
 
 let nes: NES;
 let wasmMemory: WebAssembly.Memory;
+let currentRom: string;
 
 let statsAdded = true;
 let stats1: Stats;
@@ -38,9 +39,8 @@ let stats2: Stats;
 
 ///// VIDEO
 const setupVideo = () => {
-    ////// for throttling loop to 60 fps
-    let fps = 60;
-    let requiredDelta = Math.floor(1000 / fps);
+    ////// for throttling loop to the frame rate of the region (60 fps on NTSC, 50 fps on PAL)
+    let requiredDelta = Math.floor(1000 / nes.frame_rate());
     let prev = performance.now();
     let delta = 0;
 
@@ -54,8 +54,9 @@ const setupVideo = () => {
         // stats1.begin();
         // stats2.begin();
 
-        ///// Throttle to 60 fps
+        ///// Throttle to the frame rate, the rom (and its region) can change
         videoContext = requestAnimationFrame(loop);
+        requiredDelta = Math.floor(1000 / nes.frame_rate());
         delta = now - prev;
         if (delta < requiredDelta) {
             return;
@@ -215,6 +216,25 @@ const loadState = () => {
     }
 };
 
+///// BATTERY SAVE RAM
+// save RAM of games with a battery is kept in localStorage for each rom
+const storeBatteryRam = () => {
+    const ram = nes.battery_ram();
+    if (ram) {
+        let binary = "";
+        ram.forEach((byte) => (binary += String.fromCharCode(byte)));
+        localStorage.setItem(`sav:${currentRom}`, btoa(binary));
+    }
+};
+
+const restoreBatteryRam = () => {
+    const saved = localStorage.getItem(`sav:${currentRom}`);
+    if (saved) {
+        const binary = atob(saved);
+        nes.load_battery_ram(Uint8Array.from(binary, (c) => c.charCodeAt(0)));
+    }
+};
+
 const fetchRom = async (romPath: string) => {
     const response = await fetch(romPath);
     if (!response.ok) {
@@ -227,7 +247,16 @@ const fetchRom = async (romPath: string) => {
 const changeRom = async (url: string) => {
     await stop();
     const romData = await fetchRom(url);
-    onRomChange(romData);
+    try {
+        storeBatteryRam();
+        onRomChange(romData);
+        currentRom = url;
+        restoreBatteryRam();
+    } catch (error) {
+        // invalid rom, keep running the previous one
+        alert(error);
+        console.error(error);
+    }
     await start();
 };
 
@@ -353,6 +382,7 @@ const setupEventListeners = () => {
     // Pause on blur and resume on focus
     window.onblur = () => stop();
     window.onfocus = () => start();
+    window.onbeforeunload = storeBatteryRam;
 
     const cleanupEventListeners = () => {
         window.removeEventListener("keydown", onPress);
@@ -360,6 +390,7 @@ const setupEventListeners = () => {
         window.onblur = null;
         window.onfocus = null;
         window.onresize = null;
+        window.onbeforeunload = null;
 
         mute.onclick = null;
         playButton.onclick = null;
@@ -419,6 +450,8 @@ const initialize = async (url: string) => {
     // init emulator
     nes = NES.new_nes(romData);
     (window as any).nes = nes;
+    currentRom = url;
+    restoreBatteryRam();
     isInit = true;
 
     // init controls
@@ -439,6 +472,10 @@ const initialize = async (url: string) => {
 
     onFrame = () => {
         nes.step();
+        const event = nes.take_event();
+        if (event) {
+            console.warn(event);
+        }
         imageData.data.set(
             new Uint8ClampedArray(
                 wasmMemory.buffer,